@@ -0,0 +1,394 @@
+use anyhow::{bail, Context, Result};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::nrf_json::encode_json;
+use crate::streaming_protocol::{
+    BuildReceiptChip, EditDecisionChip, RealtimePredictabilitySidecar, VodPredictabilitySidecar,
+};
+use crate::{cid_for_json_value, validate_no_json_numbers, Cid};
+
+/// Detached Ed25519 signature over a chip's content CID.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChipSignature {
+    pub context: String,
+    /// Hex-encoded 32-byte verifying key.
+    pub public_key: String,
+    /// Hex-encoded 64-byte signature.
+    pub signature: String,
+}
+
+/// Protocol chips: strict UNC-1 JSON envelopes with `@type`/`@id`/`@ver`/`@world`.
+///
+/// A chip's CID is `BLAKE3(NRF-1.1(chip without "@id"))`; `@id` is absent until [`Chip::seal`]
+/// sets it to that CID. Signatures cover `SIGNATURE_CONTEXT || 0x00 || cid bytes`; each chip type
+/// has its own context so a signature cannot be replayed on another type.
+pub trait Chip: Serialize {
+    const CHIP_TYPE: &'static str;
+    const SIGNATURE_CONTEXT: &'static str;
+
+    fn chip_id(&self) -> Option<Cid>;
+    fn set_chip_id(&mut self, id: Cid);
+
+    fn to_json(&self) -> Result<Value> {
+        let value = serde_json::to_value(self).context("ChipSerialize")?;
+        validate_no_json_numbers(&value)?;
+        Ok(value)
+    }
+
+    /// Canonical NRF-1.1 bytes of the whole chip, `@id` included.
+    fn to_nrf(&self) -> Result<Vec<u8>> {
+        encode_json(&self.to_json()?)
+    }
+
+    fn cid(&self) -> Result<Cid> {
+        let mut value = self.to_json()?;
+        value
+            .as_object_mut()
+            .context("ChipNotAnObject")?
+            .remove("@id");
+        Ok(cid_for_json_value(&value)?.0)
+    }
+
+    /// Set `@id` to the chip's CID.
+    fn seal(&mut self) -> Result<()> {
+        let cid = self.cid()?;
+        self.set_chip_id(cid);
+        Ok(())
+    }
+
+    fn verify_id(&self) -> Result<()> {
+        let cid = self.cid()?;
+        match self.chip_id() {
+            None => bail!("ChipNotSealed"),
+            Some(id) if id != cid => bail!("ChipIdMismatch({} != {})", id, cid),
+            Some(_) => Ok(()),
+        }
+    }
+
+    fn sign(&self, key: &SigningKey) -> Result<ChipSignature> {
+        let signature = key.sign(&signed_message(Self::SIGNATURE_CONTEXT, &self.cid()?));
+        Ok(ChipSignature {
+            context: Self::SIGNATURE_CONTEXT.to_string(),
+            public_key: hex::encode(key.verifying_key().as_bytes()),
+            signature: hex::encode(signature.to_bytes()),
+        })
+    }
+
+    /// Check `sig` against the chip's current content; returns the signer's key.
+    fn verify_signature(&self, sig: &ChipSignature) -> Result<VerifyingKey> {
+        if sig.context != Self::SIGNATURE_CONTEXT {
+            bail!("ChipSignatureContextMismatch({})", sig.context);
+        }
+        let key: [u8; 32] = hex::decode(&sig.public_key)
+            .ok()
+            .and_then(|b| b.try_into().ok())
+            .context("BadChipPublicKey")?;
+        let key = VerifyingKey::from_bytes(&key).context("BadChipPublicKey")?;
+        let signature: [u8; 64] = hex::decode(&sig.signature)
+            .ok()
+            .and_then(|b| b.try_into().ok())
+            .context("BadChipSignature")?;
+        key.verify_strict(
+            &signed_message(Self::SIGNATURE_CONTEXT, &self.cid()?),
+            &Signature::from_bytes(&signature),
+        )
+        .context("ChipSignatureInvalid")?;
+        Ok(key)
+    }
+}
+
+fn signed_message(context: &str, cid: &Cid) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(context.len() + 1 + Cid::DIGEST_LEN);
+    msg.extend_from_slice(context.as_bytes());
+    msg.push(0);
+    msg.extend_from_slice(cid.digest());
+    msg
+}
+
+impl Chip for RealtimePredictabilitySidecar {
+    const CHIP_TYPE: &'static str = "vcx/sidecar.predictability.realtime";
+    const SIGNATURE_CONTEXT: &'static str = "vcx-sidecar-realtime/v1";
+
+    fn chip_id(&self) -> Option<Cid> {
+        self.chip_id
+    }
+
+    fn set_chip_id(&mut self, id: Cid) {
+        self.chip_id = Some(id);
+    }
+}
+
+impl Chip for VodPredictabilitySidecar {
+    const CHIP_TYPE: &'static str = "vcx/sidecar.predictability.vod";
+    const SIGNATURE_CONTEXT: &'static str = "vcx-sidecar-vod/v1";
+
+    fn chip_id(&self) -> Option<Cid> {
+        self.chip_id
+    }
+
+    fn set_chip_id(&mut self, id: Cid) {
+        self.chip_id = Some(id);
+    }
+}
+
+impl Chip for EditDecisionChip {
+    const CHIP_TYPE: &'static str = "vcx/edit.decision";
+    const SIGNATURE_CONTEXT: &'static str = "vcx-edit-decision/v1";
+
+    fn chip_id(&self) -> Option<Cid> {
+        self.chip_id
+    }
+
+    fn set_chip_id(&mut self, id: Cid) {
+        self.chip_id = Some(id);
+    }
+}
+
+impl Chip for BuildReceiptChip {
+    const CHIP_TYPE: &'static str = "vcx/receipt.build";
+    const SIGNATURE_CONTEXT: &'static str = "vcx-receipt-build/v1";
+
+    fn chip_id(&self) -> Option<Cid> {
+        self.chip_id
+    }
+
+    fn set_chip_id(&mut self, id: Cid) {
+        self.chip_id = Some(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::realtime_predictability::{
+        PolicyMode, RealtimePredictabilityMetrics, EWMA_MODEL_ID,
+    };
+    use crate::streaming_protocol::{
+        EditOperation, RealtimeObserved, RealtimeWindow, ReceiptEncoder, ReceiptInput,
+        SelfVerifyResult, VodGlobalStats, VodRegionRule, VodRegionStrategy,
+    };
+    use crate::B3Digest;
+
+    fn realtime() -> RealtimePredictabilitySidecar {
+        RealtimePredictabilitySidecar::from_metrics(
+            "a/ep/t/live".into(),
+            Cid::blake3([1; 32]),
+            3,
+            RealtimeWindow {
+                groups: 8,
+                duration_ms: 4_000,
+            },
+            RealtimeObserved {
+                guessed_tiles: 7,
+                correct_tiles: 6,
+                corrected_tiles: 1,
+            },
+            EWMA_MODEL_ID,
+            RealtimePredictabilityMetrics {
+                instant_hit_rate: 0.857142857,
+                ewma_hit_rate: 0.9,
+                ewma_volatility: 0.01,
+                predictability_score: 0.891,
+                samples: 3,
+                mode: PolicyMode::Balanced,
+                transition: None,
+                dwell_groups: 1,
+            },
+        )
+    }
+
+    fn vod() -> VodPredictabilitySidecar {
+        VodPredictabilitySidecar {
+            chip_type: VodPredictabilitySidecar::CHIP_TYPE.into(),
+            chip_id: None,
+            chip_ver: "1.0".into(),
+            world: "a/vod".into(),
+            target_manifest: Cid::blake3([2; 32]),
+            global_stats: VodGlobalStats {
+                volatility_score: 0.2,
+                average_shot_length_ms: 2000,
+            },
+            regions: vec![VodRegionRule {
+                region_id: "r0".into(),
+                tile_range_start: 0,
+                tile_range_end: 3,
+                group_range_start: None,
+                group_range_end: None,
+                strategy: VodRegionStrategy::CopyPrevious,
+                confidence: 0.75,
+            }],
+        }
+    }
+
+    fn edit() -> EditDecisionChip {
+        EditDecisionChip {
+            chip_type: EditDecisionChip::CHIP_TYPE.into(),
+            chip_id: None,
+            chip_ver: "1.0".into(),
+            world: "a/episode/t/edit".into(),
+            input_manifest: Cid::blake3([3; 32]),
+            output_manifest: Some(Cid::blake3([4; 32])),
+            operations: vec![EditOperation::Trim {
+                start_ms: 0,
+                end_ms: 1000,
+            }],
+            reencode_required: false,
+            editorial_receipt_cid: Cid::blake3([5; 32]),
+        }
+    }
+
+    fn receipt() -> BuildReceiptChip {
+        BuildReceiptChip {
+            chip_type: BuildReceiptChip::CHIP_TYPE.into(),
+            chip_id: None,
+            chip_ver: "1.0".into(),
+            world: "a/demo/t/prod".into(),
+            input: ReceiptInput {
+                name: "in.mp4".into(),
+                hash_b3: B3Digest([0xab; 32]),
+                size_bytes: 1 << 40,
+            },
+            encoder: ReceiptEncoder {
+                name: "vcx_enc_cli".into(),
+                version: "0.1.0".into(),
+                profile: "vcx-ic0-alpha-luma-raw/v1".into(),
+            },
+            parameters: [("max_frames", "8"), ("tile_size", "64")]
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .into(),
+            tools: [("ffmpeg".to_string(), "ffmpeg version 6.1.1".to_string())].into(),
+            manifest: Cid::blake3([6; 32]),
+            merkle_root: Some(B3Digest([0xcd; 32])),
+            self_verify: SelfVerifyResult {
+                full: true,
+                passed: true,
+                error: None,
+            },
+        }
+    }
+
+    #[test]
+    fn build_receipts_seal_and_check_their_evidence() {
+        let mut r = receipt();
+        r.validate().unwrap();
+        r.seal().unwrap();
+        r.verify_id().unwrap();
+        let json = r.to_json().unwrap();
+        assert_eq!(json["input"]["size_bytes"]["v"], "1099511627776");
+        let back: BuildReceiptChip = serde_json::from_value(json).unwrap();
+        assert_eq!(back.to_nrf().unwrap(), r.to_nrf().unwrap());
+
+        // Any parameter is part of the identity.
+        r.parameters.insert("tile_size".into(), "32".into());
+        assert!(r.verify_id().is_err());
+
+        let mut failed = receipt();
+        failed.self_verify.passed = false;
+        assert!(failed.validate().is_err());
+        failed.self_verify.error = Some("MerkleRootMismatch".into());
+        failed.merkle_root = None;
+        failed.validate().unwrap();
+
+        let mut bad = receipt().to_json().unwrap();
+        bad["input"]["hash_b3"] = format!("b3:{}", "AB".repeat(32)).into();
+        assert!(serde_json::from_value::<BuildReceiptChip>(bad).is_err());
+    }
+
+    #[test]
+    fn cids_are_pinned_and_ignore_id() {
+        let mut rt = realtime();
+        let (mut v, mut e) = (vod(), edit());
+        assert_eq!(
+            rt.cid().unwrap().to_string(),
+            "b3:e938389eedeaa24ecffbb4df43dc1a504ae8d1ff7775330475f8a6bb1efd7ab2"
+        );
+        assert_eq!(
+            v.cid().unwrap().to_string(),
+            "b3:45d29904505934de4e41599c7cc031de7b2888a9a0e9b761092304a9d5fc8b2b"
+        );
+        assert_eq!(
+            e.cid().unwrap().to_string(),
+            "b3:199708916978563506eaee93335cdbfacb616ebf45a98333ff80e822aef5ef97"
+        );
+
+        assert!(rt.to_json().unwrap().get("@id").is_none());
+        assert!(rt
+            .verify_id()
+            .unwrap_err()
+            .to_string()
+            .contains("ChipNotSealed"));
+        let before = rt.cid().unwrap();
+        rt.chip_id = Some(Cid::blake3([9; 32]));
+        assert_eq!(rt.cid().unwrap(), before);
+        assert!(rt.verify_id().is_err());
+        rt.seal().unwrap();
+        rt.verify_id().unwrap();
+        v.seal().unwrap();
+        e.seal().unwrap();
+        v.verify_id().unwrap();
+        e.verify_id().unwrap();
+
+        e.reencode_required = true;
+        assert!(e
+            .verify_id()
+            .unwrap_err()
+            .to_string()
+            .contains("ChipIdMismatch"));
+
+        // The encoding itself carries @id.
+        assert_ne!(v.to_nrf().unwrap(), vod().to_nrf().unwrap());
+    }
+
+    #[test]
+    fn signatures_are_domain_separated() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let mut chip = vod();
+        chip.seal().unwrap();
+        let sig = chip.sign(&key).unwrap();
+        assert_eq!(sig.context, "vcx-sidecar-vod/v1");
+        assert_eq!(chip.verify_signature(&sig).unwrap(), key.verifying_key());
+
+        // Same key and CID bytes under another chip type's context: the signed message differs.
+        let cid = chip.cid().unwrap();
+        let signature =
+            Signature::from_bytes(&hex::decode(&sig.signature).unwrap().try_into().unwrap());
+        let key_of = key.verifying_key();
+        key_of
+            .verify_strict(
+                &signed_message(VodPredictabilitySidecar::SIGNATURE_CONTEXT, &cid),
+                &signature,
+            )
+            .unwrap();
+        assert!(key_of
+            .verify_strict(
+                &signed_message(EditDecisionChip::SIGNATURE_CONTEXT, &cid),
+                &signature
+            )
+            .is_err());
+        let mut relabeled = sig.clone();
+        relabeled.context = EditDecisionChip::SIGNATURE_CONTEXT.into();
+        assert!(chip
+            .verify_signature(&relabeled)
+            .unwrap_err()
+            .to_string()
+            .contains("ChipSignatureContextMismatch"));
+
+        let mut tampered = chip.clone();
+        tampered.regions[0].confidence = 0.5;
+        assert!(tampered
+            .verify_signature(&sig)
+            .unwrap_err()
+            .to_string()
+            .contains("ChipSignatureInvalid"));
+
+        let mut forged = sig;
+        forged.public_key = hex::encode(
+            SigningKey::from_bytes(&[8u8; 32])
+                .verifying_key()
+                .as_bytes(),
+        );
+        assert!(chip.verify_signature(&forged).is_err());
+    }
+}
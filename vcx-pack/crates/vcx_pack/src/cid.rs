@@ -0,0 +1,248 @@
+use anyhow::{bail, Context, Result};
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+use ubl_ai_nrf1::nrf::NrfValue;
+
+/// Hash algorithm of a CID. The code is the `cid_algo` byte of pack index entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CidAlgo {
+    /// BLAKE3 over the NRF-1.1 encoding of the content.
+    Blake3 = 1,
+}
+
+impl CidAlgo {
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_code(code: u8) -> Result<Self> {
+        match code {
+            1 => Ok(Self::Blake3),
+            _ => bail!("UnsupportedCidAlgo({})", code),
+        }
+    }
+
+    /// Prefix of the string form.
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Self::Blake3 => "b3",
+        }
+    }
+}
+
+/// Content identifier: `b3:<64 hex>` as a string, `[algo code, digest..]` as bytes. Orders by
+/// algorithm, then digest bytes (the pack index order).
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Cid {
+    algo: CidAlgo,
+    digest: [u8; 32],
+}
+
+impl Cid {
+    pub const DIGEST_LEN: usize = 32;
+
+    pub fn new(algo: CidAlgo, digest: [u8; 32]) -> Self {
+        Self { algo, digest }
+    }
+
+    pub fn blake3(digest: [u8; 32]) -> Self {
+        Self::new(CidAlgo::Blake3, digest)
+    }
+
+    pub fn algo(&self) -> CidAlgo {
+        self.algo
+    }
+
+    pub fn digest(&self) -> &[u8; 32] {
+        &self.digest
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(1 + Self::DIGEST_LEN);
+        out.push(self.algo.code());
+        out.extend_from_slice(&self.digest);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let (&code, digest) = bytes.split_first().context("EmptyCidBytes")?;
+        let digest = digest
+            .try_into()
+            .ok()
+            .with_context(|| format!("BadCidLenBytes({})", digest.len()))?;
+        Ok(Self::new(CidAlgo::from_code(code)?, digest))
+    }
+
+    /// Read a CID from NRF: either its string or its byte form.
+    pub fn from_nrf(value: &NrfValue) -> Result<Self> {
+        match value {
+            NrfValue::String(s) => s.parse(),
+            NrfValue::Bytes(b) => Self::from_bytes(b),
+            _ => bail!("ExpectedCid"),
+        }
+    }
+}
+
+impl FromStr for Cid {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(Self::blake3(parse_b3_hex(s)?))
+    }
+}
+
+/// `b3:<64 hex>` with the digits exactly as `Display` writes them (lowercase), so a parsed
+/// string always serializes back to itself.
+fn parse_b3_hex(s: &str) -> Result<[u8; 32]> {
+    let hex_part = s.strip_prefix("b3:").context("CidMustStartWithB3")?;
+    let raw = hex::decode(hex_part).context("BadCidHex")?;
+    if hex::encode(&raw) != hex_part {
+        bail!("NonCanonicalCid({})", s);
+    }
+    raw.as_slice()
+        .try_into()
+        .ok()
+        .with_context(|| format!("BadCidLenBytes({})", raw.len()))
+}
+
+impl fmt::Display for Cid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algo.prefix(), hex::encode(self.digest))
+    }
+}
+
+impl fmt::Debug for Cid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Cid({})", self)
+    }
+}
+
+impl From<&Cid> for NrfValue {
+    fn from(cid: &Cid) -> Self {
+        NrfValue::Bytes(cid.to_bytes())
+    }
+}
+
+impl Serialize for Cid {
+    fn serialize<S: Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        if s.is_human_readable() {
+            s.collect_str(self)
+        } else {
+            s.serialize_bytes(&self.to_bytes())
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Cid {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        struct CidVisitor;
+
+        impl Visitor<'_> for CidVisitor {
+            type Value = Cid;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a \"b3:<hex>\" CID")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> std::result::Result<Cid, E> {
+                v.parse()
+                    .map_err(|e: anyhow::Error| E::custom(format!("{} ({})", e, v)))
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> std::result::Result<Cid, E> {
+                Cid::from_bytes(v).map_err(E::custom)
+            }
+        }
+
+        if d.is_human_readable() {
+            d.deserialize_str(CidVisitor)
+        } else {
+            d.deserialize_bytes(CidVisitor)
+        }
+    }
+}
+
+/// Plain BLAKE3 digest of raw bytes (a file, a Merkle root), written like a CID but not one:
+/// nothing is NRF-wrapped before hashing.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct B3Digest(pub [u8; 32]);
+
+impl FromStr for B3Digest {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(Self(parse_b3_hex(s)?))
+    }
+}
+
+impl fmt::Display for B3Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "b3:{}", hex::encode(self.0))
+    }
+}
+
+impl fmt::Debug for B3Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "B3Digest({})", self)
+    }
+}
+
+impl Serialize for B3Digest {
+    fn serialize<S: Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for B3Digest {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        let s = String::deserialize(d)?;
+        s.parse()
+            .map_err(|e: anyhow::Error| de::Error::custom(format!("{} ({})", e, s)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn string_bytes_and_serde_forms_round_trip() {
+        let cid = Cid::blake3([0xab; 32]);
+        let s = cid.to_string();
+        assert_eq!(s, format!("b3:{}", "ab".repeat(32)));
+        assert_eq!(s.parse::<Cid>().unwrap(), cid);
+        assert_eq!(Cid::from_bytes(&cid.to_bytes()).unwrap(), cid);
+        assert_eq!(Cid::from_nrf(&NrfValue::from(&cid)).unwrap(), cid);
+        assert_eq!(Cid::from_nrf(&NrfValue::String(s.clone())).unwrap(), cid);
+
+        let json = serde_json::to_value(cid).unwrap();
+        assert_eq!(json, serde_json::Value::String(s));
+        assert_eq!(serde_json::from_value::<Cid>(json).unwrap(), cid);
+
+        for bad in ["hello", "b3:abc", "b3:zz", "sha2:00"] {
+            assert!(bad.parse::<Cid>().is_err(), "{}", bad);
+            assert!(serde_json::from_value::<Cid>(bad.into()).is_err());
+        }
+        // Uppercase digits would serialize back differently, changing any enclosing CID.
+        let upper = format!("b3:{}", "AB".repeat(32));
+        let err = upper.parse::<Cid>().unwrap_err().to_string();
+        assert!(err.contains("NonCanonicalCid"), "{}", err);
+        assert!(serde_json::from_value::<Cid>(upper.clone().into()).is_err());
+        assert!(upper.parse::<B3Digest>().is_err());
+
+        let root = format!("b3:{}", "cd".repeat(32));
+        let digest: B3Digest = root.parse().unwrap();
+        assert_eq!(digest, B3Digest([0xcd; 32]));
+        assert_eq!(digest.to_string(), root);
+        assert_eq!(
+            serde_json::from_value::<B3Digest>(serde_json::to_value(digest).unwrap()).unwrap(),
+            digest
+        );
+        assert!(Cid::from_bytes(&[2; 33]).is_err());
+        assert!(Cid::from_bytes(&[1; 32]).is_err());
+
+        assert!(Cid::blake3([1; 32]) < Cid::blake3([2; 32]));
+    }
+}
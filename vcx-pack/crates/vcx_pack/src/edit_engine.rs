@@ -0,0 +1,1218 @@
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use crate::chip::Chip;
+use crate::manifest::{Lineage, LineageReason};
+use crate::streaming_protocol::{EditDecisionChip, EditOperation};
+use crate::unc1::{self, convert_ticks, int_value, ms_to_ticks};
+use crate::{cid_for_json_value, validate_no_json_numbers, validate_ubl_manifest_envelope, Cid};
+
+/// Resolves manifests and payload availability by CID when replaying edit decisions.
+pub trait EditResolver {
+    fn manifest(&self, cid: &Cid) -> Option<&Value>;
+    fn has_payload(&self, cid: &Cid) -> bool;
+
+    /// Edit decision by [`EditDecisionChip::decision_cid`], for walking lineage.
+    fn edit_decision(&self, _cid: &Cid) -> Option<&EditDecisionChip> {
+        None
+    }
+}
+
+/// Local manifest store keyed by manifest CID (`b3:<hex>` of the NRF-1.1 bytes), plus the set
+/// of payload CIDs known to be available and the edit decisions known by decision CID.
+#[derive(Debug, Clone, Default)]
+pub struct ManifestStore {
+    manifests: BTreeMap<Cid, Value>,
+    payloads: BTreeSet<Cid>,
+    decisions: BTreeMap<Cid, EditDecisionChip>,
+}
+
+impl ManifestStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a manifest and return its CID.
+    pub fn insert(&mut self, manifest: Value) -> Result<Cid> {
+        validate_ubl_manifest_envelope(&manifest)?;
+        let (cid, _nrf) = cid_for_json_value(&manifest)?;
+        self.manifests.insert(cid, manifest);
+        Ok(cid)
+    }
+
+    pub fn get(&self, cid: &Cid) -> Option<&Value> {
+        self.manifests.get(cid)
+    }
+
+    /// Record a payload CID as available (e.g. present in a pack index).
+    pub fn insert_payload(&mut self, cid: Cid) {
+        self.payloads.insert(cid);
+    }
+
+    /// Add an edit decision (applied or not) and return its decision CID.
+    pub fn insert_edit_decision(&mut self, chip: EditDecisionChip) -> Result<Cid> {
+        let cid = chip.decision_cid()?;
+        self.decisions.insert(cid, chip);
+        Ok(cid)
+    }
+}
+
+impl EditResolver for ManifestStore {
+    fn manifest(&self, cid: &Cid) -> Option<&Value> {
+        self.get(cid)
+    }
+
+    fn has_payload(&self, cid: &Cid) -> bool {
+        self.payloads.contains(cid)
+    }
+
+    fn edit_decision(&self, cid: &Cid) -> Option<&EditDecisionChip> {
+        self.decisions.get(cid)
+    }
+}
+
+/// Output of applying edit operations to a manifest.
+#[derive(Debug, Clone)]
+pub struct EditOutcome {
+    pub manifest: Value,
+    pub manifest_cid: Cid,
+    pub reencode_required: bool,
+}
+
+/// Apply `operations` in order to `input`. Each operation's times refer to the timeline
+/// produced by the previous one. Groups are the smallest unit editable by reference: a cut
+/// or insertion point inside a group, a profile/timebase mismatch on splice, retiming a
+/// manifest that carries a single audio blob, or any operation that is not
+/// [`EditOperation::by_reference`] all set `reencode_required`. Operations that regenerate
+/// samples are recorded in `audio_edits` / `transitions` for the renderer. The output's
+/// `lineage` lists the input and every splice/revert source as parents, except when the last
+/// operation is a revert: the output is then the revert target, byte for byte. Every operation
+/// is validated first.
+pub fn apply_operations<S: EditResolver + ?Sized>(
+    input: &Value,
+    operations: &[EditOperation],
+    store: &S,
+) -> Result<EditOutcome> {
+    apply_with_lineage(input, operations, store, None)
+}
+
+fn apply_with_lineage<S: EditResolver + ?Sized>(
+    input: &Value,
+    operations: &[EditOperation],
+    store: &S,
+    edit_decision: Option<Cid>,
+) -> Result<EditOutcome> {
+    validate_ubl_manifest_envelope(input)?;
+    for (i, op) in operations.iter().enumerate() {
+        op.validate()
+            .with_context(|| format!("InvalidOperation({})", i))?;
+    }
+    let (input_cid, _nrf) = cid_for_json_value(input)?;
+
+    let mut manifest = input.as_object().context("ManifestMustBeObject")?.clone();
+    let mut timebase = unc1::timebase(
+        manifest
+            .get("timebase")
+            .context("ManifestMissingTimebase")?,
+    )?;
+    let mut gots = take_gots(&mut manifest)?;
+    let mut reencode_required = false;
+    let mut timeline_changed = false;
+
+    for op in operations {
+        reencode_required |= !op.by_reference();
+        match op {
+            EditOperation::Trim { start_ms, end_ms } => {
+                let start = ms_to_ticks(*start_ms, timebase);
+                let end = ms_to_ticks(*end_ms, timebase);
+                let before = gots.len();
+                let mut kept = Vec::with_capacity(gots.len());
+                for g in gots {
+                    if g.start < end && g.end() > start {
+                        if g.start < start || g.end() > end {
+                            reencode_required = true;
+                        }
+                        kept.push(g);
+                    }
+                }
+                if kept.is_empty() {
+                    bail!("TrimRemovesAllGroups");
+                }
+                timeline_changed |= kept.len() != before;
+                gots = kept;
+            }
+            EditOperation::SpliceInsert {
+                at_ms,
+                source_manifest,
+            } => {
+                let source = store
+                    .manifest(source_manifest)
+                    .with_context(|| format!("SpliceSourceNotFound({})", source_manifest))?;
+                let at = ms_to_ticks(*at_ms, timebase);
+                let total = gots.last().map(|g| g.end()).unwrap_or(0);
+                if at > total {
+                    bail!("SpliceOutOfRange({})", at_ms);
+                }
+                let pos = gots
+                    .iter()
+                    .position(|g| g.start >= at)
+                    .unwrap_or(gots.len());
+                if !gots.get(pos).map_or(total == at, |g| g.start == at) {
+                    reencode_required = true;
+                }
+
+                let src_obj = source.as_object().context("ManifestMustBeObject")?;
+                if !same_video_profile(&manifest, src_obj) {
+                    reencode_required = true;
+                }
+                let src_tb =
+                    unc1::timebase(src_obj.get("timebase").context("ManifestMissingTimebase")?)?;
+                let mut inserted = take_gots(&mut src_obj.clone())?;
+                if src_tb != timebase {
+                    for g in inserted.iter_mut() {
+                        let (dur, exact) = convert_ticks(g.dur, src_tb, timebase);
+                        reencode_required |= !exact;
+                        g.dur = dur;
+                    }
+                }
+                gots.splice(pos..pos, inserted);
+                timeline_changed = true;
+            }
+            EditOperation::SwapTrackRef {
+                track,
+                from_cid,
+                to_cid,
+            } => {
+                let (from, to) = (from_cid.to_string(), to_cid.to_string());
+                let swapped = match track_key(track)? {
+                    "gots" => gots
+                        .iter_mut()
+                        .map(|g| replace_cid(&mut g.body, &from, &to))
+                        .sum(),
+                    key => manifest
+                        .get_mut(key)
+                        .map(|v| replace_cid(v, &from, &to))
+                        .unwrap_or(0),
+                };
+                if swapped == 0 {
+                    bail!("SwapSourceNotFound({}, {})", track, from_cid);
+                }
+            }
+            EditOperation::OverlayRef {
+                asset_cid,
+                x,
+                y,
+                from_ms,
+                to_ms,
+            } => {
+                let from = ms_to_ticks(*from_ms, timebase);
+                let to = ms_to_ticks(*to_ms, timebase);
+                let total = gots.last().map(|g| g.end()).unwrap_or(0);
+                if to > total {
+                    bail!("OverlayOutOfRange({}..{})", from_ms, to_ms);
+                }
+                let overlays = manifest
+                    .entry("overlays")
+                    .or_insert_with(|| Value::Array(Vec::new()))
+                    .as_array_mut()
+                    .context("OverlaysMustBeArray")?;
+                overlays.push(json!({
+                    "cid": asset_cid,
+                    "x": int_value(*x),
+                    "y": int_value(*y),
+                    "from_tick": int_value(from),
+                    "to_tick": int_value(to),
+                }));
+            }
+            EditOperation::Crop {
+                tile_x,
+                tile_y,
+                tiles_w,
+                tiles_h,
+            } => {
+                let (x0, y0) = (*tile_x as u64, *tile_y as u64);
+                let (x1, y1) = (x0 + *tiles_w as u64, y0 + *tiles_h as u64);
+                let mut size = None;
+                for g in gots.iter_mut() {
+                    let tiles = g
+                        .body
+                        .get_mut("tiles")
+                        .and_then(Value::as_array_mut)
+                        .context("GotMissingTiles")?;
+                    let mut kept = Vec::with_capacity(tiles.len());
+                    for mut t in tiles.drain(..) {
+                        let (tx, ty) = (tile_coord(&t, "tile_x")?, tile_coord(&t, "tile_y")?);
+                        if tx < x0 || tx >= x1 || ty < y0 || ty >= y1 {
+                            continue;
+                        }
+                        t["tile_x"] = int_value(tx - x0);
+                        t["tile_y"] = int_value(ty - y0);
+                        kept.push(t);
+                    }
+                    if kept.len() as u64 != (x1 - x0) * (y1 - y0) {
+                        bail!(
+                            "CropOutOfGrid({},{} {}x{})",
+                            tile_x,
+                            tile_y,
+                            tiles_w,
+                            tiles_h
+                        );
+                    }
+                    if size.is_none() {
+                        let mut w = 0;
+                        let mut h = 0;
+                        for t in &kept {
+                            if tile_coord(t, "tile_y")? == 0 {
+                                w += tile_coord(t, "crop_w")?;
+                            }
+                            if tile_coord(t, "tile_x")? == 0 {
+                                h += tile_coord(t, "crop_h")?;
+                            }
+                        }
+                        size = Some((w, h));
+                    }
+                    *tiles = kept;
+                }
+                if let (Some((w, h)), Some(video)) = (
+                    size,
+                    manifest.get_mut("video").and_then(Value::as_object_mut),
+                ) {
+                    video.insert("width".to_string(), int_value(w));
+                    video.insert("height".to_string(), int_value(h));
+                }
+            }
+            EditOperation::Reorder { segments } => {
+                let mut reordered = Vec::new();
+                for (i, seg) in segments.iter().enumerate() {
+                    let start = ms_to_ticks(seg.start_ms, timebase);
+                    let end = ms_to_ticks(seg.end_ms, timebase);
+                    let before = reordered.len();
+                    for g in gots.iter().filter(|g| g.start < end && g.end() > start) {
+                        if g.start < start || g.end() > end {
+                            reencode_required = true;
+                        }
+                        reordered.push(g.clone());
+                    }
+                    if reordered.len() == before {
+                        bail!("ReorderSegmentEmpty({})", i);
+                    }
+                }
+                gots = reordered;
+                timeline_changed = true;
+            }
+            EditOperation::SpeedChange {
+                ratio_num,
+                ratio_den,
+            } => {
+                if ratio_num > ratio_den {
+                    let step = (ratio_num / ratio_den) as usize;
+                    gots = gots.into_iter().step_by(step).collect();
+                    timeline_changed = true;
+                } else if ratio_num < ratio_den {
+                    let copies = (ratio_den / ratio_num) as usize;
+                    gots = gots
+                        .into_iter()
+                        .flat_map(|g| std::iter::repeat_n(g, copies))
+                        .collect();
+                    timeline_changed = true;
+                }
+            }
+            EditOperation::MuteAudio { start_ms, end_ms }
+            | EditOperation::ReplaceAudio {
+                start_ms, end_ms, ..
+            } => {
+                if !manifest.contains_key("audio") {
+                    bail!("NoAudioTrack");
+                }
+                let from = ms_to_ticks(*start_ms, timebase);
+                let to = ms_to_ticks(*end_ms, timebase);
+                let total = gots.last().map(|g| g.end()).unwrap_or(0);
+                if to > total {
+                    bail!("AudioEditOutOfRange({}..{})", start_ms, end_ms);
+                }
+                let mut entry = json!({
+                    "kind": "mute",
+                    "from_tick": int_value(from),
+                    "to_tick": int_value(to),
+                });
+                if let EditOperation::ReplaceAudio { source_cid, .. } = op {
+                    entry["kind"] = Value::String("replace".to_string());
+                    entry["cid"] = Value::String(source_cid.to_string());
+                }
+                manifest
+                    .entry("audio_edits")
+                    .or_insert_with(|| Value::Array(Vec::new()))
+                    .as_array_mut()
+                    .context("AudioEditsMustBeArray")?
+                    .push(entry);
+            }
+            EditOperation::Crossfade { at_ms, duration_ms } => {
+                let at = ms_to_ticks(*at_ms, timebase);
+                let dur = ms_to_ticks(*duration_ms, timebase);
+                let total = gots.last().map(|g| g.end()).unwrap_or(0);
+                if at == 0 || at.checked_add(dur).is_none_or(|end| end > total) {
+                    bail!("CrossfadeOutOfRange({}+{})", at_ms, duration_ms);
+                }
+                manifest
+                    .entry("transitions")
+                    .or_insert_with(|| Value::Array(Vec::new()))
+                    .as_array_mut()
+                    .context("TransitionsMustBeArray")?
+                    .push(json!({
+                        "kind": "crossfade",
+                        "at_tick": int_value(at),
+                        "dur_ticks": int_value(dur),
+                    }));
+            }
+            EditOperation::Revert { manifest: target } => {
+                let source = store
+                    .manifest(target)
+                    .with_context(|| format!("RevertTargetNotFound({})", target))?;
+                let mut src_obj = source.as_object().context("ManifestMustBeObject")?.clone();
+                timebase =
+                    unc1::timebase(src_obj.get("timebase").context("ManifestMissingTimebase")?)?;
+                gots = take_gots(&mut src_obj)?;
+                manifest = src_obj;
+                // Everything before the revert is discarded with the state it produced.
+                reencode_required = false;
+                timeline_changed = false;
+            }
+        }
+    }
+
+    // Ending on a revert returns to the target itself, so an undo reproduces the input CID.
+    if let Some(EditOperation::Revert { manifest: target }) = operations.last() {
+        let target = store
+            .manifest(target)
+            .with_context(|| format!("RevertTargetNotFound({})", target))?;
+        return Ok(EditOutcome {
+            manifest: target.clone(),
+            manifest_cid: cid_for_json_value(target)?.0,
+            reencode_required,
+        });
+    }
+
+    if timeline_changed && manifest.contains_key("audio") {
+        reencode_required = true;
+    }
+
+    let mut cursor = 0u64;
+    let mut out_gots = Vec::with_capacity(gots.len());
+    for mut g in gots {
+        g.start = cursor;
+        cursor += g.dur;
+        out_gots.push(g.into_value());
+    }
+    manifest.insert("gots".to_string(), Value::Array(out_gots));
+    manifest.insert("duration_ticks".to_string(), int_value(cursor));
+
+    let lineage = Lineage {
+        reason: LineageReason::Edit,
+        parents: edit_parents(&input_cid, operations),
+        edit_decision,
+        source: None,
+        note: None,
+    };
+    manifest.insert("lineage".to_string(), serde_json::to_value(lineage)?);
+
+    let mut id_input = input_cid.to_string().into_bytes();
+    id_input.extend_from_slice(&serde_json::to_vec(operations)?);
+    let id_hash = hex::encode(blake3::hash(&id_input).as_bytes());
+    manifest.insert(
+        "@id".to_string(),
+        Value::String(format!("m:edit:{}", &id_hash[..24])),
+    );
+
+    let manifest = Value::Object(manifest);
+    validate_no_json_numbers(&manifest)?;
+    let (manifest_cid, _nrf) = cid_for_json_value(&manifest)?;
+    Ok(EditOutcome {
+        manifest,
+        manifest_cid,
+        reencode_required,
+    })
+}
+
+/// Lineage parents of an edit: the input, then each splice/revert source once, in order.
+pub(crate) fn edit_parents(input: &Cid, operations: &[EditOperation]) -> Vec<Cid> {
+    let mut parents = vec![*input];
+    for op in operations {
+        if let EditOperation::SpliceInsert {
+            source_manifest: source,
+            ..
+        }
+        | EditOperation::Revert { manifest: source } = op
+        {
+            if !parents.contains(source) {
+                parents.push(*source);
+            }
+        }
+    }
+    parents
+}
+
+/// Execute an edit decision: resolve its input manifest from `store`, apply the operations and
+/// return the chip with the real `output_manifest` CID and `reencode_required` filled in, sealed.
+pub fn apply_edit_decision<S: EditResolver + ?Sized>(
+    chip: &EditDecisionChip,
+    store: &S,
+) -> Result<(EditDecisionChip, EditOutcome)> {
+    chip.validate()?;
+    let input = store
+        .manifest(&chip.input_manifest)
+        .with_context(|| format!("InputManifestNotFound({})", chip.input_manifest))?;
+    let outcome = apply_with_lineage(input, &chip.operations, store, Some(chip.decision_cid()?))?;
+    let mut resolved = chip.clone();
+    resolved.output_manifest = Some(outcome.manifest_cid);
+    resolved.reencode_required = outcome.reencode_required;
+    resolved.seal()?;
+    Ok((resolved, outcome))
+}
+
+/// A single way an edit decision disagrees with the manifests and payloads it refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EditDiscrepancy {
+    InvalidChip(String),
+    InputManifestUnresolved(Cid),
+    SourceManifestUnresolved {
+        op: usize,
+        cid: Cid,
+    },
+    TrimOutOfRange {
+        op: usize,
+        end_ms: u64,
+        duration_ticks: u64,
+    },
+    SwapSourceNotInInput {
+        op: usize,
+        track: String,
+        from_cid: Cid,
+    },
+    PayloadUnresolved(Cid),
+    ReplayFailed(String),
+    OutputManifestMismatch {
+        claimed: Option<Cid>,
+        replayed: Cid,
+    },
+    ReencodeFlagMismatch {
+        claimed: bool,
+        replayed: bool,
+    },
+}
+
+impl fmt::Display for EditDiscrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidChip(e) => write!(f, "InvalidChip({})", e),
+            Self::InputManifestUnresolved(cid) => write!(f, "InputManifestUnresolved({})", cid),
+            Self::SourceManifestUnresolved { op, cid } => {
+                write!(f, "SourceManifestUnresolved(op={}, {})", op, cid)
+            }
+            Self::TrimOutOfRange {
+                op,
+                end_ms,
+                duration_ticks,
+            } => write!(
+                f,
+                "TrimOutOfRange(op={}, end_ms={}, duration_ticks={})",
+                op, end_ms, duration_ticks
+            ),
+            Self::SwapSourceNotInInput {
+                op,
+                track,
+                from_cid,
+            } => write!(
+                f,
+                "SwapSourceNotInInput(op={}, {}, {})",
+                op, track, from_cid
+            ),
+            Self::PayloadUnresolved(cid) => write!(f, "PayloadUnresolved({})", cid),
+            Self::ReplayFailed(e) => write!(f, "ReplayFailed({})", e),
+            Self::OutputManifestMismatch { claimed, replayed } => write!(
+                f,
+                "OutputManifestMismatch(claimed={}, replayed={})",
+                claimed.map_or("none".to_string(), |c| c.to_string()),
+                replayed
+            ),
+            Self::ReencodeFlagMismatch { claimed, replayed } => write!(
+                f,
+                "ReencodeFlagMismatch(claimed={}, replayed={})",
+                claimed, replayed
+            ),
+        }
+    }
+}
+
+/// Result of replaying an edit decision against its context.
+#[derive(Debug, Clone, Default)]
+pub struct EditVerification {
+    pub replayed_output: Option<Cid>,
+    pub discrepancies: Vec<EditDiscrepancy>,
+}
+
+impl EditVerification {
+    pub fn is_ok(&self) -> bool {
+        self.discrepancies.is_empty()
+    }
+}
+
+/// Contextual verification of an edit decision: resolves the input and splice sources, checks
+/// each operation against the timeline it applies to, replays the decision and compares
+/// `output_manifest`, `reencode_required` and payload availability. CIDs are well-formed by
+/// construction.
+pub fn verify_edit_decision<S: EditResolver + ?Sized>(
+    chip: &EditDecisionChip,
+    resolver: &S,
+) -> EditVerification {
+    let mut report = EditVerification::default();
+    let d = &mut report.discrepancies;
+
+    // Nothing in an invalid chip is safe to replay.
+    if let Err(e) = chip.validate() {
+        d.push(EditDiscrepancy::InvalidChip(e.to_string()));
+        return report;
+    }
+
+    let Some(input) = resolver.manifest(&chip.input_manifest) else {
+        d.push(EditDiscrepancy::InputManifestUnresolved(
+            chip.input_manifest,
+        ));
+        return report;
+    };
+
+    // Walk the operations one at a time so each is checked against the timeline it edits.
+    let mut current = input.clone();
+    for (i, op) in chip.operations.iter().enumerate() {
+        let before = d.len();
+        match op {
+            EditOperation::Trim { end_ms, .. } => {
+                let duration_ticks =
+                    unc1::int_field::<u64>(&current, "duration_ticks").unwrap_or(0);
+                let end = current
+                    .get("timebase")
+                    .and_then(|tb| unc1::timebase(tb).ok())
+                    .map(|tb| ms_to_ticks(*end_ms, tb));
+                if end.is_none_or(|end| end > duration_ticks) {
+                    d.push(EditDiscrepancy::TrimOutOfRange {
+                        op: i,
+                        end_ms: *end_ms,
+                        duration_ticks,
+                    });
+                }
+            }
+            EditOperation::SpliceInsert {
+                source_manifest: source,
+                ..
+            }
+            | EditOperation::Revert { manifest: source } => {
+                if resolver.manifest(source).is_none() {
+                    d.push(EditDiscrepancy::SourceManifestUnresolved {
+                        op: i,
+                        cid: *source,
+                    });
+                }
+            }
+            EditOperation::SwapTrackRef {
+                track, from_cid, ..
+            } => {
+                if !track_cids(&current, track).contains(&from_cid.to_string()) {
+                    d.push(EditDiscrepancy::SwapSourceNotInInput {
+                        op: i,
+                        track: track.clone(),
+                        from_cid: *from_cid,
+                    });
+                }
+            }
+            EditOperation::OverlayRef { .. }
+            | EditOperation::Crop { .. }
+            | EditOperation::Reorder { .. }
+            | EditOperation::SpeedChange { .. }
+            | EditOperation::MuteAudio { .. }
+            | EditOperation::ReplaceAudio { .. }
+            | EditOperation::Crossfade { .. } => {}
+        }
+        if d.len() > before {
+            return report;
+        }
+        match apply_operations(&current, std::slice::from_ref(op), resolver) {
+            Ok(step) => current = step.manifest,
+            Err(e) => {
+                d.push(EditDiscrepancy::ReplayFailed(format!("op={}: {}", i, e)));
+                return report;
+            }
+        }
+    }
+
+    let decision = chip.decision_cid().ok();
+    match apply_with_lineage(input, &chip.operations, resolver, decision) {
+        Ok(outcome) => {
+            if chip.output_manifest != Some(outcome.manifest_cid) {
+                d.push(EditDiscrepancy::OutputManifestMismatch {
+                    claimed: chip.output_manifest,
+                    replayed: outcome.manifest_cid,
+                });
+            }
+            if outcome.reencode_required != chip.reencode_required {
+                d.push(EditDiscrepancy::ReencodeFlagMismatch {
+                    claimed: chip.reencode_required,
+                    replayed: outcome.reencode_required,
+                });
+            }
+            match referenced_cids(&outcome.manifest) {
+                Ok(cids) => d.extend(
+                    cids.into_iter()
+                        .filter(|cid| !resolver.has_payload(cid))
+                        .map(EditDiscrepancy::PayloadUnresolved),
+                ),
+                Err(e) => d.push(EditDiscrepancy::ReplayFailed(e.to_string())),
+            }
+            report.replayed_output = Some(outcome.manifest_cid);
+        }
+        Err(e) => d.push(EditDiscrepancy::ReplayFailed(e.to_string())),
+    }
+    report
+}
+
+/// All CIDs referenced by a manifest (tiles, audio and its replacements, sidecars, overlays),
+/// sorted. Fails on a `cid` that is not a valid CID.
+pub fn referenced_cids(manifest: &Value) -> Result<Vec<Cid>> {
+    let mut raw = Vec::new();
+    for key in ["gots", "audio", "audio_edits", "sidecars", "overlays"] {
+        if let Some(v) = manifest.get(key) {
+            collect_cids(v, &mut raw);
+        }
+    }
+    let mut out = raw
+        .iter()
+        .map(|s| s.parse().with_context(|| format!("BadCidRef({})", s)))
+        .collect::<Result<Vec<Cid>>>()?;
+    out.sort();
+    out.dedup();
+    Ok(out)
+}
+
+/// Manifest key holding an edit track.
+fn track_key(track: &str) -> Result<&'static str> {
+    Ok(match track {
+        "video" => "gots",
+        "audio" => "audio",
+        "sidecars" => "sidecars",
+        "overlays" => "overlays",
+        other => bail!("UnknownTrack({})", other),
+    })
+}
+
+fn track_cids(manifest: &Value, track: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    if let Some(v) = track_key(track).ok().and_then(|k| manifest.get(k)) {
+        collect_cids(v, &mut out);
+    }
+    out
+}
+
+fn collect_cids(v: &Value, out: &mut Vec<String>) {
+    match v {
+        Value::Object(o) => {
+            for (k, x) in o {
+                match x {
+                    Value::String(s) if k == "cid" => out.push(s.clone()),
+                    _ => collect_cids(x, out),
+                }
+            }
+        }
+        Value::Array(a) => a.iter().for_each(|x| collect_cids(x, out)),
+        _ => {}
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Got {
+    start: u64,
+    dur: u64,
+    body: Value,
+}
+
+impl Got {
+    fn end(&self) -> u64 {
+        self.start + self.dur
+    }
+
+    fn into_value(mut self) -> Value {
+        if let Some(o) = self.body.as_object_mut() {
+            o.insert("start_tick".to_string(), int_value(self.start));
+            o.insert("dur_ticks".to_string(), int_value(self.dur));
+        }
+        self.body
+    }
+}
+
+fn take_gots(manifest: &mut Map<String, Value>) -> Result<Vec<Got>> {
+    let arr = match manifest.remove("gots") {
+        Some(Value::Array(a)) => a,
+        _ => bail!("ManifestMissingGots"),
+    };
+    let mut out = Vec::with_capacity(arr.len());
+    for (i, body) in arr.into_iter().enumerate() {
+        let start = unc1::int_field::<u64>(&body, "start_tick")
+            .with_context(|| format!("BadGot({})", i))?;
+        let dur =
+            unc1::int_field::<u64>(&body, "dur_ticks").with_context(|| format!("BadGot({})", i))?;
+        out.push(Got { start, dur, body });
+    }
+    Ok(out)
+}
+
+fn same_video_profile(a: &Map<String, Value>, b: &Map<String, Value>) -> bool {
+    if a.get("profile") != b.get("profile") {
+        return false;
+    }
+    let (va, vb) = (a.get("video"), b.get("video"));
+    ["codec", "width", "height", "tile_size"]
+        .iter()
+        .all(|k| va.and_then(|v| v.get(k)) == vb.and_then(|v| v.get(k)))
+}
+
+fn replace_cid(v: &mut Value, from: &str, to: &str) -> usize {
+    match v {
+        Value::Object(o) => o
+            .iter_mut()
+            .map(|(k, x)| match x {
+                Value::String(s) if k == "cid" && s == from => {
+                    *s = to.to_string();
+                    1
+                }
+                _ => replace_cid(x, from, to),
+            })
+            .sum(),
+        Value::Array(a) => a.iter_mut().map(|x| replace_cid(x, from, to)).sum(),
+        _ => 0,
+    }
+}
+
+/// Tile grid fields are decimal strings in tile refs.
+fn tile_coord(tile: &Value, key: &str) -> Result<u64> {
+    tile.get(key)
+        .and_then(|v| unc1::parse_int(v).ok())
+        .with_context(|| format!("CropNeedsTileField({})", key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{b3, decision, timeline};
+    use crate::streaming_protocol::EditSegment;
+
+    fn tile_cids(m: &Value) -> Vec<Cid> {
+        m["gots"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|g| g["tiles"][0]["cid"].as_str().unwrap().parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn trim_on_group_boundaries_is_by_reference_and_retimes() {
+        let input = timeline(&[0, 1, 2, 3], 45_000);
+        let out = apply_operations(
+            &input,
+            &[EditOperation::Trim {
+                start_ms: 500,
+                end_ms: 1500,
+            }],
+            &ManifestStore::new(),
+        )
+        .unwrap();
+
+        assert!(!out.reencode_required);
+        assert_eq!(tile_cids(&out.manifest), [b3(1), b3(2)]);
+        assert_eq!(out.manifest["gots"][0]["start_tick"], int_value(0));
+        assert_eq!(out.manifest["duration_ticks"], int_value(90_000));
+        assert_ne!(out.manifest["@id"], input["@id"]);
+        assert_eq!(
+            out.manifest_cid,
+            cid_for_json_value(&out.manifest).unwrap().0
+        );
+    }
+
+    #[test]
+    fn trim_inside_group_requires_reencode() {
+        let input = timeline(&[0, 1], 45_000);
+        let out = apply_operations(
+            &input,
+            &[EditOperation::Trim {
+                start_ms: 100,
+                end_ms: 1000,
+            }],
+            &ManifestStore::new(),
+        )
+        .unwrap();
+        assert!(out.reencode_required);
+    }
+
+    #[test]
+    fn splice_insert_resolves_source_from_store() {
+        let mut store = ManifestStore::new();
+        let source = store.insert(timeline(&[24, 25], 45_000)).unwrap();
+        let mut input = timeline(&[0, 1], 45_000);
+        input["sidecars"] = json!([{"cid": b3(40), "mime": "application/vcx-sidecar"}]);
+        let ops = [
+            EditOperation::SpliceInsert {
+                at_ms: 500,
+                source_manifest: source,
+            },
+            EditOperation::SwapTrackRef {
+                track: "sidecars".into(),
+                from_cid: b3(40),
+                to_cid: b3(41),
+            },
+        ];
+
+        let a = apply_operations(&input, &ops, &store).unwrap();
+        let b = apply_operations(&input, &ops, &store).unwrap();
+        assert_eq!(a.manifest_cid, b.manifest_cid);
+        assert!(!a.reencode_required);
+        assert_eq!(tile_cids(&a.manifest), [b3(0), b3(24), b3(25), b3(1)]);
+        assert_eq!(a.manifest["gots"][3]["start_tick"], int_value(135_000));
+        assert_eq!(a.manifest["sidecars"][0]["cid"], b3(41).to_string());
+    }
+
+    #[test]
+    fn invalid_operations_are_errors_not_panics() {
+        let input = timeline(&[0, 1], 45_000);
+        let store = ManifestStore::new();
+        for (op, error) in [
+            (
+                EditOperation::SpeedChange {
+                    ratio_num: 0,
+                    ratio_den: 1,
+                },
+                "InvalidSpeedRatio",
+            ),
+            (
+                EditOperation::Crop {
+                    tile_x: 0,
+                    tile_y: 0,
+                    tiles_w: 0,
+                    tiles_h: 1,
+                },
+                "InvalidCropSize",
+            ),
+            (
+                EditOperation::Crossfade {
+                    at_ms: u64::MAX,
+                    duration_ms: 1,
+                },
+                "CrossfadeOutOfRange",
+            ),
+            // Valid in milliseconds, but the tick conversion saturates.
+            (
+                EditOperation::Crossfade {
+                    at_ms: u64::MAX - 1,
+                    duration_ms: 1,
+                },
+                "CrossfadeOutOfRange",
+            ),
+        ] {
+            let err = apply_operations(&input, &[op], &store).unwrap_err();
+            assert!(format!("{:#}", err).contains(error), "{:#}", err);
+        }
+    }
+
+    #[test]
+    fn edit_decision_fills_output_manifest() {
+        let mut store = ManifestStore::new();
+        let input_cid = store.insert(timeline(&[0, 1], 45_000)).unwrap();
+        let chip = EditDecisionChip {
+            reencode_required: true,
+            ..decision(
+                input_cid,
+                vec![EditOperation::OverlayRef {
+                    asset_cid: b3(42),
+                    x: -8,
+                    y: 16,
+                    from_ms: 0,
+                    to_ms: 1000,
+                }],
+            )
+        };
+
+        let (resolved, outcome) = apply_edit_decision(&chip, &store).unwrap();
+        assert_eq!(resolved.output_manifest, Some(outcome.manifest_cid));
+        assert!(!resolved.reencode_required);
+        assert_eq!(outcome.manifest["overlays"][0]["x"]["v"], "-8");
+        assert!(referenced_cids(&outcome.manifest)
+            .unwrap()
+            .contains(&b3(42)));
+    }
+
+    fn review_fixture() -> (ManifestStore, EditDecisionChip) {
+        let mut store = ManifestStore::new();
+        let input_cid = store.insert(timeline(&[1, 2, 3], 45_000)).unwrap();
+        for cid in [b3(1), b3(2), b3(3), b3(9)] {
+            store.insert_payload(cid);
+        }
+        let mut chip = decision(
+            input_cid,
+            vec![
+                EditOperation::Trim {
+                    start_ms: 0,
+                    end_ms: 1000,
+                },
+                EditOperation::SwapTrackRef {
+                    track: "video".into(),
+                    from_cid: b3(2),
+                    to_cid: b3(9),
+                },
+            ],
+        );
+        let (resolved, _) = apply_edit_decision(&chip, &store).unwrap();
+        chip.output_manifest = resolved.output_manifest;
+        (store, chip)
+    }
+
+    #[test]
+    fn verifier_accepts_honest_decision() {
+        let (store, chip) = review_fixture();
+        let report = verify_edit_decision(&chip, &store);
+        assert!(report.is_ok(), "{:?}", report.discrepancies);
+        assert_eq!(report.replayed_output, chip.output_manifest);
+    }
+
+    #[test]
+    fn verifier_reports_forged_output_and_flag() {
+        let (store, mut chip) = review_fixture();
+        chip.output_manifest = Some(b3(30));
+        chip.reencode_required = true;
+        let report = verify_edit_decision(&chip, &store);
+        assert!(matches!(
+            report.discrepancies[..],
+            [
+                EditDiscrepancy::OutputManifestMismatch { .. },
+                EditDiscrepancy::ReencodeFlagMismatch {
+                    claimed: true,
+                    replayed: false
+                }
+            ]
+        ));
+    }
+
+    #[test]
+    fn verifier_reports_context_errors() {
+        let (store, mut chip) = review_fixture();
+        let mut malformed = serde_json::to_value(&chip).unwrap();
+        malformed["editorial_receipt_cid"] = "hello".into();
+        assert!(serde_json::from_value::<EditDecisionChip>(malformed).is_err());
+
+        chip.operations[1] = EditOperation::SwapTrackRef {
+            track: "video".into(),
+            from_cid: b3(3),
+            to_cid: b3(9),
+        };
+        let report = verify_edit_decision(&chip, &store);
+        assert_eq!(
+            report.discrepancies,
+            [EditDiscrepancy::SwapSourceNotInInput {
+                op: 1,
+                track: "video".into(),
+                from_cid: b3(3)
+            }]
+        );
+
+        chip.operations = vec![EditOperation::Trim {
+            start_ms: 0,
+            end_ms: 5000,
+        }];
+        let report = verify_edit_decision(&chip, &store);
+        assert!(report
+            .discrepancies
+            .iter()
+            .any(|d| matches!(d, EditDiscrepancy::TrimOutOfRange { op: 0, .. })));
+
+        // A forged operation is reported, never replayed.
+        chip.operations = vec![EditOperation::SpeedChange {
+            ratio_num: 0,
+            ratio_den: 1,
+        }];
+        let report = verify_edit_decision(&chip, &store);
+        assert!(matches!(
+            &report.discrepancies[..],
+            [EditDiscrepancy::InvalidChip(e)] if e.contains("InvalidSpeedRatio")
+        ));
+    }
+
+    #[test]
+    fn verifier_reports_unavailable_payloads() {
+        let (mut store, mut chip) = review_fixture();
+        chip.operations[1] = EditOperation::OverlayRef {
+            asset_cid: b3(11),
+            x: 0,
+            y: 0,
+            from_ms: 0,
+            to_ms: 500,
+        };
+        chip.output_manifest = apply_edit_decision(&chip, &store)
+            .unwrap()
+            .0
+            .output_manifest;
+        let report = verify_edit_decision(&chip, &store);
+        assert_eq!(
+            report.discrepancies,
+            [EditDiscrepancy::PayloadUnresolved(b3(11))]
+        );
+
+        store.insert_payload(b3(11));
+        assert!(verify_edit_decision(&chip, &store).is_ok());
+    }
+
+    #[test]
+    fn swap_of_missing_ref_fails() {
+        let input = timeline(&[0], 45_000);
+        let err = apply_operations(
+            &input,
+            &[EditOperation::SwapTrackRef {
+                track: "audio".into(),
+                from_cid: b3(43),
+                to_cid: b3(44),
+            }],
+            &ManifestStore::new(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("SwapSourceNotFound"));
+    }
+
+    #[test]
+    fn reorder_and_speed_change_are_by_reference() {
+        let input = timeline(&[0, 1, 2, 3], 45_000);
+        let out = apply_operations(
+            &input,
+            &[
+                EditOperation::Reorder {
+                    segments: vec![
+                        EditSegment {
+                            start_ms: 1000,
+                            end_ms: 2000,
+                        },
+                        EditSegment {
+                            start_ms: 0,
+                            end_ms: 1000,
+                        },
+                    ],
+                },
+                EditOperation::SpeedChange {
+                    ratio_num: 2,
+                    ratio_den: 1,
+                },
+            ],
+            &ManifestStore::new(),
+        )
+        .unwrap();
+        assert!(!out.reencode_required);
+        assert_eq!(tile_cids(&out.manifest), [b3(2), b3(0)]);
+
+        let slow = apply_operations(
+            &input,
+            &[EditOperation::SpeedChange {
+                ratio_num: 1,
+                ratio_den: 2,
+            }],
+            &ManifestStore::new(),
+        )
+        .unwrap();
+        assert_eq!(slow.manifest["gots"].as_array().unwrap().len(), 8);
+        assert_eq!(slow.manifest["duration_ticks"]["v"], "360000");
+    }
+
+    #[test]
+    fn crop_keeps_roi_tiles_and_rebases_grid() {
+        let mut input = timeline(&[0], 45_000);
+        let tiles: Vec<Value> = (0..3)
+            .flat_map(|y| (0..3).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                json!({
+                    "cid": b3(50 + 3 * y + x).to_string(),
+                    "mime": "application/vcx-ic0t",
+                    "role": "base",
+                    "tile_x": int_value(x),
+                    "tile_y": int_value(y),
+                    "crop_w": int_value(if x == 2 { 16 } else { 64 }),
+                    "crop_h": int_value(64)
+                })
+            })
+            .collect();
+        input["gots"][0]["tiles"] = Value::Array(tiles);
+        input["video"] = json!({"width": int_value(144), "height": int_value(192)});
+
+        let out = apply_operations(
+            &input,
+            &[EditOperation::Crop {
+                tile_x: 1,
+                tile_y: 1,
+                tiles_w: 2,
+                tiles_h: 2,
+            }],
+            &ManifestStore::new(),
+        )
+        .unwrap();
+        assert!(!out.reencode_required);
+        let kept = out.manifest["gots"][0]["tiles"].as_array().unwrap();
+        assert_eq!(kept.len(), 4);
+        assert_eq!(kept[0]["cid"], b3(54).to_string());
+        assert_eq!(kept[0]["tile_x"], int_value(0));
+        assert_eq!(out.manifest["video"]["width"]["v"], "80");
+        assert_eq!(out.manifest["video"]["height"]["v"], "128");
+
+        let err = apply_operations(
+            &input,
+            &[EditOperation::Crop {
+                tile_x: 2,
+                tile_y: 0,
+                tiles_w: 2,
+                tiles_h: 1,
+            }],
+            &ManifestStore::new(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("CropOutOfGrid"));
+    }
+
+    #[test]
+    fn sample_level_operations_force_reencode() {
+        let mut input = timeline(&[0, 1], 45_000);
+        input["audio"] = json!({"codec": "opus", "cid": b3(45).to_string(), "mime": "audio/opus"});
+        let out = apply_operations(
+            &input,
+            &[
+                EditOperation::ReplaceAudio {
+                    start_ms: 0,
+                    end_ms: 500,
+                    source_cid: b3(46),
+                },
+                EditOperation::Crossfade {
+                    at_ms: 500,
+                    duration_ms: 250,
+                },
+            ],
+            &ManifestStore::new(),
+        )
+        .unwrap();
+        assert!(out.reencode_required);
+        assert_eq!(out.manifest["audio_edits"][0]["kind"], "replace");
+        assert_eq!(out.manifest["transitions"][0]["dur_ticks"]["v"], "22500");
+        assert!(referenced_cids(&out.manifest).unwrap().contains(&b3(46)));
+
+        let no_audio = timeline(&[0], 45_000);
+        let err = apply_operations(
+            &no_audio,
+            &[EditOperation::MuteAudio {
+                start_ms: 0,
+                end_ms: 100,
+            }],
+            &ManifestStore::new(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("NoAudioTrack"));
+    }
+}
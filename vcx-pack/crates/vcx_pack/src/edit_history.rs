@@ -0,0 +1,969 @@
+use anyhow::{bail, Context, Result};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+
+use crate::edit_engine::{
+    apply_edit_decision, apply_operations, referenced_cids, EditOutcome, EditResolver,
+};
+use crate::streaming_protocol::{EditDecisionChip, EditOperation};
+use crate::unc1::{self, ticks_to_ms};
+use crate::Cid;
+use serde_json::Value;
+
+/// Edit lineage as a graph: nodes are manifest CIDs, edges are applied edit decisions from
+/// `input_manifest` to `output_manifest`. It is acyclic except for undo edges, which return to
+/// a manifest already in the history.
+#[derive(Debug, Clone, Default)]
+pub struct EditHistory {
+    chips: Vec<EditDecisionChip>,
+    decisions: BTreeSet<Cid>,
+    by_input: BTreeMap<Cid, Vec<usize>>,
+    by_output: BTreeMap<Cid, Vec<usize>>,
+}
+
+impl EditHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an applied decision. Unresolved decisions, decisions already present (by
+    /// [`EditDecisionChip::decision_cid`]) and edges that would close a cycle are rejected,
+    /// except undo edges: decisions ending in a revert to their own output.
+    pub fn insert(&mut self, chip: EditDecisionChip) -> Result<()> {
+        chip.validate()?;
+        let output = output_of(&chip)?;
+        let decision = chip.decision_cid()?;
+        if self.decisions.contains(&decision) {
+            bail!("DuplicateEditDecision({})", decision);
+        }
+        let undo = matches!(
+            chip.operations.last(),
+            Some(EditOperation::Revert { manifest }) if *manifest == output
+        );
+        if !undo
+            && (chip.input_manifest == output || self.is_ancestor(&output, &chip.input_manifest))
+        {
+            bail!("EditHistoryCycle({} -> {})", chip.input_manifest, output);
+        }
+        self.decisions.insert(decision);
+        let idx = self.chips.len();
+        self.by_input
+            .entry(chip.input_manifest)
+            .or_default()
+            .push(idx);
+        self.by_output.entry(output).or_default().push(idx);
+        self.chips.push(chip);
+        Ok(())
+    }
+
+    pub fn chips(&self) -> &[EditDecisionChip] {
+        &self.chips
+    }
+
+    /// Decisions whose output is `manifest`, in insertion order.
+    pub fn producers(&self, manifest: &Cid) -> Vec<&EditDecisionChip> {
+        self.edges(&self.by_output, manifest)
+    }
+
+    /// Decisions whose input is `manifest`, in insertion order.
+    pub fn consumers(&self, manifest: &Cid) -> Vec<&EditDecisionChip> {
+        self.edges(&self.by_input, manifest)
+    }
+
+    /// All manifests `manifest` was derived from (excluding itself).
+    pub fn ancestors(&self, manifest: &Cid) -> BTreeSet<Cid> {
+        let mut out = BTreeSet::new();
+        let mut queue = VecDeque::from([*manifest]);
+        while let Some(cid) = queue.pop_front() {
+            for chip in self.producers(&cid) {
+                if out.insert(chip.input_manifest) {
+                    queue.push_back(chip.input_manifest);
+                }
+            }
+        }
+        out.remove(manifest);
+        out
+    }
+
+    pub fn is_ancestor(&self, ancestor: &Cid, manifest: &Cid) -> bool {
+        self.ancestors(manifest).contains(ancestor)
+    }
+
+    /// First-parent history of `manifest`, oldest decision first. It stops where an undo
+    /// loops back to a manifest already visited.
+    pub fn history(&self, manifest: &Cid) -> Vec<&EditDecisionChip> {
+        let mut out = Vec::new();
+        let mut seen = BTreeSet::from([*manifest]);
+        let mut cid = manifest;
+        while let Some(chip) = self.producers(cid).into_iter().next() {
+            out.push(chip);
+            cid = &chip.input_manifest;
+            if !seen.insert(*cid) {
+                break;
+            }
+        }
+        out.reverse();
+        out
+    }
+
+    /// Shortest chain of decisions leading from `from` to `to`, oldest first.
+    pub fn path(&self, from: &Cid, to: &Cid) -> Option<Vec<&EditDecisionChip>> {
+        let mut via: BTreeMap<&Cid, &EditDecisionChip> = BTreeMap::new();
+        let mut queue = VecDeque::from([to]);
+        while let Some(cid) = queue.pop_front() {
+            if cid == from {
+                let mut out = Vec::new();
+                let mut cur = from;
+                while let Some(chip) = via.get(cur) {
+                    out.push(*chip);
+                    cur = chip.output_manifest.as_ref()?;
+                }
+                return Some(out);
+            }
+            for chip in self.producers(cid) {
+                let input = &chip.input_manifest;
+                if input != to && !via.contains_key(input) {
+                    via.insert(input, chip);
+                    queue.push_back(input);
+                }
+            }
+        }
+        None
+    }
+
+    /// Nearest manifest both `a` and `b` derive from (either may be the ancestor itself).
+    pub fn common_ancestor(&self, a: &Cid, b: &Cid) -> Option<Cid> {
+        let mut anc_a = self.ancestors(a);
+        anc_a.insert(*a);
+        let mut seen = BTreeSet::new();
+        let mut queue = VecDeque::from([*b]);
+        while let Some(cid) = queue.pop_front() {
+            if anc_a.contains(&cid) {
+                return Some(cid);
+            }
+            for chip in self.producers(&cid) {
+                if seen.insert(chip.input_manifest) {
+                    queue.push_back(chip.input_manifest);
+                }
+            }
+        }
+        None
+    }
+
+    /// Three-way merge of the chains leading to `ours` and `theirs` from their common ancestor.
+    pub fn merge_heads<S: EditResolver + ?Sized>(
+        &self,
+        ours: &Cid,
+        theirs: &Cid,
+        editorial_receipt_cid: &Cid,
+        store: &S,
+    ) -> Result<EditMerge> {
+        let Some(base) = self.common_ancestor(ours, theirs) else {
+            bail!("NoCommonAncestor({}, {})", ours, theirs);
+        };
+        let chain = |head: &Cid| -> Vec<EditDecisionChip> {
+            self.path(&base, head)
+                .unwrap_or_default()
+                .into_iter()
+                .cloned()
+                .collect()
+        };
+        merge(
+            &base,
+            &chain(ours),
+            &chain(theirs),
+            editorial_receipt_cid,
+            store,
+        )
+    }
+
+    fn edges(&self, map: &BTreeMap<Cid, Vec<usize>>, manifest: &Cid) -> Vec<&EditDecisionChip> {
+        map.get(manifest)
+            .map(|ids| ids.iter().map(|&i| &self.chips[i]).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Collapse a linked chain of decisions into one decision from the first input. Operations are
+/// concatenated, which is exact because each operation already refers to the timeline produced
+/// by the previous one. The squashed output equals the chain's last output except for `@id` and
+/// `lineage`, so its CID differs.
+pub fn squash<S: EditResolver + ?Sized>(
+    chain: &[EditDecisionChip],
+    editorial_receipt_cid: &Cid,
+    store: &S,
+) -> Result<(EditDecisionChip, EditOutcome)> {
+    let Some(first) = chain.first() else {
+        bail!("EmptyEditChain");
+    };
+    check_chain(&first.input_manifest, &first.world, chain)?;
+    let operations = chain.iter().flat_map(|c| c.operations.clone()).collect();
+    derive_decision(
+        first,
+        &first.input_manifest,
+        operations,
+        editorial_receipt_cid,
+        store,
+    )
+}
+
+/// Decision undoing `chip`: a single revert from its output to its input. The output is the
+/// input manifest itself, same bytes and CID; in an [`EditHistory`] the undo edge loops back.
+pub fn inverse<S: EditResolver + ?Sized>(
+    chip: &EditDecisionChip,
+    editorial_receipt_cid: &Cid,
+    store: &S,
+) -> Result<(EditDecisionChip, EditOutcome)> {
+    chip.validate()?;
+    derive_decision(
+        chip,
+        &output_of(chip)?,
+        vec![EditOperation::Revert {
+            manifest: chip.input_manifest,
+        }],
+        editorial_receipt_cid,
+        store,
+    )
+}
+
+/// Why two concurrent chains cannot be merged automatically. Operation indices count across
+/// each whole chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EditConflict {
+    OverlappingRange {
+        ours_op: usize,
+        theirs_op: usize,
+        ours: (u64, u64),
+        theirs: (u64, u64),
+    },
+    SameTrackRef {
+        ours_op: usize,
+        theirs_op: usize,
+        cid: Cid,
+    },
+    /// One side swaps a track ref that the other side's structural operations dropped.
+    RefRewritten {
+        ours_op: usize,
+        theirs_op: usize,
+        cid: Cid,
+    },
+    /// One side edits a range that a structural operation of the other side drops or splits.
+    RangeRewritten {
+        ours_op: usize,
+        theirs_op: usize,
+        range: (u64, u64),
+    },
+    /// Both sides move or reshape the timeline or frame.
+    BothRestructure { ours_op: usize, theirs_op: usize },
+}
+
+impl fmt::Display for EditConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OverlappingRange {
+                ours_op,
+                theirs_op,
+                ours,
+                theirs,
+            } => write!(
+                f,
+                "OverlappingRange(ours[{}]={}..{}, theirs[{}]={}..{})",
+                ours_op, ours.0, ours.1, theirs_op, theirs.0, theirs.1
+            ),
+            Self::SameTrackRef {
+                ours_op,
+                theirs_op,
+                cid,
+            } => write!(
+                f,
+                "SameTrackRef(ours[{}], theirs[{}], {})",
+                ours_op, theirs_op, cid
+            ),
+            Self::RefRewritten {
+                ours_op,
+                theirs_op,
+                cid,
+            } => write!(
+                f,
+                "RefRewritten(ours[{}], theirs[{}], {})",
+                ours_op, theirs_op, cid
+            ),
+            Self::RangeRewritten {
+                ours_op,
+                theirs_op,
+                range,
+            } => write!(
+                f,
+                "RangeRewritten(ours[{}], theirs[{}], {}..{})",
+                ours_op, theirs_op, range.0, range.1
+            ),
+            Self::BothRestructure { ours_op, theirs_op } => {
+                write!(
+                    f,
+                    "BothRestructure(ours[{}], theirs[{}])",
+                    ours_op, theirs_op
+                )
+            }
+        }
+    }
+}
+
+/// Result of a three-way merge: either conflicts or the merged decision from the base.
+#[derive(Debug, Clone)]
+pub struct EditMerge {
+    pub base: Cid,
+    pub conflicts: Vec<EditConflict>,
+    pub merged: Option<(EditDecisionChip, EditOutcome)>,
+}
+
+impl EditMerge {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// Merge two chains that both start at `base`. Trim, splice, reorder, speed, crop and revert
+/// are structural: they move or reshape the timeline or frame, and only one side may have
+/// them. That side (else `ours`) is applied first. Each range of the other side is shifted
+/// through it onto the timeline each of its operations sees, in milliseconds: by a trim's
+/// start, a splice source's duration, reorder segment offsets or a speed ratio (rounded
+/// outward). Conflicts are ranges that then overlap, ranges a structural operation drops or
+/// splits, swaps of a track ref both sides swap, and swaps of a ref the first side's head no
+/// longer has. A clean merge appends the other side with its shifted ranges.
+pub fn merge<S: EditResolver + ?Sized>(
+    base: &Cid,
+    ours: &[EditDecisionChip],
+    theirs: &[EditDecisionChip],
+    editorial_receipt_cid: &Cid,
+    store: &S,
+) -> Result<EditMerge> {
+    let Some(template) = ours.first().or(theirs.first()) else {
+        bail!("NothingToMerge");
+    };
+    check_chain(base, &template.world, ours)?;
+    check_chain(base, &template.world, theirs)?;
+
+    let ops = |chain: &[EditDecisionChip]| -> Vec<EditOperation> {
+        chain.iter().flat_map(|c| c.operations.clone()).collect()
+    };
+    let (ours_ops, theirs_ops) = (ops(ours), ops(theirs));
+    let restructures =
+        |ops: &[EditOperation]| ops.iter().any(|op| footprint(op) == Footprint::Structural);
+    let ours_first = restructures(&ours_ops) || !restructures(&theirs_ops);
+    let (first, first_chain, second) = if ours_first {
+        (&ours_ops, ours, &theirs_ops)
+    } else {
+        (&theirs_ops, theirs, &ours_ops)
+    };
+    // The manifest each structural operation of the first side applies to.
+    let base_manifest = store
+        .manifest(base)
+        .with_context(|| format!("MergeBaseNotFound({})", base))?;
+    let mut before = BTreeMap::new();
+    for (i, op) in first.iter().enumerate() {
+        if footprint(op) == Footprint::Structural {
+            let m = match i {
+                0 => base_manifest.clone(),
+                _ => apply_operations(base_manifest, &first[..i], store)?.manifest,
+            };
+            before.insert(i, m);
+        }
+    }
+    // Refs a swap on the second side can still find, when the first side restructured.
+    let restructured_at = before.keys().next().copied();
+    let mut refs: Option<BTreeSet<Cid>> = None;
+    if restructured_at.is_some() {
+        let head = first_chain.last().map_or(Ok(*base), output_of)?;
+        refs = Some(match store.manifest(&head) {
+            Some(m) => referenced_cids(m)?.into_iter().collect(),
+            None => BTreeSet::new(),
+        });
+    }
+
+    let mut report = EditMerge {
+        base: *base,
+        conflicts: Vec::new(),
+        merged: None,
+    };
+    let mut shifted = Vec::with_capacity(second.len());
+    for (j, b) in second.iter().enumerate() {
+        let fb = footprint(b);
+        let mut range = match fb {
+            Footprint::Range(r) => Some(r),
+            _ => None,
+        };
+        for (i, a) in first.iter().enumerate() {
+            let (ours_op, theirs_op) = if ours_first { (i, j) } else { (j, i) };
+            match (footprint(a), fb) {
+                (Footprint::Range(ra), Footprint::Range(rb))
+                    if range.is_some_and(|(s, e)| ra.0 < e && s < ra.1) =>
+                {
+                    let (ours, theirs) = if ours_first { (ra, rb) } else { (rb, ra) };
+                    report.conflicts.push(EditConflict::OverlappingRange {
+                        ours_op,
+                        theirs_op,
+                        ours,
+                        theirs,
+                    });
+                }
+                (Footprint::Swap(ra), Footprint::Swap(rb)) => {
+                    if let Some(cid) = ra.iter().find(|c| rb.contains(c)) {
+                        report.conflicts.push(EditConflict::SameTrackRef {
+                            ours_op,
+                            theirs_op,
+                            cid: **cid,
+                        });
+                    }
+                }
+                (Footprint::Structural, Footprint::Range(rb)) => {
+                    if let Some(r) = range {
+                        range = shift_range(a, r, &before[&i], store)?;
+                        if range.is_none() {
+                            report.conflicts.push(EditConflict::RangeRewritten {
+                                ours_op,
+                                theirs_op,
+                                range: rb,
+                            });
+                        }
+                    }
+                }
+                (Footprint::Structural, Footprint::Structural) => {
+                    report
+                        .conflicts
+                        .push(EditConflict::BothRestructure { ours_op, theirs_op });
+                }
+                _ => {}
+            }
+        }
+        if let (Footprint::Swap([from, to]), Some(known)) = (fb, refs.as_mut()) {
+            if !known.contains(from) {
+                let s = restructured_at.unwrap_or(0);
+                let (ours_op, theirs_op) = if ours_first { (s, j) } else { (j, s) };
+                report.conflicts.push(EditConflict::RefRewritten {
+                    ours_op,
+                    theirs_op,
+                    cid: *from,
+                });
+            }
+            known.insert(*to);
+        }
+        shifted.push(match range {
+            Some(r) => with_range(b, r),
+            None => b.clone(),
+        });
+    }
+    if report.is_clean() {
+        let mut operations = first.clone();
+        operations.extend(shifted);
+        report.merged = Some(derive_decision(
+            template,
+            base,
+            operations,
+            editorial_receipt_cid,
+            store,
+        )?);
+    }
+    Ok(report)
+}
+
+/// How an operation meets concurrent edits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Footprint<'a> {
+    /// Edits `[start, end)` ms of the timeline it applies to.
+    Range((u64, u64)),
+    /// Swaps a track ref: `[from, to]`.
+    Swap([&'a Cid; 2]),
+    /// Moves, drops or reshapes the timeline or frame.
+    Structural,
+}
+
+fn footprint(op: &EditOperation) -> Footprint<'_> {
+    match op {
+        EditOperation::Trim { .. }
+        | EditOperation::SpliceInsert { .. }
+        | EditOperation::Crop { .. }
+        | EditOperation::Reorder { .. }
+        | EditOperation::SpeedChange { .. }
+        | EditOperation::Revert { .. } => Footprint::Structural,
+        EditOperation::SwapTrackRef {
+            from_cid, to_cid, ..
+        } => Footprint::Swap([from_cid, to_cid]),
+        EditOperation::OverlayRef { from_ms, to_ms, .. } => Footprint::Range((*from_ms, *to_ms)),
+        EditOperation::MuteAudio { start_ms, end_ms }
+        | EditOperation::ReplaceAudio {
+            start_ms, end_ms, ..
+        } => Footprint::Range((*start_ms, *end_ms)),
+        EditOperation::Crossfade { at_ms, duration_ms } => {
+            Footprint::Range((*at_ms, at_ms + duration_ms))
+        }
+    }
+}
+
+/// Where `[start, end)` of `before`, the manifest structural `op` applies to, lands after
+/// it, or `None` when `op` drops or splits it. Trim, splice and reorder work on whole groups,
+/// as the engine does.
+fn shift_range<S: EditResolver + ?Sized>(
+    op: &EditOperation,
+    (start, end): (u64, u64),
+    before: &Value,
+    store: &S,
+) -> Result<Option<(u64, u64)>> {
+    let spans = group_spans(before)?;
+    let covering = |from: u64, to: u64| -> Option<(u64, u64)> {
+        let mut hit = spans.iter().filter(|g| g.0 < to && g.1 > from);
+        let first = hit.next()?;
+        Some((first.0, hit.next_back().unwrap_or(first).1))
+    };
+    Ok(match op {
+        EditOperation::Trim { start_ms, end_ms } => covering(*start_ms, *end_ms)
+            .filter(|k| start >= k.0 && end <= k.1)
+            .map(|k| (start - k.0, end - k.0)),
+        EditOperation::SpliceInsert {
+            at_ms,
+            source_manifest,
+        } => {
+            let at = spans
+                .iter()
+                .find(|g| g.0 >= *at_ms)
+                .map_or(spans.last().map_or(0, |g| g.1), |g| g.0);
+            if end <= at {
+                Some((start, end))
+            } else if start >= at {
+                let source = store
+                    .manifest(source_manifest)
+                    .with_context(|| format!("SpliceSourceNotFound({})", source_manifest))?;
+                let d = duration_ms(source)?;
+                Some((start + d, end + d))
+            } else {
+                None
+            }
+        }
+        EditOperation::Reorder { segments } => {
+            let mut offset = 0;
+            let mut out = None;
+            for seg in segments {
+                let Some(k) = covering(seg.start_ms, seg.end_ms) else {
+                    break;
+                };
+                if out.is_none() && start >= k.0 && end <= k.1 {
+                    out = Some((offset + start - k.0, offset + end - k.0));
+                }
+                offset += k.1 - k.0;
+            }
+            out
+        }
+        EditOperation::SpeedChange {
+            ratio_num,
+            ratio_den,
+        } => {
+            let (num, den) = (*ratio_num as u64, *ratio_den as u64);
+            Some((
+                start.saturating_mul(den) / num,
+                end.saturating_mul(den).div_ceil(num),
+            ))
+        }
+        EditOperation::Revert { .. } => None,
+        _ => Some((start, end)),
+    })
+}
+
+/// `[start, end)` ms of each group of `manifest`.
+fn group_spans(manifest: &Value) -> Result<Vec<(u64, u64)>> {
+    let timebase = manifest_timebase(manifest)?;
+    let gots = manifest
+        .get("gots")
+        .and_then(Value::as_array)
+        .context("ManifestMissingGots")?;
+    gots.iter()
+        .map(|g| {
+            let start: u64 = unc1::int_field(g, "start_tick")?;
+            let dur: u64 = unc1::int_field(g, "dur_ticks")?;
+            Ok((
+                ticks_to_ms(start, timebase),
+                ticks_to_ms(start.saturating_add(dur), timebase),
+            ))
+        })
+        .collect()
+}
+
+fn duration_ms(manifest: &Value) -> Result<u64> {
+    Ok(ticks_to_ms(
+        unc1::int_field(manifest, "duration_ticks")?,
+        manifest_timebase(manifest)?,
+    ))
+}
+
+fn manifest_timebase(manifest: &Value) -> Result<unc1::UncRat> {
+    unc1::timebase(
+        manifest
+            .get("timebase")
+            .context("ManifestMissingTimebase")?,
+    )
+}
+
+/// `op` with its range moved to `[start, end)`.
+fn with_range(op: &EditOperation, (start, end): (u64, u64)) -> EditOperation {
+    let mut op = op.clone();
+    match &mut op {
+        EditOperation::OverlayRef { from_ms, to_ms, .. } => (*from_ms, *to_ms) = (start, end),
+        EditOperation::MuteAudio { start_ms, end_ms }
+        | EditOperation::ReplaceAudio {
+            start_ms, end_ms, ..
+        } => (*start_ms, *end_ms) = (start, end),
+        EditOperation::Crossfade { at_ms, duration_ms } => {
+            (*at_ms, *duration_ms) = (start, end - start)
+        }
+        _ => {}
+    }
+    op
+}
+
+fn check_chain(start: &Cid, world: &str, chain: &[EditDecisionChip]) -> Result<()> {
+    let mut cur = start;
+    for (i, chip) in chain.iter().enumerate() {
+        chip.validate()?;
+        if chip.input_manifest != *cur {
+            bail!("EditChainBroken({})", i);
+        }
+        if chip.world != world {
+            bail!("EditChainWorldMismatch({})", i);
+        }
+        cur = chip
+            .output_manifest
+            .as_ref()
+            .with_context(|| format!("EditChainUnresolved({})", i))?;
+    }
+    Ok(())
+}
+
+fn output_of(chip: &EditDecisionChip) -> Result<Cid> {
+    chip.output_manifest
+        .with_context(|| format!("EditDecisionUnresolved({})", chip.input_manifest))
+}
+
+/// Build and execute a decision derived from existing ones; the resolved chip comes back sealed.
+fn derive_decision<S: EditResolver + ?Sized>(
+    template: &EditDecisionChip,
+    input: &Cid,
+    operations: Vec<EditOperation>,
+    editorial_receipt_cid: &Cid,
+    store: &S,
+) -> Result<(EditDecisionChip, EditOutcome)> {
+    let chip = EditDecisionChip {
+        chip_type: template.chip_type.clone(),
+        chip_id: None,
+        chip_ver: template.chip_ver.clone(),
+        world: template.world.clone(),
+        input_manifest: *input,
+        output_manifest: None,
+        operations,
+        reencode_required: false,
+        editorial_receipt_cid: *editorial_receipt_cid,
+    };
+    apply_edit_decision(&chip, store)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::Chip;
+    use crate::edit_engine::ManifestStore;
+    use crate::fixtures::{b3, decision, timeline};
+    use crate::unc1::int_value;
+    use serde_json::json;
+
+    fn output(chip: &EditDecisionChip) -> Cid {
+        chip.output_manifest.unwrap()
+    }
+
+    /// Apply `ops` to `input` and register the output, returning the executed decision.
+    fn step(store: &mut ManifestStore, input: &Cid, ops: Vec<EditOperation>) -> EditDecisionChip {
+        let (chip, outcome) = apply_edit_decision(&decision(*input, ops), store).unwrap();
+        store.insert(outcome.manifest).unwrap();
+        chip
+    }
+
+    fn overlay(from_ms: u64, to_ms: u64) -> EditOperation {
+        EditOperation::OverlayRef {
+            asset_cid: b3(42),
+            x: 0,
+            y: 0,
+            from_ms,
+            to_ms,
+        }
+    }
+
+    /// The manifest without `@id` and `lineage`, which name how it was produced.
+    fn without_provenance(v: &Value) -> Value {
+        let mut v = v.clone();
+        let o = v.as_object_mut().unwrap();
+        o.remove("@id");
+        o.remove("lineage");
+        v
+    }
+
+    #[test]
+    fn history_walks_paths_and_rejects_cycles() {
+        let mut store = ManifestStore::new();
+        let base = store.insert(timeline(&[0, 1, 2, 3], 45_000)).unwrap();
+        let a = step(&mut store, &base, vec![overlay(0, 500)]);
+        let b = step(&mut store, &output(&a), vec![overlay(500, 900)]);
+        let c = step(&mut store, &base, vec![overlay(1000, 1500)]);
+
+        let mut h = EditHistory::new();
+        for chip in [&a, &b, &c] {
+            h.insert(chip.clone()).unwrap();
+        }
+        let ids = |v: Vec<&EditDecisionChip>| -> Vec<Option<Cid>> {
+            v.into_iter().map(|c| c.chip_id).collect()
+        };
+        let (a_id, b_id, c_id) = (a.chip_id, b.chip_id, c.chip_id);
+        assert_eq!(ids(h.history(&output(&b))), [a_id, b_id]);
+        assert_eq!(ids(h.path(&base, &output(&b)).unwrap()), [a_id, b_id]);
+        assert!(h.path(&output(&c), &output(&b)).is_none());
+        assert_eq!(ids(h.consumers(&base)), [a_id, c_id]);
+        assert_eq!(h.common_ancestor(&output(&b), &output(&c)), Some(base));
+        assert!(h.is_ancestor(&base, &output(&b)));
+
+        let mut back = a.clone();
+        back.chip_id = None;
+        back.input_manifest = output(&b);
+        back.output_manifest = Some(base);
+        assert!(h
+            .insert(back)
+            .unwrap_err()
+            .to_string()
+            .contains("EditHistoryCycle"));
+        assert!(h.insert(a.clone()).is_err());
+    }
+
+    #[test]
+    fn squash_matches_chain_and_inverse_restores_input() {
+        let mut store = ManifestStore::new();
+        let base = store.insert(timeline(&[0, 1, 2, 3], 45_000)).unwrap();
+        let a = step(
+            &mut store,
+            &base,
+            vec![EditOperation::Trim {
+                start_ms: 500,
+                end_ms: 2000,
+            }],
+        );
+        let b = step(&mut store, &output(&a), vec![overlay(0, 500)]);
+
+        let (squashed, outcome) = squash(&[a.clone(), b.clone()], &b3(22), &store).unwrap();
+        assert_eq!(squashed.input_manifest, base);
+        assert_eq!(squashed.operations.len(), 2);
+        squashed.verify_id().unwrap();
+        let chained = store.get(&output(&b)).unwrap();
+        assert_eq!(
+            without_provenance(&outcome.manifest),
+            without_provenance(chained)
+        );
+        assert_eq!(outcome.manifest["lineage"]["parents"], json!([base]));
+        assert!(squash(&[b.clone(), a.clone()], &b3(22), &store).is_err());
+
+        let (undo, restored) = inverse(&a, &b3(23), &store).unwrap();
+        assert_eq!(undo.input_manifest, output(&a));
+        assert!(!undo.reencode_required);
+        assert_eq!(restored.manifest_cid, base);
+        assert_eq!(&restored.manifest, store.get(&base).unwrap());
+        assert_eq!(undo.output_manifest, Some(base));
+
+        // The undo edge loops back; traversals still terminate.
+        let mut h = EditHistory::new();
+        h.insert(a.clone()).unwrap();
+        h.insert(undo.clone()).unwrap();
+        assert_eq!(h.history(&base).len(), 2);
+        assert_eq!(h.ancestors(&base), BTreeSet::from([output(&a)]));
+        assert!(h
+            .insert(undo)
+            .unwrap_err()
+            .to_string()
+            .contains("DuplicateEditDecision"));
+    }
+
+    #[test]
+    fn merge_applies_disjoint_chains_and_reports_conflicts() {
+        let mut store = ManifestStore::new();
+        let base = store.insert(timeline(&[0, 1, 2, 3], 45_000)).unwrap();
+        let ours = step(&mut store, &base, vec![overlay(0, 500)]);
+        let theirs = step(
+            &mut store,
+            &base,
+            vec![EditOperation::SwapTrackRef {
+                track: "video".into(),
+                from_cid: b3(1),
+                to_cid: b3(9),
+            }],
+        );
+        let mut h = EditHistory::new();
+        h.insert(ours.clone()).unwrap();
+        h.insert(theirs.clone()).unwrap();
+
+        let clean = h
+            .merge_heads(&output(&ours), &output(&theirs), &b3(21), &store)
+            .unwrap();
+        assert!(clean.is_clean());
+        assert_eq!(clean.base, base);
+        let (chip, outcome) = clean.merged.unwrap();
+        assert_eq!(chip.operations.len(), 2);
+        assert_eq!(
+            outcome.manifest["gots"][1]["tiles"][0]["cid"],
+            b3(9).to_string()
+        );
+        assert_eq!(outcome.manifest["overlays"][0]["cid"], b3(42).to_string());
+
+        assert!(
+            merge(&base, &[ours, theirs], &[], &b3(21), &store).is_err(),
+            "chains must be linked"
+        );
+
+        let ours = step(&mut store, &base, vec![overlay(0, 500)]);
+        let theirs = step(
+            &mut store,
+            &base,
+            vec![
+                overlay(400, 800),
+                EditOperation::SwapTrackRef {
+                    track: "video".into(),
+                    from_cid: b3(0),
+                    to_cid: b3(7),
+                },
+            ],
+        );
+        let swap = step(
+            &mut store,
+            &output(&ours),
+            vec![EditOperation::SwapTrackRef {
+                track: "video".into(),
+                from_cid: b3(0),
+                to_cid: b3(6),
+            }],
+        );
+        let report = merge(&base, &[ours, swap], &[theirs], &b3(21), &store).unwrap();
+        assert!(report.merged.is_none());
+        assert_eq!(
+            report.conflicts,
+            [
+                EditConflict::OverlappingRange {
+                    ours_op: 0,
+                    theirs_op: 0,
+                    ours: (0, 500),
+                    theirs: (400, 800),
+                },
+                EditConflict::SameTrackRef {
+                    ours_op: 1,
+                    theirs_op: 1,
+                    cid: b3(0),
+                },
+            ]
+        );
+
+        // A trim that drops the group a concurrent swap targets is a conflict, not an error.
+        let trim = step(
+            &mut store,
+            &base,
+            vec![EditOperation::Trim {
+                start_ms: 500,
+                end_ms: 2000,
+            }],
+        );
+        let swap = step(
+            &mut store,
+            &base,
+            vec![EditOperation::SwapTrackRef {
+                track: "video".into(),
+                from_cid: b3(0),
+                to_cid: b3(8),
+            }],
+        );
+        let mut h = EditHistory::new();
+        h.insert(trim.clone()).unwrap();
+        h.insert(swap.clone()).unwrap();
+        let report = h
+            .merge_heads(&output(&trim), &output(&swap), &b3(21), &store)
+            .unwrap();
+        assert!(report.merged.is_none());
+        assert_eq!(
+            report.conflicts,
+            [EditConflict::RefRewritten {
+                ours_op: 0,
+                theirs_op: 0,
+                cid: b3(0),
+            }]
+        );
+    }
+
+    #[test]
+    fn merge_shifts_ranges_through_structural_edits() {
+        let mut store = ManifestStore::new();
+        let base = store.insert(timeline(&[0, 1, 2, 3], 45_000)).unwrap();
+        let trim = vec![EditOperation::Trim {
+            start_ms: 500,
+            end_ms: 2000,
+        }];
+        let swap = |from: u8, to: u8| EditOperation::SwapTrackRef {
+            track: "video".into(),
+            from_cid: b3(from),
+            to_cid: b3(to),
+        };
+        let run = |store: &mut ManifestStore, ours: Vec<EditOperation>, theirs| {
+            let ours = step(store, &base, ours);
+            let theirs = step(store, &base, theirs);
+            merge(&base, &[ours], &[theirs], &b3(21), store).unwrap()
+        };
+
+        // The trim drops the first 500 ms, so their overlay moves back by as much.
+        let report = run(&mut store, trim.clone(), vec![overlay(1000, 1500)]);
+        let (chip, outcome) = report.merged.unwrap();
+        assert_eq!(json!(chip.operations), json!([trim[0], overlay(500, 1000)]));
+        assert_eq!(
+            outcome.manifest["overlays"][0]["from_tick"],
+            int_value(45_000)
+        );
+
+        // Applied first whichever side it is on; the swapped group survives the trim.
+        let report = run(&mut store, vec![swap(2, 9)], trim.clone());
+        let (chip, outcome) = report.merged.unwrap();
+        assert_eq!(json!(chip.operations), json!([trim[0], swap(2, 9)]));
+        assert_eq!(
+            outcome.manifest["gots"][1]["tiles"][0]["cid"],
+            b3(9).to_string()
+        );
+
+        let report = run(&mut store, trim.clone(), vec![overlay(0, 600)]);
+        assert_eq!(
+            report.conflicts,
+            [EditConflict::RangeRewritten {
+                ours_op: 0,
+                theirs_op: 0,
+                range: (0, 600),
+            }]
+        );
+
+        let speed = EditOperation::SpeedChange {
+            ratio_num: 2,
+            ratio_den: 1,
+        };
+        let report = run(&mut store, trim.clone(), vec![speed]);
+        assert_eq!(
+            report.conflicts,
+            [EditConflict::BothRestructure {
+                ours_op: 0,
+                theirs_op: 0,
+            }]
+        );
+
+        // Both sides making the same swap is one conflict, not one per matching ref.
+        let report = run(&mut store, vec![swap(1, 9)], vec![swap(1, 9)]);
+        assert_eq!(
+            report.conflicts,
+            [EditConflict::SameTrackRef {
+                ours_op: 0,
+                theirs_op: 0,
+                cid: b3(1),
+            }]
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use serde_json::Value;
+
+use crate::chip::Chip;
+use crate::manifest::{AudioRef, Got, Manifest, TileRef, MANIFEST_TYPE};
+use crate::streaming_protocol::{EditDecisionChip, EditOperation};
+use crate::unc1::UncRat;
+use crate::Cid;
+
+pub const WORLD: &str = "a/demo/t/edit";
+
+pub fn b3(n: u8) -> Cid {
+    Cid::blake3([n; 32])
+}
+
+/// Manifest at 1/90000 s with one group of `dur_ticks` per entry of `groups`, its tiles laid
+/// out two per row. No audio, sidecars or lineage.
+pub fn manifest(groups: &[Vec<Cid>], dur_ticks: u64) -> Manifest {
+    let gots = groups
+        .iter()
+        .enumerate()
+        .map(|(g, tiles)| Got {
+            start_tick: g as u64 * dur_ticks,
+            dur_ticks,
+            tiles: tiles
+                .iter()
+                .enumerate()
+                .map(|(i, &cid)| TileRef {
+                    cid,
+                    mime: "application/vcx-ic0t".to_string(),
+                    role: Some("base".to_string()),
+                    tile_x: Some(i as u32 % 2),
+                    tile_y: Some(i as u32 / 2),
+                    crop_w: None,
+                    crop_h: None,
+                })
+                .collect(),
+        })
+        .collect();
+    Manifest {
+        manifest_type: MANIFEST_TYPE.to_string(),
+        id: "m:test".to_string(),
+        ver: "1.0".to_string(),
+        world: WORLD.to_string(),
+        profile: None,
+        timebase: UncRat::new(1, 90_000).unwrap(),
+        duration_ticks: groups.len() as u64 * dur_ticks,
+        video: None,
+        gots,
+        audio: None,
+        sidecars: Vec::new(),
+        overlays: Vec::new(),
+        audio_edits: Vec::new(),
+        transitions: Vec::new(),
+        source_frame_hint: None,
+        lineage: None,
+    }
+}
+
+/// The edit tests' timeline as JSON: one tile `b3(n)` per group.
+pub fn timeline(tiles: &[u8], dur_ticks: u64) -> Value {
+    let groups: Vec<Vec<Cid>> = tiles.iter().map(|&n| vec![b3(n)]).collect();
+    manifest(&groups, dur_ticks).to_json().unwrap()
+}
+
+pub fn opus(cid: Cid) -> AudioRef {
+    AudioRef {
+        codec: Some("opus".to_string()),
+        cid,
+        mime: "audio/opus".to_string(),
+    }
+}
+
+/// Unresolved decision applying `operations` to `input`.
+pub fn decision(input: Cid, operations: Vec<EditOperation>) -> EditDecisionChip {
+    EditDecisionChip {
+        chip_type: EditDecisionChip::CHIP_TYPE.into(),
+        chip_id: None,
+        chip_ver: "1.0".into(),
+        world: WORLD.into(),
+        input_manifest: input,
+        output_manifest: None,
+        operations,
+        reencode_required: false,
+        editorial_receipt_cid: b3(20),
+    }
+}
@@ -0,0 +1,214 @@
+use anyhow::{bail, Result};
+
+use crate::realtime_predictability::GroupObservation;
+use crate::realtime_sim::splitmix64;
+use crate::streaming_protocol::{RealtimeObserved, VodRegionStrategy};
+use crate::vod_predictability::{tile_change, tile_samples, IC0_HEADER_LEN};
+
+#[derive(Debug, Clone, Copy)]
+pub struct GhostConfig {
+    /// Largest per-sample noise added by `hold_and_noise`, in sample units.
+    pub max_noise: u8,
+    /// Normalized mean absolute difference (as in the VOD analyzer) at or below which a ghost
+    /// counts as correct.
+    pub tolerance: f64,
+}
+
+impl Default for GhostConfig {
+    fn default() -> Self {
+        Self {
+            max_noise: 4,
+            tolerance: 0.02,
+        }
+    }
+}
+
+/// Seed for the tile at `position` of group `group_seq`, so a ghost does not depend on the
+/// order in which a client synthesizes tiles.
+pub fn tile_seed(stream_seed: u64, group_seq: u64, position: u64) -> u64 {
+    let mut state = stream_seed ^ group_seq.rotate_left(32) ^ position;
+    splitmix64(&mut state)
+}
+
+/// Synthesize a speculative tile from `history` (previously decoded payloads of the same tile
+/// position, oldest first). Returns `None` for `download_aggressive`, which never ghosts.
+///
+/// `copy_previous` repeats the latest payload. `hold_and_noise` holds it and adds uniform
+/// integer noise of at most `max_noise`, or of the mean sample change between the last two
+/// payloads when that is smaller (at least 1, unless `max_noise` is 0, which holds the payload
+/// unchanged). IC0 headers are copied verbatim; equal inputs
+/// and seed give byte-identical ghosts.
+pub fn synthesize(
+    strategy: VodRegionStrategy,
+    history: &[&[u8]],
+    seed: u64,
+    cfg: &GhostConfig,
+) -> Result<Option<Vec<u8>>> {
+    let Some(prev) = history.last() else {
+        bail!("GhostNeedsHistory");
+    };
+    match strategy {
+        VodRegionStrategy::DownloadAggressive => Ok(None),
+        VodRegionStrategy::CopyPrevious => Ok(Some(prev.to_vec())),
+        VodRegionStrategy::HoldAndNoise => {
+            let amplitude = match history {
+                [.., older, _] => {
+                    let change = tile_change(older, prev) * 255.0;
+                    (change.round() as u8).clamp(cfg.max_noise.min(1), cfg.max_noise)
+                }
+                _ => cfg.max_noise,
+            };
+            let mut out = prev.to_vec();
+            let header = out.len() - tile_samples(prev).len();
+            debug_assert!(header == 0 || header == IC0_HEADER_LEN);
+            let span = 2 * amplitude as u64 + 1;
+            let mut state = seed;
+            for sample in &mut out[header..] {
+                let delta = (splitmix64(&mut state) % span) as i16 - amplitude as i16;
+                *sample = (*sample as i16 + delta).clamp(0, 255) as u8;
+            }
+            Ok(Some(out))
+        }
+    }
+}
+
+/// Whether `ghost` is within `tolerance` of the `actual` tile.
+pub fn ghost_matches(ghost: &[u8], actual: &[u8], tolerance: f64) -> bool {
+    tile_change(ghost, actual) <= tolerance
+}
+
+/// Per-group comparison of ghosts against the tiles that later arrived.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GhostTally {
+    pub guessed_tiles: u64,
+    pub correct_tiles: u64,
+}
+
+impl GhostTally {
+    /// Record one ghost once its real tile arrived; returns whether it was correct.
+    pub fn record(&mut self, ghost: &[u8], actual: &[u8], cfg: &GhostConfig) -> bool {
+        let ok = ghost_matches(ghost, actual, cfg.tolerance);
+        self.guessed_tiles += 1;
+        self.correct_tiles += ok as u64;
+        ok
+    }
+
+    /// Counters for the realtime sidecar; every wrong ghost was corrected.
+    pub fn observed(&self) -> RealtimeObserved {
+        RealtimeObserved {
+            guessed_tiles: self.guessed_tiles,
+            correct_tiles: self.correct_tiles,
+            corrected_tiles: self.guessed_tiles - self.correct_tiles,
+        }
+    }
+
+    /// Input for `RealtimePredictor::observe`.
+    pub fn observation(&self) -> GroupObservation {
+        GroupObservation {
+            guessed_tiles: self.guessed_tiles,
+            correct_tiles: self.correct_tiles,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ic0(fill: u8) -> Vec<u8> {
+        let mut out = b"IC0T".to_vec();
+        out.extend_from_slice(&[1, 1]);
+        out.extend_from_slice(&[0u8; 20]);
+        out.extend((0..64u8).map(|i| fill.wrapping_add(i % 4)));
+        out
+    }
+
+    #[test]
+    fn strategies_are_deterministic_and_bounded() {
+        let cfg = GhostConfig::default();
+        let (a, b) = (ic0(100), ic0(101));
+        let copy = synthesize(VodRegionStrategy::CopyPrevious, &[&a, &b], 1, &cfg).unwrap();
+        assert_eq!(copy.as_deref(), Some(&b[..]));
+        assert_eq!(
+            synthesize(VodRegionStrategy::DownloadAggressive, &[&b], 1, &cfg).unwrap(),
+            None
+        );
+        assert!(synthesize(VodRegionStrategy::CopyPrevious, &[], 1, &cfg).is_err());
+
+        let seed = tile_seed(7, 3, 5);
+        assert_ne!(seed, tile_seed(7, 3, 6));
+        let noisy = synthesize(VodRegionStrategy::HoldAndNoise, &[&b], seed, &cfg)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            noisy,
+            synthesize(VodRegionStrategy::HoldAndNoise, &[&b], seed, &cfg)
+                .unwrap()
+                .unwrap()
+        );
+        assert_ne!(
+            noisy,
+            synthesize(VodRegionStrategy::HoldAndNoise, &[&b], seed + 1, &cfg)
+                .unwrap()
+                .unwrap()
+        );
+        assert_eq!(noisy[..IC0_HEADER_LEN], b[..IC0_HEADER_LEN]);
+        assert!(noisy[IC0_HEADER_LEN..]
+            .iter()
+            .zip(&b[IC0_HEADER_LEN..])
+            .all(|(x, y)| x.abs_diff(*y) <= cfg.max_noise));
+
+        // A nearly static history narrows the noise to one sample unit.
+        let calm = synthesize(VodRegionStrategy::HoldAndNoise, &[&a, &b], seed, &cfg)
+            .unwrap()
+            .unwrap();
+        assert!(calm[IC0_HEADER_LEN..]
+            .iter()
+            .zip(&b[IC0_HEADER_LEN..])
+            .all(|(x, y)| x.abs_diff(*y) <= 1));
+
+        // No noise budget holds the payload whatever the history.
+        let still = GhostConfig {
+            max_noise: 0,
+            ..cfg
+        };
+        for history in [&[&b[..]][..], &[&a[..], &b[..]][..]] {
+            let held = synthesize(VodRegionStrategy::HoldAndNoise, history, seed, &still)
+                .unwrap()
+                .unwrap();
+            assert_eq!(held, b);
+        }
+    }
+
+    #[test]
+    fn tally_feeds_predictor_counters() {
+        let cfg = GhostConfig::default();
+        let prev = ic0(50);
+        let ghost = synthesize(VodRegionStrategy::HoldAndNoise, &[&prev], 9, &cfg)
+            .unwrap()
+            .unwrap();
+        let mut tally = GhostTally::default();
+        assert!(tally.record(&ghost, &ic0(50), &cfg));
+        assert!(tally.record(&prev, &ic0(52), &cfg));
+        assert!(!tally.record(&prev, &ic0(120), &cfg));
+        assert!(!tally.record(
+            &prev,
+            &ic0(52),
+            &GhostConfig {
+                tolerance: 0.0,
+                ..cfg
+            }
+        ));
+
+        let observed = tally.observed();
+        assert_eq!(
+            (
+                observed.guessed_tiles,
+                observed.correct_tiles,
+                observed.corrected_tiles
+            ),
+            (4, 2, 2)
+        );
+        assert_eq!(tally.observation().hit_rate(), 0.5);
+    }
+}
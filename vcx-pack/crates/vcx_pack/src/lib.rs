@@ -2,10 +2,30 @@ use anyhow::{bail, Context, Result};
 use serde_json::Value;
 use std::io::{Cursor, Read, Seek, SeekFrom, Write};
 
-use ubl_ai_nrf1::nrf::{decode_from_slice, encode_to_vec, json_to_nrf, NrfValue};
-
+use ubl_ai_nrf1::nrf::{decode_from_slice, encode_to_vec, NrfValue};
+
+pub mod chip;
+pub mod cid;
+pub mod edit_engine;
+pub mod edit_history;
+/// Manifests and decisions shared by unit tests here and in the tools (feature `fixtures`).
+#[cfg(any(test, feature = "fixtures"))]
+pub mod fixtures;
+pub mod ghost_tile;
+pub mod lineage;
+pub mod manifest;
+pub mod nrf_json;
+pub mod playback_sim;
+pub mod prefetch;
 pub mod realtime_predictability;
+pub mod realtime_sim;
+pub mod redaction;
 pub mod streaming_protocol;
+pub mod tile_index;
+pub mod unc1;
+pub mod vod_predictability;
+
+pub use cid::{B3Digest, Cid, CidAlgo};
 
 pub const PACK_MAGIC: &[u8; 4] = b"VCX1";
 pub const MERKLE_MAGIC: &[u8; 4] = b"VMRK";
@@ -15,6 +35,12 @@ pub const PACK_VERSION: u16 = 1;
 pub const INDEX_VERSION: u16 = 1;
 pub const MERKLE_VERSION: u16 = 1;
 
+/// Index entry flag: the payload bytes were zeroed by [`redaction::redact_pack`].
+pub const INDEX_FLAG_REDACTED: u16 = 0x0001;
+
+/// magic[4] + ver u16 + entry_len u16 + count u32 + reserved u32
+const INDEX_HEADER_LEN: usize = 16;
+
 /// Header is always 96 bytes, little-endian.
 #[derive(Debug, Clone)]
 pub struct PackHeader {
@@ -150,7 +176,7 @@ impl MimeTag {
 /// Index header + entries are binary and deterministic.
 #[derive(Debug, Clone)]
 pub struct IndexEntry {
-    pub cid: [u8; 32], // BLAKE3 hash bytes
+    pub cid: Cid,
     pub mime_tag: MimeTag,
     pub flags: u16,
     pub payload_off: u64, // absolute file offset
@@ -160,12 +186,18 @@ pub struct IndexEntry {
 
 impl IndexEntry {
     pub const LEN: usize = 96;
+    /// Byte offset of `flags` within an encoded entry.
+    const FLAGS_OFFSET: usize = 1 + 1 + 32 + 2;
+
+    pub fn is_redacted(&self) -> bool {
+        self.flags & INDEX_FLAG_REDACTED != 0
+    }
 
     pub fn write_to<W: Write>(&self, mut w: W) -> Result<()> {
         // cid algo + len
-        w.write_all(&[1u8])?; // cid_algo: 1 = BLAKE3(NRF(Bytes(payload)))
-        w.write_all(&[32u8])?;
-        w.write_all(&self.cid)?;
+        w.write_all(&[self.cid.algo().code()])?; // cid_algo: 1 = BLAKE3(NRF(Bytes(payload)))
+        w.write_all(&[Cid::DIGEST_LEN as u8])?;
+        w.write_all(self.cid.digest())?;
         w.write_all(&(self.mime_tag as u16).to_le_bytes())?;
         w.write_all(&self.flags.to_le_bytes())?;
         w.write_all(&self.payload_off.to_le_bytes())?;
@@ -181,17 +213,15 @@ impl IndexEntry {
     pub fn read_from<R: Read>(mut r: R) -> Result<Self> {
         let mut b1 = [0u8; 1];
         r.read_exact(&mut b1)?;
-        let cid_algo = b1[0];
-        if cid_algo != 1 {
-            bail!("UnsupportedCidAlgo({})", cid_algo);
-        }
+        let cid_algo = CidAlgo::from_code(b1[0])?;
         r.read_exact(&mut b1)?;
         let cid_len = b1[0];
-        if cid_len != 32 {
+        if cid_len as usize != Cid::DIGEST_LEN {
             bail!("BadCidLen({})", cid_len);
         }
-        let mut cid = [0u8; 32];
-        r.read_exact(&mut cid)?;
+        let mut digest = [0u8; 32];
+        r.read_exact(&mut digest)?;
+        let cid = Cid::new(cid_algo, digest);
         let mut u16b = [0u8; 2];
         r.read_exact(&mut u16b)?;
         let mime_tag_u = u16::from_le_bytes(u16b);
@@ -204,6 +234,9 @@ impl IndexEntry {
         };
         r.read_exact(&mut u16b)?;
         let flags = u16::from_le_bytes(u16b);
+        if flags & !INDEX_FLAG_REDACTED != 0 {
+            bail!("UnsupportedIndexEntryFlags({:#06x})", flags);
+        }
         let mut u64b = [0u8; 8];
         r.read_exact(&mut u64b)?;
         let payload_off = u64::from_le_bytes(u64b);
@@ -238,6 +271,36 @@ pub struct Pack {
     pub merkle: MerkleTrailer,
 }
 
+impl Pack {
+    /// Reference-level verification: decode the manifest with its typed schema and
+    /// cross-check its payload references against the index. Reads no payload bytes.
+    pub fn verify_references(&self) -> Result<manifest::ReferenceReport> {
+        let manifest = manifest::Manifest::from_nrf(&self.manifest_bytes)?;
+        Ok(manifest::check_references(&manifest, &self.index_entries))
+    }
+
+    /// Look up an index entry by CID (the index is sorted by CID bytes).
+    pub fn find_entry(&self, cid: &Cid) -> Option<&IndexEntry> {
+        self.index_entries
+            .binary_search_by(|e| e.cid.cmp(cid))
+            .ok()
+            .map(|i| &self.index_entries[i])
+    }
+
+    /// Entries whose payload has been tombstoned, in index order.
+    pub fn redacted_entries(&self) -> impl Iterator<Item = &IndexEntry> {
+        self.index_entries.iter().filter(|e| e.is_redacted())
+    }
+}
+
+/// Read the raw bytes of a single payload entry from a pack.
+pub fn read_payload<R: Read + Seek>(mut r: R, entry: &IndexEntry) -> Result<Vec<u8>> {
+    r.seek(SeekFrom::Start(entry.payload_off))?;
+    let mut buf = vec![0u8; entry.payload_len as usize];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
 /// Merkle trailer stores full tree levels so proofs can be derived.
 #[derive(Debug, Clone)]
 pub struct MerkleTrailer {
@@ -330,68 +393,25 @@ fn align8(x: u64) -> u64 {
     (x + 7) & !7
 }
 
-/// Compute CID bytes for a structured JSON value: cid = BLAKE3(NRF-1.1(value)).
-pub fn cid_for_json_value(value: &Value) -> Result<([u8; 32], String, Vec<u8>)> {
-    let nrf = json_to_nrf(value)?;
-    let nrf_bytes = encode_to_vec(&nrf)?;
+/// Compute the CID of a structured JSON value: cid = BLAKE3(NRF-1.1(value)), mapped with
+/// [`nrf_json::json_to_nrf`]. Also returns the NRF bytes.
+pub fn cid_for_json_value(value: &Value) -> Result<(Cid, Vec<u8>)> {
+    let nrf_bytes = nrf_json::encode_json(value)?;
     let h = blake3::hash(&nrf_bytes);
-    Ok((
-        h.as_bytes().clone(),
-        format!("b3:{}", hex::encode(h.as_bytes())),
-        nrf_bytes,
-    ))
+    Ok((Cid::blake3(*h.as_bytes()), nrf_bytes))
 }
 
-/// Compute payload CID bytes: cid = BLAKE3(NRF-1.1(Bytes(payload_raw))).
-pub fn cid_for_payload_bytes(payload_raw: &[u8]) -> Result<([u8; 32], String)> {
+/// Compute a payload CID: cid = BLAKE3(NRF-1.1(Bytes(payload_raw))).
+pub fn cid_for_payload_bytes(payload_raw: &[u8]) -> Result<Cid> {
     let nrf = NrfValue::Bytes(payload_raw.to_vec());
     let nrf_bytes = encode_to_vec(&nrf)?;
-    let h = blake3::hash(&nrf_bytes);
-    Ok((
-        h.as_bytes().clone(),
-        format!("b3:{}", hex::encode(h.as_bytes())),
-    ))
+    Ok(Cid::blake3(*blake3::hash(&nrf_bytes).as_bytes()))
 }
 
-/// Strict UNC-1 mode: reject any JSON numbers anywhere in the manifest.
-/// This forces using {"@num": ...} consistently.
-
+/// Strict UNC-1 mode: reject any JSON numbers anywhere in the manifest and any malformed or
+/// non-canonical `{"@num": ...}` object (see [`unc1::validate`]).
 pub fn validate_no_json_numbers(value: &Value) -> Result<()> {
-    fn walk(v: &Value, path: &mut Vec<String>, allow_dec_scale_here: bool) -> Result<()> {
-        match v {
-            Value::Number(n) => {
-                // Only allowed in a single, very specific place: UNC-1 dec/1 scale field `s`.
-                if allow_dec_scale_here && n.is_i64() {
-                    return Ok(());
-                }
-                bail!("ManifestHasJsonNumber at {}", path.join("."));
-            }
-            Value::Array(a) => {
-                for (i, x) in a.iter().enumerate() {
-                    path.push(format!("[{}]", i));
-                    walk(x, path, allow_dec_scale_here)?;
-                    path.pop();
-                }
-            }
-            Value::Object(o) => {
-                // Detect UNC-1 objects: {"@num": "<tag>", ...}
-                let is_dec = match o.get("@num") {
-                    Some(Value::String(tag)) if tag == "dec/1" => true,
-                    _ => false,
-                };
-                for (k, x) in o.iter() {
-                    path.push(k.clone());
-                    let allow_here = is_dec && k == "s";
-                    walk(x, path, allow_here)?;
-                    path.pop();
-                }
-            }
-            _ => {}
-        }
-        Ok(())
-    }
-    let mut path = vec!["$".to_string()];
-    walk(value, &mut path, false)
+    unc1::validate(value)
 }
 
 /// Ensure the manifest is a UBL chip (envelope anchors present), because VCX-PACK is meant
@@ -426,16 +446,15 @@ pub fn build_pack<W: Write + Seek>(
     }
 
     // Manifest bytes (NRF-1.1)
-    let (_manifest_cid_bytes, _manifest_cid_str, manifest_bytes) =
-        cid_for_json_value(manifest_json)?;
+    let (_manifest_cid, manifest_bytes) = cid_for_json_value(manifest_json)?;
 
     // Prepare entries (compute CIDs and raw payload hashes)
     let mut entries: Vec<(IndexEntry, Vec<u8>)> = Vec::with_capacity(payloads.len());
     for (mime_tag, bytes) in payloads {
-        let (cid_bytes, _cid_str) = cid_for_payload_bytes(&bytes)?;
+        let cid = cid_for_payload_bytes(&bytes)?;
         let payload_hash = blake3::hash(&bytes).as_bytes().clone();
         let e = IndexEntry {
-            cid: cid_bytes,
+            cid,
             mime_tag,
             flags: 0,
             payload_off: 0,
@@ -455,8 +474,7 @@ pub fn build_pack<W: Write + Seek>(
 
     // We'll build index after payload offsets are known.
     // Pre-compute index length: fixed header + N entries.
-    let index_header_len = 16; // magic[4] + ver u16 + entry_len u16 + count u32 + reserved u32
-    let index_len = align8((index_header_len + entries.len() * IndexEntry::LEN) as u64);
+    let index_len = align8((INDEX_HEADER_LEN + entries.len() * IndexEntry::LEN) as u64);
     let payload_off = align8(index_off + index_len);
 
     // Assign payload offsets sequentially.
@@ -484,7 +502,7 @@ pub fn build_pack<W: Write + Seek>(
     }
 
     // Build Merkle tree (leaves commit to manifest+index+payload hashes)
-    let index_hash = blake3::hash(&index_bytes).as_bytes().clone();
+    let index_hash = index_leaf_hash(&index_bytes);
     let manifest_hash = blake3::hash(&manifest_bytes).as_bytes().clone();
 
     let mut leaves: Vec<[u8; 32]> = Vec::with_capacity(2 + entries.len());
@@ -501,7 +519,7 @@ pub fn build_pack<W: Write + Seek>(
             2,
             &e.payload_hash,
             e.payload_len,
-            Some(&e.cid),
+            Some(e.cid.digest()),
         ));
     }
 
@@ -579,6 +597,18 @@ pub fn build_pack<W: Write + Seek>(
     Ok(header)
 }
 
+/// Hash the index leaf commits to: the index bytes with [`INDEX_FLAG_REDACTED`] cleared, so
+/// redacting a payload leaves the root unchanged.
+fn index_leaf_hash(index_bytes: &[u8]) -> [u8; 32] {
+    let mut committed = index_bytes.to_vec();
+    let count = u32::from_le_bytes(index_bytes[8..12].try_into().unwrap()) as usize;
+    for i in 0..count {
+        committed[INDEX_HEADER_LEN + i * IndexEntry::LEN + IndexEntry::FLAGS_OFFSET] &=
+            !(INDEX_FLAG_REDACTED as u8);
+    }
+    *blake3::hash(&committed).as_bytes()
+}
+
 fn hash_leaf(
     i: u32,
     kind: u8,
@@ -671,7 +701,24 @@ fn validate_merkle_shape(levels: &[Vec<[u8; 32]>], expected_leaves: usize) -> Re
 }
 
 /// Read and minimally verify a pack. If `full` is true, recompute payload hashes from bytes.
-pub fn read_and_verify_pack<R: Read + Seek>(mut r: R, full: bool) -> Result<Pack> {
+/// Packs with redacted payloads are rejected; see [`read_and_verify_redacted_pack`].
+pub fn read_and_verify_pack<R: Read + Seek>(r: R, full: bool) -> Result<Pack> {
+    let pack = verify_pack(r, full)?;
+    let redacted = pack.redacted_entries().count();
+    if redacted > 0 {
+        bail!("PackHasRedactions({})", redacted);
+    }
+    Ok(pack)
+}
+
+/// [`read_and_verify_pack`] that also accepts redacted payloads; list them with
+/// [`Pack::redacted_entries`]. With `full`, their bytes must be all zero in place of the
+/// hash check. The Merkle root is the one the pack had before redaction.
+pub fn read_and_verify_redacted_pack<R: Read + Seek>(r: R, full: bool) -> Result<Pack> {
+    verify_pack(r, full)
+}
+
+fn verify_pack<R: Read + Seek>(mut r: R, full: bool) -> Result<Pack> {
     r.seek(SeekFrom::Start(0))?;
     let header = PackHeader::read_from(&mut r)?;
     if (header.flags & 0b0010) == 0 {
@@ -776,12 +823,17 @@ pub fn read_and_verify_pack<R: Read + Seek>(mut r: R, full: bool) -> Result<Pack
             r.seek(SeekFrom::Start(e.payload_off))?;
             let mut buf = vec![0u8; e.payload_len as usize];
             r.read_exact(&mut buf)?;
+            if e.is_redacted() {
+                if buf.iter().any(|b| *b != 0) {
+                    bail!("RedactedPayloadNotZeroed({})", e.cid);
+                }
+                continue;
+            }
             let h = blake3::hash(&buf).as_bytes().clone();
             if h != e.payload_hash {
                 bail!("PayloadHashMismatch");
             }
-            let (cid_bytes, _cid_str) = cid_for_payload_bytes(&buf)?;
-            if cid_bytes != e.cid {
+            if cid_for_payload_bytes(&buf)? != e.cid {
                 bail!("PayloadCidMismatch");
             }
         }
@@ -789,7 +841,7 @@ pub fn read_and_verify_pack<R: Read + Seek>(mut r: R, full: bool) -> Result<Pack
 
     // recompute merkle root from manifest/index and either stored hashes or recomputed.
     let manifest_hash = blake3::hash(&manifest_bytes).as_bytes().clone();
-    let index_hash = blake3::hash(&index_bytes).as_bytes().clone();
+    let index_hash = index_leaf_hash(&index_bytes);
     let mut leaves = Vec::with_capacity(2 + entries.len());
     leaves.push(hash_leaf(0, 0, &manifest_hash, header.manifest_len, None));
     leaves.push(hash_leaf(1, 1, &index_hash, header.index_len, None));
@@ -800,7 +852,7 @@ pub fn read_and_verify_pack<R: Read + Seek>(mut r: R, full: bool) -> Result<Pack
             2,
             &e.payload_hash,
             e.payload_len,
-            Some(&e.cid),
+            Some(e.cid.digest()),
         ));
     }
     validate_merkle_shape(&merkle.levels, leaves.len())?;
@@ -819,7 +871,7 @@ pub fn read_and_verify_pack<R: Read + Seek>(mut r: R, full: bool) -> Result<Pack
 }
 
 fn parse_index(buf: &[u8]) -> Result<(Vec<IndexEntry>, usize)> {
-    if buf.len() < 16 {
+    if buf.len() < INDEX_HEADER_LEN {
         bail!("IndexTooShort");
     }
     if &buf[0..4] != INDEX_MAGIC {
@@ -839,7 +891,7 @@ fn parse_index(buf: &[u8]) -> Result<(Vec<IndexEntry>, usize)> {
         bail!("NonZeroIndexReserved");
     }
     let mut entries = Vec::with_capacity(count);
-    let mut pos = 16usize;
+    let mut pos = INDEX_HEADER_LEN;
     for _ in 0..count {
         if pos + entry_len > buf.len() {
             bail!("IndexTruncated");
@@ -851,23 +903,6 @@ fn parse_index(buf: &[u8]) -> Result<(Vec<IndexEntry>, usize)> {
     Ok((entries, pos))
 }
 
-/// Convert CID string "b3:<hex>" to bytes.
-pub fn cid_str_to_bytes(cid: &str) -> Result<[u8; 32]> {
-    let s = cid.strip_prefix("b3:").context("CidMustStartWithB3")?;
-    let raw = hex::decode(s).context("BadCidHex")?;
-    if raw.len() != 32 {
-        bail!("BadCidLenBytes({})", raw.len());
-    }
-    let mut out = [0u8; 32];
-    out.copy_from_slice(&raw);
-    Ok(out)
-}
-
-/// Convert CID bytes to string "b3:<hex>".
-pub fn cid_bytes_to_str(cid: &[u8; 32]) -> String {
-    format!("b3:{}", hex::encode(cid))
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
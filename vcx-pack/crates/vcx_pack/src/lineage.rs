@@ -0,0 +1,369 @@
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::edit_engine::{apply_edit_decision, edit_parents, EditResolver};
+use crate::manifest::{Lineage, LineageReason};
+use crate::unc1::{self, UncRat};
+use crate::{cid_for_json_value, Cid};
+
+/// A single way a manifest's lineage does not check out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineageIssue {
+    Malformed {
+        manifest: Cid,
+        error: String,
+    },
+    ParentUnresolved {
+        manifest: Cid,
+        parent: Cid,
+    },
+    DecisionUnresolved {
+        manifest: Cid,
+        decision: Cid,
+    },
+    /// An `edit` link without an `edit_decision` cannot be replayed.
+    EditUnverified {
+        manifest: Cid,
+    },
+    /// The manifest is not what its stated derivation makes of its parents.
+    Inconsistent {
+        manifest: Cid,
+        detail: String,
+    },
+}
+
+impl fmt::Display for LineageIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed { manifest, error } => {
+                write!(f, "LineageMalformed({}: {})", manifest, error)
+            }
+            Self::ParentUnresolved { manifest, parent } => {
+                write!(f, "ParentUnresolved({} -> {})", manifest, parent)
+            }
+            Self::DecisionUnresolved { manifest, decision } => {
+                write!(f, "EditDecisionUnresolved({} -> {})", manifest, decision)
+            }
+            Self::EditUnverified { manifest } => write!(f, "EditDecisionMissing({})", manifest),
+            Self::Inconsistent { manifest, detail } => {
+                write!(f, "LineageInconsistent({}: {})", manifest, detail)
+            }
+        }
+    }
+}
+
+/// Result of walking a manifest's lineage.
+#[derive(Debug, Clone, Default)]
+pub struct LineageReport {
+    /// Every manifest reached, the checked one first.
+    pub visited: Vec<Cid>,
+    /// Reached manifests where the chain ends: no `lineage`, or an `encode` one.
+    pub roots: Vec<Cid>,
+    pub issues: Vec<LineageIssue>,
+}
+
+impl LineageReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Walk the lineage of `manifest` through `store`. Every parent and edit decision must
+/// resolve, and each link must match its reason: an edit names a decision whose input and
+/// splice/revert sources are the parents and which replays to exactly the child, a world override differs from its parent only in `@world` (and `lineage`), and a
+/// re-encode keeps the parent's duration. Each manifest is checked once.
+pub fn verify_lineage<S: EditResolver + ?Sized>(
+    manifest: &Value,
+    store: &S,
+) -> Result<LineageReport> {
+    let (cid, _nrf) = cid_for_json_value(manifest)?;
+    let mut report = LineageReport::default();
+    let mut seen = BTreeSet::from([cid]);
+    let mut pending = vec![(cid, manifest)];
+    while let Some((cid, child)) = pending.pop() {
+        report.visited.push(cid);
+        let Some(raw) = child.get("lineage") else {
+            report.roots.push(cid);
+            continue;
+        };
+        let lineage = match Lineage::deserialize(raw)
+            .map_err(anyhow::Error::from)
+            .and_then(|l| l.validate().map(|()| l))
+        {
+            Ok(l) => l,
+            Err(e) => {
+                report.issues.push(LineageIssue::Malformed {
+                    manifest: cid,
+                    error: e.to_string(),
+                });
+                continue;
+            }
+        };
+        if lineage.reason == LineageReason::Encode {
+            report.roots.push(cid);
+        }
+
+        let mut parents = Vec::with_capacity(lineage.parents.len());
+        for parent in &lineage.parents {
+            match store.manifest(parent) {
+                Some(v) => parents.push((*parent, v)),
+                None => report.issues.push(LineageIssue::ParentUnresolved {
+                    manifest: cid,
+                    parent: *parent,
+                }),
+            }
+        }
+        // Depth-first in parent order.
+        for &(parent, v) in parents.iter().rev() {
+            if seen.insert(parent) {
+                pending.push((parent, v));
+            }
+        }
+        if parents.len() < lineage.parents.len() {
+            continue;
+        }
+
+        let inconsistent = |detail: String| LineageIssue::Inconsistent {
+            manifest: cid,
+            detail,
+        };
+        let issue = match lineage.reason {
+            LineageReason::Encode => None,
+            LineageReason::Edit => match lineage.edit_decision {
+                Some(decision) => check_edit(cid, decision, &lineage, store),
+                None => Some(LineageIssue::EditUnverified { manifest: cid }),
+            },
+            LineageReason::WorldOverride => (strip(child) != strip(parents[0].1))
+                .then(|| inconsistent("world override changes more than @world".to_string())),
+            LineageReason::Reencode => match (duration(child), duration(parents[0].1)) {
+                (Some(a), Some(b)) if a == b => None,
+                (Some(a), Some(b)) => Some(inconsistent(format!(
+                    "re-encode duration {}s != parent {}s",
+                    a, b
+                ))),
+                _ => Some(inconsistent("re-encode duration unreadable".to_string())),
+            },
+        };
+        report.issues.extend(issue);
+    }
+    Ok(report)
+}
+
+/// Replay the edit decision and compare with the manifest it claims to have produced.
+fn check_edit<S: EditResolver + ?Sized>(
+    cid: Cid,
+    decision: Cid,
+    lineage: &Lineage,
+    store: &S,
+) -> Option<LineageIssue> {
+    let Some(chip) = store.edit_decision(&decision) else {
+        return Some(LineageIssue::DecisionUnresolved {
+            manifest: cid,
+            decision,
+        });
+    };
+    let expected = edit_parents(&chip.input_manifest, &chip.operations);
+    let detail = if lineage.parents.first() != Some(&chip.input_manifest) {
+        format!("edit input {} is not the first parent", chip.input_manifest)
+    } else if lineage.parents != expected {
+        let list = |p: &[Cid]| p.iter().map(Cid::to_string).collect::<Vec<_>>().join(", ");
+        format!(
+            "edit parents [{}] are not the decision's sources [{}]",
+            list(&lineage.parents),
+            list(&expected)
+        )
+    } else {
+        match apply_edit_decision(chip, store) {
+            Ok((_, outcome)) if outcome.manifest_cid == cid => return None,
+            Ok((_, outcome)) => format!("edit replays to {}", outcome.manifest_cid),
+            Err(e) => format!("edit replay failed: {}", e),
+        }
+    };
+    Some(LineageIssue::Inconsistent {
+        manifest: cid,
+        detail,
+    })
+}
+
+/// The manifest without the fields a world override may change.
+fn strip(manifest: &Value) -> Value {
+    let mut out = manifest.clone();
+    if let Some(o) = out.as_object_mut() {
+        o.remove("@world");
+        o.remove("lineage");
+    }
+    out
+}
+
+/// Duration in seconds.
+fn duration(manifest: &Value) -> Option<UncRat> {
+    let ticks = unc1::int_field::<u64>(manifest, "duration_ticks").ok()?;
+    let timebase = unc1::timebase(manifest.get("timebase")?).ok()?;
+    UncRat::from_int(ticks as i128).checked_mul(timebase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edit_engine::ManifestStore;
+    use crate::fixtures::{decision, timeline};
+    use crate::streaming_protocol::{EditDecisionChip, EditOperation};
+    use crate::unc1::int_value;
+    use serde_json::json;
+
+    fn base() -> Value {
+        timeline(&[0, 1, 2], 45_000)
+    }
+
+    fn trim(input: Cid) -> EditDecisionChip {
+        decision(
+            input,
+            vec![EditOperation::Trim {
+                start_ms: 0,
+                end_ms: 1000,
+            }],
+        )
+    }
+
+    fn world_override(parent: &Value, world: &str) -> Value {
+        let mut child = parent.clone();
+        child["@world"] = json!(world);
+        child["lineage"] = json!({
+            "reason": "world_override",
+            "parents": [cid_for_json_value(parent).unwrap().0]
+        });
+        child
+    }
+
+    #[test]
+    fn chain_through_edit_and_world_override_verifies() {
+        let mut store = ManifestStore::new();
+        let base_cid = store.insert(base()).unwrap();
+        let (resolved, edited) = apply_edit_decision(&trim(base_cid), &store).unwrap();
+        let decision = store.insert_edit_decision(resolved.clone()).unwrap();
+        assert_eq!(decision, trim(base_cid).decision_cid().unwrap());
+        assert_eq!(edited.manifest["lineage"]["edit_decision"], json!(decision));
+        store.insert(edited.manifest.clone()).unwrap();
+
+        let moved = world_override(&edited.manifest, "a/other/t/prod");
+        let report = verify_lineage(&moved, &store).unwrap();
+        assert!(report.is_ok(), "{:?}", report.issues);
+        assert_eq!(report.visited.len(), 3);
+        assert_eq!(report.visited[1], edited.manifest_cid);
+        assert_eq!(report.roots, [base_cid]);
+
+        // A world override that changes anything else.
+        let mut bad = moved.clone();
+        bad["@id"] = json!("m:other");
+        let report = verify_lineage(&bad, &store).unwrap();
+        assert!(matches!(
+            &report.issues[..],
+            [LineageIssue::Inconsistent { detail, .. }] if detail.contains("@world")
+        ));
+
+        // An edited manifest the decision does not replay to.
+        let mut forged = edited.manifest.clone();
+        forged["gots"][1]["tiles"][0]["cid"] = json!(Cid::blake3([7; 32]));
+        let report = verify_lineage(&forged, &store).unwrap();
+        assert!(matches!(
+            &report.issues[..],
+            [LineageIssue::Inconsistent { detail, .. }] if detail.starts_with("edit replays to")
+        ));
+
+        // An extra parent the decision does not splice or revert from.
+        let mut other = base();
+        other["@id"] = json!("m:other");
+        let other_cid = store.insert(other).unwrap();
+        let mut grafted = edited.manifest.clone();
+        grafted["lineage"]["parents"] = json!([base_cid, other_cid]);
+        let report = verify_lineage(&grafted, &store).unwrap();
+        assert!(matches!(
+            &report.issues[..],
+            [LineageIssue::Inconsistent { detail, .. }] if detail.contains("decision's sources")
+        ));
+
+        // An edit that names no decision cannot be replayed.
+        let mut unnamed = edited.manifest.clone();
+        unnamed["lineage"]
+            .as_object_mut()
+            .unwrap()
+            .remove("edit_decision");
+        let report = verify_lineage(&unnamed, &store).unwrap();
+        assert_eq!(
+            report.issues,
+            [LineageIssue::EditUnverified {
+                manifest: cid_for_json_value(&unnamed).unwrap().0
+            }]
+        );
+
+        // Links that do not resolve.
+        let mut bare = ManifestStore::new();
+        bare.insert(base()).unwrap();
+        let report = verify_lineage(&edited.manifest, &bare).unwrap();
+        assert_eq!(
+            report.issues,
+            [LineageIssue::DecisionUnresolved {
+                manifest: edited.manifest_cid,
+                decision
+            }]
+        );
+        let report = verify_lineage(&moved, &bare).unwrap();
+        assert_eq!(
+            report.issues,
+            [LineageIssue::ParentUnresolved {
+                manifest: cid_for_json_value(&moved).unwrap().0,
+                parent: edited.manifest_cid
+            }]
+        );
+    }
+
+    #[test]
+    fn lineage_shape_and_reencode_duration_are_checked() {
+        let mut store = ManifestStore::new();
+        let base_cid = store.insert(base()).unwrap();
+
+        let mut malformed = base();
+        malformed["lineage"] = json!({"reason": "world_override"});
+        let report = verify_lineage(&malformed, &store).unwrap();
+        assert!(matches!(
+            &report.issues[..],
+            [LineageIssue::Malformed { .. }]
+        ));
+
+        // An encode is a root but must say what it read and which encoder ran.
+        let mut encoded = base();
+        encoded["lineage"] = json!({"reason": "encode"});
+        let report = verify_lineage(&encoded, &store).unwrap();
+        assert!(matches!(
+            &report.issues[..],
+            [LineageIssue::Malformed { error, .. }] if error.contains("LineageMissingSource")
+        ));
+        encoded["lineage"]["source"] = json!({
+            "hash_b3": crate::B3Digest([3; 32]),
+            "encoder": {"name": "vcx_enc_cli", "version": "0.1.0", "profile": "p"}
+        });
+        let report = verify_lineage(&encoded, &store).unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.roots, [cid_for_json_value(&encoded).unwrap().0]);
+
+        // Same duration at another timebase is a consistent re-encode; a shorter one is not.
+        let mut reencoded = base();
+        reencoded["timebase"] = json!(UncRat::new(1, 1000).unwrap());
+        reencoded["duration_ticks"] = int_value(1500);
+        reencoded["lineage"] = json!({"reason": "reencode", "parents": [base_cid]});
+        assert!(verify_lineage(&reencoded, &store).unwrap().is_ok());
+        reencoded["duration_ticks"] = int_value(1000);
+        let report = verify_lineage(&reencoded, &store).unwrap();
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(
+            report.issues[0].to_string(),
+            format!(
+                "LineageInconsistent({}: re-encode duration 1/1s != parent 3/2s)",
+                cid_for_json_value(&reencoded).unwrap().0
+            )
+        );
+    }
+}
@@ -0,0 +1,750 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use crate::nrf_json::decode_json;
+use crate::streaming_protocol::ReceiptEncoder;
+use crate::unc1::{self, UncRat};
+use crate::{cid_for_json_value, validate_no_json_numbers, B3Digest, Cid, IndexEntry, MimeTag};
+
+pub const MANIFEST_TYPE: &str = "vcx/manifest";
+
+/// `vcx/manifest`: the timeline of a pack. Unknown fields and non-canonical numbers are
+/// rejected so that a manifest read and written back encodes to the same NRF bytes (and CID).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    #[serde(rename = "@type")]
+    pub manifest_type: String,
+    #[serde(rename = "@id")]
+    pub id: String,
+    #[serde(rename = "@ver")]
+    pub ver: String,
+    #[serde(rename = "@world")]
+    pub world: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// Seconds per tick.
+    pub timebase: UncRat,
+    #[serde(with = "unc1::int")]
+    pub duration_ticks: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video: Option<VideoInfo>,
+    pub gots: Vec<Got>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio: Option<AudioRef>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sidecars: Vec<SidecarRef>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overlays: Vec<OverlayRef>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub audio_edits: Vec<AudioEdit>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transitions: Vec<Transition>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "unc1::opt_int"
+    )]
+    pub source_frame_hint: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lineage: Option<Lineage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VideoInfo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub codec: Option<String>,
+    #[serde(with = "unc1::int")]
+    pub width: u32,
+    #[serde(with = "unc1::int")]
+    pub height: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fps: Option<UncRat>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "unc1::opt_int"
+    )]
+    pub frames: Option<u64>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "unc1::opt_int"
+    )]
+    pub tile_size: Option<u32>,
+}
+
+/// Group of tiles (GoT) covering `[start_tick, start_tick + dur_ticks)`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Got {
+    #[serde(with = "unc1::int")]
+    pub start_tick: u64,
+    #[serde(with = "unc1::int")]
+    pub dur_ticks: u64,
+    pub tiles: Vec<TileRef>,
+}
+
+/// Tile payload reference. Grid coordinates and crop sizes are `int/1`; tiles without
+/// coordinates are addressed by position.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TileRef {
+    pub cid: Cid,
+    pub mime: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "unc1::opt_int"
+    )]
+    pub tile_x: Option<u32>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "unc1::opt_int"
+    )]
+    pub tile_y: Option<u32>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "unc1::opt_int"
+    )]
+    pub crop_w: Option<u32>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "unc1::opt_int"
+    )]
+    pub crop_h: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AudioRef {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub codec: Option<String>,
+    pub cid: Cid,
+    pub mime: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SidecarRef {
+    pub cid: Cid,
+    pub mime: String,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub sidecar_type: Option<String>,
+}
+
+/// Written by `overlay_ref` edits.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OverlayRef {
+    pub cid: Cid,
+    #[serde(with = "unc1::int")]
+    pub x: i64,
+    #[serde(with = "unc1::int")]
+    pub y: i64,
+    #[serde(with = "unc1::int")]
+    pub from_tick: u64,
+    #[serde(with = "unc1::int")]
+    pub to_tick: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioEditKind {
+    Mute,
+    Replace,
+}
+
+/// Written by `mute_audio` and `replace_audio` edits; `cid` is the replacement audio.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AudioEdit {
+    pub kind: AudioEditKind,
+    #[serde(with = "unc1::int")]
+    pub from_tick: u64,
+    #[serde(with = "unc1::int")]
+    pub to_tick: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cid: Option<Cid>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransitionKind {
+    Crossfade,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineageReason {
+    /// Encoded from source media; no parent manifest.
+    Encode,
+    /// Re-encoded from one parent over the same timeline.
+    Reencode,
+    /// Produced by an edit decision from its input (first parent) and splice sources.
+    Edit,
+    /// The one parent with only `@world` changed.
+    WorldOverride,
+}
+
+/// Where a manifest came from. `edit_decision` is the decision CID of the `vcx/edit.decision`
+/// chip (see `EditDecisionChip::decision_cid`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Lineage {
+    pub reason: LineageReason,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parents: Vec<Cid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edit_decision: Option<Cid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<LineageSource>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// What an encoder read and which build ran; required for `encode`, allowed for `reencode`.
+/// These are the build receipt's `input.hash_b3` and `encoder`: the receipt names the
+/// manifest, so the manifest cannot name the receipt.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LineageSource {
+    /// BLAKE3 of the raw source file bytes.
+    pub hash_b3: B3Digest,
+    pub encoder: ReceiptEncoder,
+}
+
+impl Lineage {
+    /// Parent count and derivation references allowed by `reason`.
+    pub fn validate(&self) -> Result<()> {
+        let parents_ok = match self.reason {
+            LineageReason::Encode => self.parents.is_empty(),
+            LineageReason::Edit => !self.parents.is_empty(),
+            LineageReason::Reencode | LineageReason::WorldOverride => self.parents.len() == 1,
+        };
+        if !parents_ok {
+            bail!(
+                "LineageParentCount({:?}, {})",
+                self.reason,
+                self.parents.len()
+            );
+        }
+        if self.edit_decision.is_some() && self.reason != LineageReason::Edit {
+            bail!("LineageUnexpectedEditDecision({:?})", self.reason);
+        }
+        match (self.reason, &self.source) {
+            (LineageReason::Encode, None) => bail!("LineageMissingSource"),
+            (LineageReason::Edit | LineageReason::WorldOverride, Some(_)) => {
+                bail!("LineageUnexpectedSource({:?})", self.reason)
+            }
+            _ => {}
+        }
+        let unique: BTreeSet<_> = self.parents.iter().collect();
+        if unique.len() != self.parents.len() {
+            bail!("LineageDuplicateParent");
+        }
+        Ok(())
+    }
+}
+
+/// Written by `crossfade` edits.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Transition {
+    pub kind: TransitionKind,
+    #[serde(with = "unc1::int")]
+    pub at_tick: u64,
+    #[serde(with = "unc1::int")]
+    pub dur_ticks: u64,
+}
+
+impl Manifest {
+    /// Strict: the value must already be canonical UNC-1 ([`unc1::validate`]); nothing is
+    /// normalized on the way in.
+    pub fn from_json(value: &Value) -> Result<Self> {
+        unc1::validate(value).context("ManifestNotUnc1")?;
+        Self::deserialize(value).context("ManifestSchema")
+    }
+
+    pub fn to_json(&self) -> Result<Value> {
+        let value = serde_json::to_value(self).context("ManifestSerialize")?;
+        validate_no_json_numbers(&value)?;
+        Ok(value)
+    }
+
+    /// Decode the NRF-1.1 manifest bytes of a pack.
+    pub fn from_nrf(bytes: &[u8]) -> Result<Self> {
+        Self::from_json(&decode_json(bytes).context("ManifestNrfDecodeFailed")?)
+    }
+
+    pub fn to_nrf(&self) -> Result<Vec<u8>> {
+        Ok(cid_for_json_value(&self.to_json()?)?.1)
+    }
+
+    pub fn cid(&self) -> Result<Cid> {
+        Ok(cid_for_json_value(&self.to_json()?)?.0)
+    }
+
+    /// Structural checks the schema cannot express: a positive timebase, contiguous groups
+    /// from tick 0 summing to `duration_ticks`, tiles inside the video's tile grid and a
+    /// lineage shaped for its reason.
+    pub fn validate(&self) -> Result<()> {
+        if self.manifest_type != MANIFEST_TYPE {
+            bail!("UnexpectedManifestType({})", self.manifest_type);
+        }
+        if !self.timebase.is_positive() {
+            bail!("BadTimebase({})", self.timebase);
+        }
+        if self.gots.is_empty() {
+            bail!("ManifestHasNoGroups");
+        }
+        let mut end = 0u64;
+        for (i, got) in self.gots.iter().enumerate() {
+            if got.dur_ticks == 0 {
+                bail!("EmptyGot({})", i);
+            }
+            if got.start_tick < end {
+                bail!("GotOverlap({})", i);
+            }
+            if got.start_tick > end {
+                bail!("GotGap({})", i);
+            }
+            end = end
+                .checked_add(got.dur_ticks)
+                .with_context(|| format!("GotTickOverflow({})", i))?;
+        }
+        if end != self.duration_ticks {
+            bail!("DurationMismatch({} != {})", end, self.duration_ticks);
+        }
+        if let Some(video) = &self.video {
+            video.validate_tiles(&self.gots)?;
+        }
+        if let Some(lineage) = &self.lineage {
+            lineage.validate()?;
+        }
+        Ok(())
+    }
+}
+
+impl VideoInfo {
+    fn validate_tiles(&self, gots: &[Got]) -> Result<()> {
+        if self.width == 0 || self.height == 0 {
+            bail!("ZeroVideoSize");
+        }
+        let Some(size) = self.tile_size else {
+            return Ok(());
+        };
+        if size == 0 {
+            bail!("ZeroTileSize");
+        }
+        let (cols, rows) = (self.width.div_ceil(size), self.height.div_ceil(size));
+        for (g, got) in gots.iter().enumerate() {
+            let mut seen = BTreeSet::new();
+            for tile in &got.tiles {
+                let (x, y) = match (tile.tile_x, tile.tile_y) {
+                    (Some(x), Some(y)) => (x, y),
+                    (None, None) => continue,
+                    _ => bail!("PartialTileAddress({})", g),
+                };
+                if x >= cols || y >= rows {
+                    bail!("TileOutOfGrid({}/{}/{})", g, y, x);
+                }
+                if !seen.insert((x, y)) {
+                    bail!("DuplicateTile({}/{}/{})", g, y, x);
+                }
+                let w = size.min(self.width - x * size);
+                let h = size.min(self.height - y * size);
+                if tile.crop_w.is_some_and(|c| c != w) || tile.crop_h.is_some_and(|c| c != h) {
+                    bail!("TileCropMismatch({}/{}/{})", g, y, x);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A payload reference in a manifest: where it is, what it names and the MIME it declares
+/// (overlay and audio-edit assets declare none).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayloadRef<'a> {
+    pub path: String,
+    pub cid: Cid,
+    pub mime: Option<&'a str>,
+}
+
+impl Manifest {
+    /// Every payload the manifest refers to, in document order.
+    pub fn references(&self) -> Vec<PayloadRef<'_>> {
+        let mut out = Vec::new();
+        for (g, got) in self.gots.iter().enumerate() {
+            for (t, tile) in got.tiles.iter().enumerate() {
+                out.push(PayloadRef {
+                    path: format!("gots[{}].tiles[{}]", g, t),
+                    cid: tile.cid,
+                    mime: Some(&tile.mime),
+                });
+            }
+        }
+        if let Some(audio) = &self.audio {
+            out.push(PayloadRef {
+                path: "audio".to_string(),
+                cid: audio.cid,
+                mime: Some(&audio.mime),
+            });
+        }
+        for (i, s) in self.sidecars.iter().enumerate() {
+            out.push(PayloadRef {
+                path: format!("sidecars[{}]", i),
+                cid: s.cid,
+                mime: Some(&s.mime),
+            });
+        }
+        for (i, o) in self.overlays.iter().enumerate() {
+            out.push(PayloadRef {
+                path: format!("overlays[{}]", i),
+                cid: o.cid,
+                mime: None,
+            });
+        }
+        for (i, e) in self.audio_edits.iter().enumerate() {
+            if let Some(cid) = e.cid {
+                out.push(PayloadRef {
+                    path: format!("audio_edits[{}]", i),
+                    cid,
+                    mime: None,
+                });
+            }
+        }
+        out
+    }
+}
+
+/// A single way a manifest and a pack index disagree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReferenceIssue {
+    /// Referenced by the manifest but absent from the index.
+    Dangling { path: String, cid: Cid },
+    /// In the index but referenced by nothing.
+    Orphaned { cid: Cid, mime_tag: MimeTag },
+    MimeMismatch {
+        path: String,
+        cid: Cid,
+        declared: MimeTag,
+        indexed: MimeTag,
+    },
+}
+
+impl fmt::Display for ReferenceIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Dangling { path, cid } => write!(f, "DanglingReference({}, {})", path, cid),
+            Self::Orphaned { cid, mime_tag } => {
+                write!(f, "OrphanedPayload({}, {:?})", cid, mime_tag)
+            }
+            Self::MimeMismatch {
+                path,
+                cid,
+                declared,
+                indexed,
+            } => write!(
+                f,
+                "MimeMismatch({}, {}, declared={:?}, indexed={:?})",
+                path, cid, declared, indexed
+            ),
+        }
+    }
+}
+
+/// Result of cross-checking manifest references against a pack index.
+#[derive(Debug, Clone, Default)]
+pub struct ReferenceReport {
+    pub references: usize,
+    pub issues: Vec<ReferenceIssue>,
+}
+
+impl ReferenceReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Referential integrity: every reference resolves in `index` with the MIME tag it declares,
+/// and every index entry is referenced. Issues are reported in manifest order, then orphans in
+/// index order.
+pub fn check_references(manifest: &Manifest, index: &[IndexEntry]) -> ReferenceReport {
+    let by_cid: BTreeMap<&Cid, &IndexEntry> = index.iter().map(|e| (&e.cid, e)).collect();
+    let refs = manifest.references();
+    let mut report = ReferenceReport {
+        references: refs.len(),
+        issues: Vec::new(),
+    };
+    let mut used = BTreeSet::new();
+    for r in refs {
+        let Some(entry) = by_cid.get(&r.cid) else {
+            report.issues.push(ReferenceIssue::Dangling {
+                path: r.path,
+                cid: r.cid,
+            });
+            continue;
+        };
+        used.insert(r.cid);
+        if let Some(mime) = r.mime {
+            let declared = MimeTag::from_mime(mime);
+            if declared != entry.mime_tag {
+                report.issues.push(ReferenceIssue::MimeMismatch {
+                    path: r.path,
+                    cid: r.cid,
+                    declared,
+                    indexed: entry.mime_tag,
+                });
+            }
+        }
+    }
+    for e in index {
+        if !used.contains(&e.cid) {
+            report.issues.push(ReferenceIssue::Orphaned {
+                cid: e.cid,
+                mime_tag: e.mime_tag,
+            });
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edit_engine::{apply_operations, ManifestStore};
+    use crate::streaming_protocol::EditOperation;
+    use serde_json::json;
+
+    /// 80x64 at 64 px tiles: two columns, the second 16 px wide.
+    fn encoded() -> Value {
+        let unc = |v: u64| unc1::int_value(v);
+        let gots: Vec<Value> = (0..3u8)
+            .map(|g| {
+                let tiles: Vec<Value> = (0..2u8)
+                    .map(|x| {
+                        json!({
+                            "cid": Cid::blake3([g * 2 + x; 32]),
+                            "mime": "application/vcx-ic0t",
+                            "role": "base",
+                            "tile_x": unc(x as u64),
+                            "tile_y": unc(0),
+                            "crop_w": unc(if x == 0 { 64 } else { 16 }),
+                            "crop_h": unc(64)
+                        })
+                    })
+                    .collect();
+                json!({"start_tick": unc(g as u64 * 3000), "dur_ticks": unc(3000), "tiles": tiles})
+            })
+            .collect();
+        json!({
+            "@type": "vcx/manifest",
+            "@id": "m:enc",
+            "@ver": "1.0",
+            "@world": "a/demo/t/prod",
+            "profile": "vcx-ic0-alpha-luma-raw/v1",
+            "timebase": UncRat::new(1, 90_000).unwrap(),
+            "duration_ticks": unc(9000),
+            "video": {
+                "codec": "VCX-IC0-ALPHA",
+                "width": unc(80),
+                "height": unc(64),
+                "fps": UncRat::from_int(30),
+                "frames": unc(3),
+                "tile_size": unc(64)
+            },
+            "gots": gots,
+            "sidecars": [{
+                "cid": Cid::blake3([9; 32]),
+                "mime": "application/vcx-sidecar",
+                "type": "vcx/sidecar.media.import.v1"
+            }],
+            "audio": {"codec": "opus", "cid": Cid::blake3([8; 32]), "mime": "audio/opus"},
+            "source_frame_hint": unc(3)
+        })
+    }
+
+    fn rejects(m: &Manifest, code: &str) {
+        let err = m.validate().unwrap_err().to_string();
+        assert!(err.contains(code), "{} vs {}", err, code);
+    }
+
+    #[test]
+    fn round_trips_through_json_and_nrf() {
+        let json = encoded();
+        let m = Manifest::from_json(&json).unwrap();
+        m.validate().unwrap();
+        assert_eq!(m.to_json().unwrap(), json);
+        let (cid, nrf) = cid_for_json_value(&json).unwrap();
+        assert_eq!(m.to_nrf().unwrap(), nrf);
+        assert_eq!(m.cid().unwrap(), cid);
+        assert_eq!(Manifest::from_nrf(&nrf).unwrap(), m);
+
+        let mut unknown = json.clone();
+        unknown["gots"][0]["tiles"][0]["extra"] = "x".into();
+        assert!(Manifest::from_json(&unknown).is_err());
+        let mut bad_cid = json.clone();
+        bad_cid["audio"]["cid"] = "b3:00".into();
+        assert!(Manifest::from_json(&bad_cid).is_err());
+
+        // Non-canonical spellings would re-serialize differently: rejected, not normalized.
+        for (path, bad) in [
+            ("/gots/0/tiles/0/tile_x", json!("0")),
+            (
+                "/gots/0/tiles/1/tile_x",
+                json!({"@num": "int/1", "v": "01"}),
+            ),
+            ("/gots/0/tiles/0/crop_w", json!(64)),
+            ("/duration_ticks", json!(9000)),
+            ("/video/width", json!({"@num": "int/1", "v": "080"})),
+        ] {
+            let mut v = json.clone();
+            *v.pointer_mut(path).unwrap() = bad.clone();
+            assert!(Manifest::from_json(&v).is_err(), "{} = {}", path, bad);
+        }
+    }
+
+    #[test]
+    fn validate_checks_timeline_and_grid() {
+        let base = Manifest::from_json(&encoded()).unwrap();
+
+        let mut m = base.clone();
+        m.timebase = UncRat::from_int(0);
+        rejects(&m, "BadTimebase");
+
+        let mut m = base.clone();
+        m.gots[1].start_tick = 3001;
+        rejects(&m, "GotGap(1)");
+        m.gots[1].start_tick = 2999;
+        rejects(&m, "GotOverlap(1)");
+
+        let mut m = base.clone();
+        m.duration_ticks = 9001;
+        rejects(&m, "DurationMismatch");
+
+        let mut m = base.clone();
+        m.gots[2].tiles[1].tile_x = Some(2);
+        rejects(&m, "TileOutOfGrid(2/0/2)");
+
+        let mut m = base.clone();
+        m.gots[0].tiles[1].crop_w = Some(64);
+        rejects(&m, "TileCropMismatch(0/0/1)");
+
+        let mut m = base.clone();
+        m.gots[0].tiles[1].tile_x = Some(0);
+        rejects(&m, "DuplicateTile(0/0/0)");
+
+        let mut m = base;
+        m.gots[0].tiles[1].tile_y = None;
+        rejects(&m, "PartialTileAddress(0)");
+    }
+
+    #[test]
+    fn references_are_cross_checked_against_index() {
+        let m = Manifest::from_json(&encoded()).unwrap();
+        let entry = |cid: Cid, mime_tag: MimeTag| IndexEntry {
+            cid,
+            mime_tag,
+            flags: 0,
+            payload_off: 0,
+            payload_len: 0,
+            payload_hash: [0; 32],
+        };
+        let mut index: Vec<IndexEntry> = (1..6)
+            .map(|n| entry(Cid::blake3([n; 32]), MimeTag::Ic0Tile))
+            .collect();
+        index.push(entry(Cid::blake3([8; 32]), MimeTag::Sidecar));
+        index.push(entry(Cid::blake3([9; 32]), MimeTag::Sidecar));
+        index.push(entry(Cid::blake3([10; 32]), MimeTag::Opus));
+
+        let report = check_references(&m, &index);
+        assert_eq!(report.references, 8);
+        assert_eq!(
+            report.issues,
+            [
+                ReferenceIssue::Dangling {
+                    path: "gots[0].tiles[0]".into(),
+                    cid: Cid::blake3([0; 32])
+                },
+                ReferenceIssue::MimeMismatch {
+                    path: "audio".into(),
+                    cid: Cid::blake3([8; 32]),
+                    declared: MimeTag::Opus,
+                    indexed: MimeTag::Sidecar
+                },
+                ReferenceIssue::Orphaned {
+                    cid: Cid::blake3([10; 32]),
+                    mime_tag: MimeTag::Opus
+                },
+            ]
+        );
+        assert_eq!(
+            report.issues[0].to_string(),
+            format!(
+                "DanglingReference(gots[0].tiles[0], {})",
+                Cid::blake3([0; 32])
+            )
+        );
+
+        index[5].mime_tag = MimeTag::Opus;
+        index[7].cid = Cid::blake3([0; 32]);
+        index[7].mime_tag = MimeTag::Ic0Tile;
+        assert!(check_references(&m, &index).is_ok());
+    }
+
+    #[test]
+    fn edit_outputs_fit_the_schema() {
+        let input = encoded();
+        let out = apply_operations(
+            &input,
+            &[
+                EditOperation::Trim {
+                    start_ms: 0,
+                    end_ms: 66,
+                },
+                EditOperation::OverlayRef {
+                    asset_cid: Cid::blake3([7; 32]),
+                    x: -4,
+                    y: 0,
+                    from_ms: 0,
+                    to_ms: 30,
+                },
+                EditOperation::MuteAudio {
+                    start_ms: 0,
+                    end_ms: 30,
+                },
+                EditOperation::Crossfade {
+                    at_ms: 33,
+                    duration_ms: 10,
+                },
+                EditOperation::Crop {
+                    tile_x: 1,
+                    tile_y: 0,
+                    tiles_w: 1,
+                    tiles_h: 1,
+                },
+            ],
+            &ManifestStore::new(),
+        )
+        .unwrap();
+        let m = Manifest::from_json(&out.manifest).unwrap();
+        m.validate().unwrap();
+        assert_eq!(m.gots.len(), 2);
+        assert_eq!(m.overlays[0].x, -4);
+        assert_eq!(m.audio_edits[0].kind, AudioEditKind::Mute);
+        assert_eq!(m.video.as_ref().unwrap().width, 16);
+        assert_eq!(m.cid().unwrap(), out.manifest_cid);
+    }
+}
@@ -0,0 +1,315 @@
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Map, Value};
+use ubl_ai_nrf1::nrf::{
+    decode_from_slice, encode_to_vec, json_to_nrf as upstream_json_to_nrf, NrfValue,
+};
+
+use crate::unc1::UncInt;
+
+/// Reserved object key marking JSON that stands for an NRF value JSON cannot express.
+pub const NRF_TAG_KEY: &str = "@nrf";
+
+/// Map an NRF value to JSON so that [`json_to_nrf`] gives it back unchanged (same bytes, same
+/// CID):
+///
+/// - `Int(i)` becomes `{"@nrf":"int","v":"<digits>"}`, never a JSON number, so the result
+///   still passes strict UNC-1;
+/// - `Bytes(b)` becomes `{"@nrf":"bytes","hex":"<lowercase hex>"}`;
+/// - a map with an `@nrf` key is rejected, since it would read back as one of the above.
+///
+/// NRF maps are ordered by the UTF-8 bytes of their keys and objects are emitted in that
+/// order. Key order on the JSON side never reaches the NRF bytes.
+pub fn nrf_to_json(v: &NrfValue) -> Result<Value> {
+    Ok(match v {
+        NrfValue::Null => Value::Null,
+        NrfValue::Bool(b) => Value::Bool(*b),
+        NrfValue::Int(i) => json!({NRF_TAG_KEY: "int", "v": i.to_string()}),
+        NrfValue::String(s) => Value::String(s.clone()),
+        NrfValue::Bytes(b) => json!({NRF_TAG_KEY: "bytes", "hex": hex::encode(b)}),
+        NrfValue::Array(a) => Value::Array(a.iter().map(nrf_to_json).collect::<Result<_>>()?),
+        NrfValue::Map(m) => {
+            if m.contains_key(NRF_TAG_KEY) {
+                bail!("ReservedNrfKey({})", NRF_TAG_KEY);
+            }
+            Value::Object(
+                m.iter()
+                    .map(|(k, x)| Ok((k.clone(), nrf_to_json(x)?)))
+                    .collect::<Result<_>>()?,
+            )
+        }
+    })
+}
+
+/// Inverse of [`nrf_to_json`]. JSON without `@nrf` objects goes through the upstream
+/// `ubl_ai_nrf1` mapping unchanged, so bare JSON integers still read as `Int` for non-strict
+/// manifests. Only canonical tagged objects are intercepted (digits without leading zeros,
+/// lowercase hex), so distinct JSON never maps to the same NRF value.
+pub fn json_to_nrf(v: &Value) -> Result<NrfValue> {
+    if !has_tag(v) {
+        return Ok(upstream_json_to_nrf(v)?);
+    }
+    Ok(match v {
+        Value::Array(a) => NrfValue::Array(a.iter().map(json_to_nrf).collect::<Result<_>>()?),
+        Value::Object(o) if o.contains_key(NRF_TAG_KEY) => tagged_to_nrf(o)?,
+        Value::Object(o) => NrfValue::Map(
+            o.iter()
+                .map(|(k, x)| Ok((k.clone(), json_to_nrf(x)?)))
+                .collect::<Result<_>>()?,
+        ),
+        _ => upstream_json_to_nrf(v)?,
+    })
+}
+
+/// Whether any object in `v` carries [`NRF_TAG_KEY`].
+fn has_tag(v: &Value) -> bool {
+    match v {
+        Value::Array(a) => a.iter().any(has_tag),
+        Value::Object(o) => o.contains_key(NRF_TAG_KEY) || o.values().any(has_tag),
+        _ => false,
+    }
+}
+
+fn tagged_to_nrf(o: &Map<String, Value>) -> Result<NrfValue> {
+    let field = |k: &str| {
+        o.get(k)
+            .and_then(Value::as_str)
+            .with_context(|| format!("BadNrfTagged({})", k))
+    };
+    let (value, key) = match o[NRF_TAG_KEY].as_str() {
+        Some("int") => {
+            let v = field("v")?
+                .parse::<UncInt>()
+                .and_then(UncInt::get)
+                .context("BadNrfInt")?;
+            (NrfValue::Int(v), "v")
+        }
+        Some("bytes") => {
+            let digits = field("hex")?;
+            let b = hex::decode(digits).context("BadNrfBytes")?;
+            if hex::encode(&b) != digits {
+                bail!("NonCanonicalNrfBytes");
+            }
+            (NrfValue::Bytes(b), "hex")
+        }
+        _ => bail!("UnknownNrfTag({})", o[NRF_TAG_KEY]),
+    };
+    if let Some(k) = o.keys().find(|k| *k != NRF_TAG_KEY && *k != key) {
+        bail!("BadNrfTagged({})", k);
+    }
+    Ok(value)
+}
+
+/// NRF-1.1 bytes of a JSON value under [`json_to_nrf`].
+pub fn encode_json(value: &Value) -> Result<Vec<u8>> {
+    Ok(encode_to_vec(&json_to_nrf(value)?)?)
+}
+
+/// JSON of NRF-1.1 bytes under [`nrf_to_json`].
+pub fn decode_json(bytes: &[u8]) -> Result<Value> {
+    nrf_to_json(&decode_from_slice(bytes).context("NrfDecodeFailed")?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::manifest::{Got, Manifest, SidecarRef, TileRef, VideoInfo};
+    use crate::realtime_sim::splitmix64;
+    use crate::unc1::{self, UncRat};
+    use crate::{cid_for_json_value, cid_for_payload_bytes, Cid};
+
+    fn below(state: &mut u64, n: u64) -> u64 {
+        splitmix64(state) % n
+    }
+
+    fn arbitrary(state: &mut u64, depth: u32) -> NrfValue {
+        const TEXT: [&str; 7] = [
+            "",
+            "@num",
+            "int/1",
+            "b64:AAAA",
+            "a\u{e7}\u{e3}o",
+            "0x10",
+            "v",
+        ];
+        match below(state, if depth == 0 { 5 } else { 7 }) {
+            0 => NrfValue::Null,
+            1 => NrfValue::Bool(below(state, 2) == 1),
+            2 => NrfValue::Int(match below(state, 4) {
+                0 => i64::MIN,
+                1 => i64::MAX,
+                2 => below(state, 3) as i64 - 1,
+                _ => splitmix64(state) as i64,
+            }),
+            3 => NrfValue::String(TEXT[below(state, 7) as usize].to_string()),
+            4 => NrfValue::Bytes(
+                (0..below(state, 9))
+                    .map(|_| splitmix64(state) as u8)
+                    .collect(),
+            ),
+            5 => NrfValue::Array(
+                (0..below(state, 4))
+                    .map(|_| arbitrary(state, depth - 1))
+                    .collect(),
+            ),
+            _ => NrfValue::Map(
+                (0..below(state, 4))
+                    .map(|_| {
+                        let k = TEXT[below(state, 7) as usize].to_string();
+                        (k, arbitrary(state, depth - 1))
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// A manifest shaped like the ones `vcx_enc_cli` builds, with random geometry and timing.
+    fn encoder_manifest(state: &mut u64) -> Manifest {
+        let tile_size = [16, 32, 64][below(state, 3) as usize];
+        let (width, height) = (1 + below(state, 200) as u32, 1 + below(state, 120) as u32);
+        let frames = 1 + below(state, 3);
+        let fps = UncRat::new(
+            1 + below(state, 60_000) as i128,
+            1 + below(state, 1001) as i128,
+        )
+        .unwrap();
+        let frame_tick = 1 + below(state, 10_000);
+        let blob =
+            |state: &mut u64| cid_for_payload_bytes(&splitmix64(state).to_le_bytes()).unwrap();
+        let mut gots = Vec::new();
+        for f in 0..frames {
+            let mut tiles = Vec::new();
+            for y in 0..height.div_ceil(tile_size) {
+                for x in 0..width.div_ceil(tile_size) {
+                    tiles.push(TileRef {
+                        cid: blob(state),
+                        mime: "application/vcx-ic0t".to_string(),
+                        role: Some("base".to_string()),
+                        tile_x: Some(x),
+                        tile_y: Some(y),
+                        crop_w: Some(tile_size.min(width - x * tile_size)),
+                        crop_h: Some(tile_size.min(height - y * tile_size)),
+                    });
+                }
+            }
+            gots.push(Got {
+                start_tick: f * frame_tick,
+                dur_ticks: frame_tick,
+                tiles,
+            });
+        }
+        let audio = (below(state, 2) == 1).then(|| fixtures::opus(blob(state)));
+        let sidecar = blob(state);
+        Manifest {
+            id: format!("m:{:x}", splitmix64(state)),
+            world: "a/demo/t/s\u{e3}o".to_string(),
+            profile: Some("vcx-ic0-alpha-luma-raw/v1".to_string()),
+            duration_ticks: frames * frame_tick,
+            video: Some(VideoInfo {
+                codec: Some("VCX-IC0-ALPHA".to_string()),
+                width,
+                height,
+                fps: Some(fps),
+                frames: Some(frames),
+                tile_size: Some(tile_size),
+            }),
+            gots,
+            audio,
+            sidecars: vec![SidecarRef {
+                cid: sidecar,
+                mime: "application/vcx-sidecar".to_string(),
+                sidecar_type: Some("vcx/sidecar.media.import.v1".to_string()),
+            }],
+            source_frame_hint: (below(state, 2) == 1).then(|| below(state, 1 << 40)),
+            ..fixtures::manifest(&[], frame_tick)
+        }
+    }
+
+    #[test]
+    fn any_nrf_value_round_trips() {
+        let mut state = 0x6e72_665f_6a73_6f6e;
+        for _ in 0..2000 {
+            let v = arbitrary(&mut state, 4);
+            let json = nrf_to_json(&v).unwrap_or_else(|e| panic!("{:?}: {}", v, e));
+            assert_eq!(json_to_nrf(&json).unwrap(), v);
+            let bytes = encode_to_vec(&v).unwrap();
+            assert_eq!(encode_json(&decode_json(&bytes).unwrap()).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn encoder_manifests_keep_bytes_and_cid() {
+        let mut state = 0x0076_6378_5f65_6e63;
+        for _ in 0..200 {
+            let m = encoder_manifest(&mut state);
+            m.validate().unwrap();
+            let json = m.to_json().unwrap();
+            let (cid, nrf) = cid_for_json_value(&json).unwrap();
+            assert_eq!(nrf, m.to_nrf().unwrap());
+
+            let back = decode_json(&nrf).unwrap();
+            unc1::validate(&back).unwrap();
+            assert_eq!(encode_json(&back).unwrap(), nrf);
+            assert_eq!(cid_for_json_value(&back).unwrap().0, cid);
+            assert_eq!(Manifest::from_nrf(&nrf).unwrap(), m);
+        }
+    }
+
+    #[test]
+    fn encoding_is_explicit_and_canonical() {
+        assert_eq!(
+            nrf_to_json(&NrfValue::Int(-5)).unwrap(),
+            json!({"@nrf": "int", "v": "-5"})
+        );
+        assert_eq!(
+            nrf_to_json(&NrfValue::Bytes(vec![0xab, 1])).unwrap(),
+            json!({"@nrf": "bytes", "hex": "ab01"})
+        );
+        // A string that merely looks encoded stays a string.
+        assert_eq!(
+            json_to_nrf(&json!("b64:qwE=")).unwrap(),
+            NrfValue::String("b64:qwE=".to_string())
+        );
+        assert_eq!(json_to_nrf(&json!(7)).unwrap(), NrfValue::Int(7));
+
+        let reserved = NrfValue::Map([(NRF_TAG_KEY.to_string(), NrfValue::Null)].into());
+        assert!(nrf_to_json(&reserved).is_err());
+        for bad in [
+            json!({"@nrf": "int", "v": "007"}),
+            json!({"@nrf": "int", "v": 7}),
+            json!({"@nrf": "int", "v": "9223372036854775808"}),
+            json!({"@nrf": "bytes", "hex": "AB"}),
+            json!({"@nrf": "bytes", "hex": "abc"}),
+            json!({"@nrf": "bytes", "hex": "ab", "v": "1"}),
+            json!({"@nrf": "float", "v": "1.5"}),
+            json!(1.5),
+        ] {
+            assert!(json_to_nrf(&bad).is_err(), "{}", bad);
+        }
+
+        // JSON without tags hashes exactly as the upstream mapping does.
+        for plain in [
+            json!(null),
+            json!(-7),
+            json!([true, "x", [], {}]),
+            json!({"@type": "vcx/manifest", "@num": "int/1", "v": "12", "n": 3, "\u{e7}": [1, {"b": null}]}),
+            json!({"lineage": {"parents": ["b3:00"]}, "gots": [{"dur_ticks": {"@num": "int/1", "v": "9"}}]}),
+        ] {
+            let upstream = encode_to_vec(&upstream_json_to_nrf(&plain).unwrap()).unwrap();
+            let (cid, bytes) = cid_for_json_value(&plain).unwrap();
+            assert_eq!(bytes, upstream, "{}", plain);
+            assert_eq!(cid, Cid::blake3(*blake3::hash(&upstream).as_bytes()));
+        }
+
+        // Key order on the JSON side does not reach the bytes; decoding sorts by key bytes.
+        let a = encode_json(&json!({"b": "1", "a": "2", "\u{e7}": "3"})).unwrap();
+        let keys: Vec<String> = decode_json(&a)
+            .unwrap()
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+        assert_eq!(keys, ["a", "b", "\u{e7}"]);
+    }
+}
@@ -0,0 +1,603 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::io::{Read, Seek};
+
+use crate::ghost_tile::{ghost_matches, GhostConfig};
+use crate::nrf_json::decode_json;
+use crate::streaming_protocol::{RealtimePolicyHint, VodRegionStrategy};
+use crate::unc1::{self, ticks_to_ms};
+use crate::vod_predictability::tile_cid;
+use crate::{read_and_verify_pack, read_payload, Cid, Pack};
+
+/// One piece of a piecewise-constant network trace. The last segment lasts forever.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BandwidthSegment {
+    pub duration_ms: u64,
+    pub bits_per_second: u64,
+    pub latency_ms: u64,
+}
+
+/// Parse an NDJSON bandwidth trace, one segment per line; blank lines are skipped.
+pub fn parse_bandwidth_trace(text: &str) -> Result<Vec<BandwidthSegment>> {
+    let segments = text
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(i, l)| {
+            serde_json::from_str(l).with_context(|| format!("BadBandwidthLine({})", i + 1))
+        })
+        .collect::<Result<Vec<BandwidthSegment>>>()?;
+    validate_trace(&segments)?;
+    Ok(segments)
+}
+
+fn validate_trace(segments: &[BandwidthSegment]) -> Result<()> {
+    let Some(last) = segments.last() else {
+        bail!("EmptyBandwidthTrace");
+    };
+    if segments.iter().any(|s| s.duration_ms == 0) {
+        bail!("ZeroBandwidthSegmentDuration");
+    }
+    if last.bits_per_second == 0 {
+        bail!("BandwidthTraceEndsStalled");
+    }
+    Ok(())
+}
+
+/// What the simulated client plays under.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PlaybackPolicy {
+    Realtime {
+        hint: RealtimePolicyHint,
+    },
+    /// Uniform VOD strategy: `copy_previous` may ghost every tile with prefetch depth 2,
+    /// `hold_and_noise` half the tiles with depth 3, `download_aggressive` none with depth 6.
+    Vod {
+        strategy: VodRegionStrategy,
+    },
+}
+
+impl PlaybackPolicy {
+    fn speculation_budget(&self, tiles: usize) -> usize {
+        match self {
+            Self::Realtime { hint } => hint.max_speculative_tiles_per_group as usize,
+            Self::Vod { strategy } => match strategy {
+                VodRegionStrategy::CopyPrevious => tiles,
+                VodRegionStrategy::HoldAndNoise => tiles / 2,
+                VodRegionStrategy::DownloadAggressive => 0,
+            },
+        }
+    }
+
+    fn prefetch_depth(&self) -> usize {
+        match self {
+            Self::Realtime { hint } => hint.prefetch_depth_groups.max(1) as usize,
+            Self::Vod { strategy } => match strategy {
+                VodRegionStrategy::CopyPrevious => 2,
+                VodRegionStrategy::HoldAndNoise => 3,
+                VodRegionStrategy::DownloadAggressive => 6,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaybackTile {
+    pub cid: Cid,
+    pub bytes: u64,
+    /// Content is within ghost tolerance of the previous group's tile at this position, so
+    /// rendering that tile instead is a correct ghost.
+    pub unchanged: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaybackGroup {
+    pub duration_ms: u64,
+    pub tiles: Vec<PlaybackTile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GroupPlayback {
+    pub group: u64,
+    pub ready_ms: u64,
+    pub display_ms: u64,
+    pub stall_ms: u64,
+    pub fetched_bytes: u64,
+    pub cached_tiles: u64,
+    pub speculated_tiles: u64,
+    pub wrong_speculations: u64,
+    /// Wrong ghosts whose correction arrived after the group was displayed.
+    pub degraded_tiles: u64,
+    /// Share of tiles displayed with their real content.
+    pub quality: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PlaybackReport {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manifest_cid: Option<Cid>,
+    pub policy: PlaybackPolicy,
+    pub groups: u64,
+    pub startup_delay_ms: u64,
+    pub stall_count: u64,
+    pub total_stall_ms: u64,
+    pub bytes_downloaded: u64,
+    pub bytes_wasted: u64,
+    pub speculated_tiles: u64,
+    pub wrong_speculations: u64,
+    pub degraded_tiles: u64,
+    pub mean_quality: f64,
+    pub per_group: Vec<GroupPlayback>,
+}
+
+/// Single-connection link following a bandwidth trace.
+struct Link<'a> {
+    segments: &'a [BandwidthSegment],
+}
+
+impl Link<'_> {
+    fn segment_at(&self, t: f64) -> (&BandwidthSegment, f64) {
+        let mut start = 0.0;
+        for (i, s) in self.segments.iter().enumerate() {
+            let end = start + s.duration_ms as f64;
+            if t < end || i + 1 == self.segments.len() {
+                let end = if i + 1 == self.segments.len() {
+                    f64::INFINITY
+                } else {
+                    end
+                };
+                return (s, end);
+            }
+            start = end;
+        }
+        unreachable!("validated trace is non-empty")
+    }
+
+    /// Completion time of a request for `bytes` issued at `start` (ms).
+    fn fetch(&self, start: f64, bytes: u64) -> f64 {
+        let mut t = start + self.segment_at(start).0.latency_ms as f64;
+        let mut bits = bytes as f64 * 8.0;
+        loop {
+            let (seg, end) = self.segment_at(t);
+            let bps = seg.bits_per_second as f64;
+            let capacity = (end - t) * bps / 1000.0;
+            if bits <= capacity {
+                return t + bits * 1000.0 / bps.max(f64::MIN_POSITIVE);
+            }
+            bits -= capacity;
+            t = end;
+        }
+    }
+}
+
+/// Deterministic playback of `groups` over `trace`.
+///
+/// The client downloads over one connection, in group order, never more than the policy's
+/// prefetch depth ahead of the group on screen, and never downloads a CID twice. It ghosts a
+/// tile (renders the previous group's tile at that position instead of downloading it) when
+/// that position was unchanged in the previous transition, up to the policy's speculation
+/// budget. A group is displayed once its downloaded tiles are in; before that playback stalls.
+/// A correct ghost ([`PlaybackTile::unchanged`]) is never downloaded. A wrong ghost's real
+/// tile is fetched right after the group's other tiles, and if it lands after display the
+/// tile is shown degraded and its bytes are counted as wasted. The audio payload, if any, is fetched before the first group.
+pub fn simulate_playback(
+    groups: &[PlaybackGroup],
+    audio_bytes: Option<u64>,
+    trace: &[BandwidthSegment],
+    policy: &PlaybackPolicy,
+) -> Result<PlaybackReport> {
+    validate_trace(trace)?;
+    let link = Link { segments: trace };
+    let depth = policy.prefetch_depth();
+    let mut cache = BTreeSet::new();
+    let mut link_free = 0.0f64;
+    let mut report = PlaybackReport {
+        manifest_cid: None,
+        policy: policy.clone(),
+        groups: groups.len() as u64,
+        startup_delay_ms: 0,
+        stall_count: 0,
+        total_stall_ms: 0,
+        bytes_downloaded: 0,
+        bytes_wasted: 0,
+        speculated_tiles: 0,
+        wrong_speculations: 0,
+        degraded_tiles: 0,
+        mean_quality: 0.0,
+        per_group: Vec::with_capacity(groups.len()),
+    };
+    if let Some(bytes) = audio_bytes {
+        link_free = link.fetch(0.0, bytes);
+        report.bytes_downloaded += bytes;
+    }
+
+    let mut display: Vec<f64> = Vec::with_capacity(groups.len());
+    let mut quality_sum = 0.0;
+    for (g, group) in groups.iter().enumerate() {
+        let earliest = if g >= depth { display[g - depth] } else { 0.0 };
+        let mut t = link_free.max(earliest);
+
+        let mut budget = policy.speculation_budget(group.tiles.len());
+        let mut fetched_bytes = 0;
+        let mut cached = 0;
+        let mut ghosts = Vec::new();
+        for (p, tile) in group.tiles.iter().enumerate() {
+            if cache.contains(&tile.cid) {
+                cached += 1;
+                continue;
+            }
+            if budget > 0 && stable_position(groups, g, p) {
+                budget -= 1;
+                ghosts.push(p);
+                continue;
+            }
+            t = link.fetch(t, tile.bytes);
+            fetched_bytes += tile.bytes;
+            cache.insert(tile.cid);
+        }
+        let ready = t;
+
+        let nominal = match display.last() {
+            Some(prev) => prev + groups[g - 1].duration_ms as f64,
+            None => ready,
+        };
+        let shown = nominal.max(ready);
+        let stall = shown - nominal;
+        if stall > 0.0 {
+            report.stall_count += 1;
+            report.total_stall_ms += stall.round() as u64;
+        }
+        display.push(shown);
+
+        let (mut wrong, mut degraded) = (0u64, 0u64);
+        for &p in &ghosts {
+            // Ghosts only start at g >= 2, so the previous group exists.
+            let tile = &group.tiles[p];
+            if tile.unchanged {
+                continue;
+            }
+            wrong += 1;
+            t = link.fetch(t, tile.bytes);
+            fetched_bytes += tile.bytes;
+            cache.insert(tile.cid);
+            if t > shown {
+                degraded += 1;
+                report.bytes_wasted += tile.bytes;
+            }
+        }
+        link_free = t;
+
+        let quality = if group.tiles.is_empty() {
+            1.0
+        } else {
+            (group.tiles.len() as u64 - degraded) as f64 / group.tiles.len() as f64
+        };
+        quality_sum += quality;
+        report.bytes_downloaded += fetched_bytes;
+        report.speculated_tiles += ghosts.len() as u64;
+        report.wrong_speculations += wrong;
+        report.degraded_tiles += degraded;
+        report.per_group.push(GroupPlayback {
+            group: g as u64,
+            ready_ms: ready.round() as u64,
+            display_ms: shown.round() as u64,
+            stall_ms: stall.round() as u64,
+            fetched_bytes,
+            cached_tiles: cached,
+            speculated_tiles: ghosts.len() as u64,
+            wrong_speculations: wrong,
+            degraded_tiles: degraded,
+            quality,
+        });
+    }
+
+    report.startup_delay_ms = display.first().map_or(0, |t| t.round() as u64);
+    if !groups.is_empty() {
+        report.mean_quality = quality_sum / groups.len() as f64;
+    }
+    Ok(report)
+}
+
+/// Whether tile position `p` was unchanged between the two groups before `g`.
+fn stable_position(groups: &[PlaybackGroup], g: usize, p: usize) -> bool {
+    g >= 2 && groups[g - 1].tiles.get(p).is_some_and(|t| t.unchanged)
+}
+
+/// Build playback groups from a verified pack: group durations from the manifest timebase,
+/// tile sizes from the index, and `unchanged` from comparing each payload with the previous
+/// group's at the same position under the default ghost tolerance.
+pub fn playback_groups_for_pack<R: Read + Seek>(
+    mut r: R,
+) -> Result<(Cid, Vec<PlaybackGroup>, Option<u64>)> {
+    let pack = read_and_verify_pack(&mut r, true)?;
+    let manifest = decode_json(&pack.manifest_bytes).context("ManifestNrfDecodeFailed")?;
+    let manifest_cid = Cid::blake3(*blake3::hash(&pack.manifest_bytes).as_bytes());
+
+    let timebase = unc1::timebase(
+        manifest
+            .get("timebase")
+            .context("ManifestMissingTimebase")?,
+    )?;
+    let gots = manifest
+        .get("gots")
+        .and_then(Value::as_array)
+        .context("ManifestMissingGots")?;
+    let tolerance = GhostConfig::default().tolerance;
+    let mut groups = Vec::with_capacity(gots.len());
+    let mut prev: Vec<(Cid, Vec<u8>)> = Vec::new();
+    for (g, got) in gots.iter().enumerate() {
+        let dur_ticks =
+            unc1::int_field(got, "dur_ticks").with_context(|| format!("BadGotDuration({})", g))?;
+        let refs = got
+            .get("tiles")
+            .and_then(Value::as_array)
+            .with_context(|| format!("GotMissingTiles({})", g))?;
+        let mut tiles = Vec::with_capacity(refs.len());
+        let mut payloads = Vec::with_capacity(refs.len());
+        for (p, tile) in refs.iter().enumerate() {
+            let cid = tile_cid(tile).with_context(|| format!("TileRefMissingCid({})", g))?;
+            let entry = pack
+                .find_entry(&cid)
+                .with_context(|| format!("PayloadNotInPack({})", cid))?;
+            let payload = read_payload(&mut r, entry)?;
+            let unchanged = prev
+                .get(p)
+                .is_some_and(|(c, b)| *c == cid || ghost_matches(b, &payload, tolerance));
+            tiles.push(PlaybackTile {
+                cid,
+                bytes: entry.payload_len,
+                unchanged,
+            });
+            payloads.push((cid, payload));
+        }
+        prev = payloads;
+        groups.push(PlaybackGroup {
+            duration_ms: ticks_to_ms(dur_ticks, timebase),
+            tiles,
+        });
+    }
+    let audio = match manifest.get("audio") {
+        Some(a) => Some(payload_len(&pack, &tile_cid(a)?)?),
+        None => None,
+    };
+    Ok((manifest_cid, groups, audio))
+}
+
+fn payload_len(pack: &Pack, cid: &Cid) -> Result<u64> {
+    let entry = pack
+        .find_entry(cid)
+        .with_context(|| format!("PayloadNotInPack({})", cid))?;
+    Ok(entry.payload_len)
+}
+
+/// Simulate playback of a pack; see [`simulate_playback`].
+pub fn simulate_pack_playback<R: Read + Seek>(
+    r: R,
+    trace: &[BandwidthSegment],
+    policy: &PlaybackPolicy,
+) -> Result<PlaybackReport> {
+    let (manifest_cid, groups, audio) = playback_groups_for_pack(r)?;
+    let mut report = simulate_playback(&groups, audio, trace, policy)?;
+    report.manifest_cid = Some(manifest_cid);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::realtime_predictability::PolicyMode;
+    use crate::{build_pack, cid_for_payload_bytes, MimeTag};
+    use serde_json::{json, Value};
+    use std::io::Cursor;
+
+    /// Group of 10 kB tiles whose CIDs are the hashes of `names`.
+    fn group(names: &[&str]) -> PlaybackGroup {
+        PlaybackGroup {
+            duration_ms: 500,
+            tiles: names
+                .iter()
+                .map(|n| PlaybackTile {
+                    cid: Cid::blake3(*blake3::hash(n.as_bytes()).as_bytes()),
+                    bytes: 10_000,
+                    unchanged: false,
+                })
+                .collect(),
+        }
+    }
+
+    /// Mark tiles that repeat the previous group's CID at their position as unchanged.
+    fn timeline(mut groups: Vec<PlaybackGroup>) -> Vec<PlaybackGroup> {
+        for g in 1..groups.len() {
+            let (before, after) = groups.split_at_mut(g);
+            for (p, tile) in after[0].tiles.iter_mut().enumerate() {
+                tile.unchanged = before[g - 1]
+                    .tiles
+                    .get(p)
+                    .is_some_and(|t| t.cid == tile.cid);
+            }
+        }
+        groups
+    }
+
+    fn mbps(n: u64) -> BandwidthSegment {
+        BandwidthSegment {
+            duration_ms: 1_000,
+            bits_per_second: n * 1_000_000,
+            latency_ms: 20,
+        }
+    }
+
+    #[test]
+    fn link_integrates_across_segments() {
+        let trace = [
+            BandwidthSegment {
+                duration_ms: 100,
+                bits_per_second: 0,
+                latency_ms: 0,
+            },
+            mbps(8),
+        ];
+        let link = Link { segments: &trace };
+        // 1 MB at 8 Mbit/s is 1 s, after waiting out the dead first 100 ms.
+        assert_eq!(link.fetch(0.0, 1_000_000), 1_100.0);
+        assert_eq!(link.fetch(150.0, 1_000), 171.0);
+    }
+
+    #[test]
+    fn slow_link_stalls_and_wrong_speculation_degrades() {
+        let groups: Vec<PlaybackGroup> = (0..8)
+            .map(|g| group(&["a", "b", &format!("m{}", g)]))
+            .collect();
+        let download = PlaybackPolicy::Vod {
+            strategy: VodRegionStrategy::DownloadAggressive,
+        };
+        let fast = simulate_playback(&groups, None, &[mbps(8)], &download).unwrap();
+        assert_eq!(fast.stall_count, 0);
+        assert_eq!(fast.bytes_downloaded, 100_000);
+        assert_eq!(fast.startup_delay_ms, 90);
+        assert!(fast.per_group.iter().all(|g| g.quality == 1.0));
+
+        let slow = simulate_playback(
+            &groups,
+            Some(200_000),
+            &[BandwidthSegment {
+                duration_ms: 1,
+                bits_per_second: 100_000,
+                latency_ms: 50,
+            }],
+            &download,
+        )
+        .unwrap();
+        assert!(slow.stall_count > 0);
+        assert!(slow.startup_delay_ms > 16_000);
+
+        let moving = timeline(
+            (0..6)
+                .map(|g| group(&["a", &format!("x{}", g / 2), &format!("m{}", g)]))
+                .collect(),
+        );
+        let ghost = PlaybackPolicy::Realtime {
+            hint: RealtimePolicyHint::for_mode(PolicyMode::AggressiveGhost),
+        };
+        let report = simulate_playback(&moving, None, &[mbps(8)], &ghost).unwrap();
+        assert!(report.speculated_tiles > 0);
+        assert!(report.wrong_speculations > 0);
+        assert_eq!(report.degraded_tiles, 0);
+        assert_eq!(report.mean_quality, 1.0);
+
+        let narrow = [BandwidthSegment {
+            duration_ms: 1,
+            bits_per_second: 100_000,
+            latency_ms: 50,
+        }];
+        let report = simulate_playback(&moving, None, &narrow, &ghost).unwrap();
+        assert!(report.degraded_tiles > 0);
+        assert_eq!(report.bytes_wasted, report.degraded_tiles * 10_000);
+        assert!(report.mean_quality < 1.0);
+        let again = simulate_playback(&moving, None, &narrow, &ghost).unwrap();
+        assert_eq!(
+            serde_json::to_string(&report).unwrap(),
+            serde_json::to_string(&again).unwrap()
+        );
+    }
+
+    #[test]
+    fn correct_speculation_saves_bytes() {
+        // Position 1 gets a new CID every group, but its content stays within ghost tolerance.
+        let mut drifting = timeline(
+            (0..6)
+                .map(|g| group(&["a", &format!("s{}", g), &format!("m{}", g)]))
+                .collect(),
+        );
+        for g in &mut drifting[1..] {
+            g.tiles[1].unchanged = true;
+        }
+        let download = PlaybackPolicy::Vod {
+            strategy: VodRegionStrategy::DownloadAggressive,
+        };
+        let ghost = PlaybackPolicy::Realtime {
+            hint: RealtimePolicyHint::for_mode(PolicyMode::AggressiveGhost),
+        };
+        let full = simulate_playback(&drifting, None, &[mbps(8)], &download).unwrap();
+        let report = simulate_playback(&drifting, None, &[mbps(8)], &ghost).unwrap();
+        assert_eq!(full.bytes_downloaded, 130_000);
+        // Groups 2..6 ghost position 1 and never download it.
+        assert_eq!(report.speculated_tiles, 4);
+        assert_eq!(report.wrong_speculations, 0);
+        assert_eq!(report.bytes_downloaded, 90_000);
+        assert_eq!(report.bytes_wasted, 0);
+        assert_eq!(report.mean_quality, 1.0);
+    }
+
+    #[test]
+    fn bandwidth_trace_parsing_rejects_bad_traces() {
+        let ok = parse_bandwidth_trace(
+            "{\"duration_ms\":500,\"bits_per_second\":0,\"latency_ms\":10}\n\n\
+             {\"duration_ms\":500,\"bits_per_second\":1000000,\"latency_ms\":10}\n",
+        )
+        .unwrap();
+        assert_eq!(ok.len(), 2);
+        assert!(parse_bandwidth_trace("").is_err());
+        assert!(parse_bandwidth_trace(
+            "{\"duration_ms\":500,\"bits_per_second\":0,\"latency_ms\":1}"
+        )
+        .unwrap_err()
+        .to_string()
+        .contains("BandwidthTraceEndsStalled"));
+    }
+
+    #[test]
+    fn pack_groups_use_timebase_and_index_sizes() {
+        let payloads: Vec<Vec<u8>> = vec![vec![1u8; 4_000], vec![2u8; 6_000]];
+        let cids: Vec<Cid> = payloads
+            .iter()
+            .map(|p| cid_for_payload_bytes(p).unwrap())
+            .collect();
+        let gots: Vec<Value> = [0, 0, 1]
+            .iter()
+            .map(|&i| {
+                json!({
+                    "start_tick": {"@num": "int/1", "v": "0"},
+                    "dur_ticks": {"@num": "int/1", "v": "45000"},
+                    "tiles": [{"cid": cids[i], "mime": "application/vcx-ic0t", "role": "base"}]
+                })
+            })
+            .collect();
+        let manifest = json!({
+            "@type": "vcx/manifest",
+            "@id": "m:play",
+            "@ver": "1.0",
+            "@world": "a/vod/t/prod",
+            "timebase": {"@num": "rat/1", "p": "1", "q": "90000"},
+            "duration_ticks": {"@num": "int/1", "v": "135000"},
+            "gots": gots
+        });
+        let mut out = Cursor::new(Vec::<u8>::new());
+        let entries = payloads
+            .into_iter()
+            .map(|p| (MimeTag::Ic0Tile, p))
+            .collect();
+        build_pack(&mut out, &manifest, entries, true).unwrap();
+
+        out.set_position(0);
+        let (_, groups, audio) = playback_groups_for_pack(&mut out).unwrap();
+        assert_eq!(audio, None);
+        assert_eq!(groups.len(), 3);
+        assert!(groups.iter().all(|g| g.duration_ms == 500));
+        assert_eq!(groups[2].tiles[0].bytes, 6_000);
+        assert!(groups[1].tiles[0].unchanged);
+        assert!(!groups[2].tiles[0].unchanged);
+
+        out.set_position(0);
+        let policy = PlaybackPolicy::Vod {
+            strategy: VodRegionStrategy::DownloadAggressive,
+        };
+        let report = simulate_pack_playback(&mut out, &[mbps(8)], &policy).unwrap();
+        assert!(report.manifest_cid.is_some());
+        assert_eq!(report.bytes_downloaded, 10_000);
+        assert_eq!(report.per_group[1].cached_tiles, 1);
+    }
+}
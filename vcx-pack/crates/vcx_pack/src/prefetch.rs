@@ -0,0 +1,347 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::realtime_predictability::PolicyMode;
+use crate::streaming_protocol::{RealtimePolicyHint, VodRegionStrategy};
+use crate::tile_index::{RegionMap, TileGrid};
+use crate::unc1::{self, ticks_to_ms};
+use crate::Cid;
+
+/// Fetch classes in priority order.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum FetchClass {
+    /// Tiles with role `base` (or no role).
+    Base,
+    /// Tiles with any other role.
+    Refinement,
+    Audio,
+    /// Tiles the player ghosts first; their bytes only replace the ghost.
+    Speculative,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FetchItem {
+    pub cid: Cid,
+    pub class: FetchClass,
+    pub group: u32,
+    /// Media time at which the group is displayed.
+    pub deadline_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FetchPlan {
+    pub playhead_group: u32,
+    pub mode: PolicyMode,
+    /// Exclusive end of the prefetch window.
+    pub window_end_group: u32,
+    pub items: Vec<FetchItem>,
+}
+
+/// Change from the previous plan: new requests to issue and pending ones to cancel.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FetchPlanDelta {
+    pub added: Vec<FetchItem>,
+    pub cancelled: Vec<Cid>,
+}
+
+#[derive(Debug, Clone)]
+struct PlanTile {
+    cid: Cid,
+    base: bool,
+    slot: u32,
+}
+
+#[derive(Debug, Clone)]
+struct PlanGroup {
+    start_ms: u64,
+    tiles: Vec<PlanTile>,
+}
+
+/// Turns the playhead, the active policy hint and optional VOD region rules into an ordered
+/// fetch plan.
+///
+/// The window is `prefetch_depth_groups` groups from the playhead (at least one). Items are
+/// ordered by class, then deadline, then tile slot; each CID appears once, at its most urgent
+/// use, and CIDs marked fetched are left out. Tiles whose region strategy is `copy_previous`
+/// or `hold_and_noise` are speculative, up to `max_speculative_tiles_per_group` per group in
+/// slot order; the rest are fetched as usual.
+#[derive(Debug, Clone)]
+pub struct PrefetchScheduler {
+    groups: Vec<PlanGroup>,
+    audio: Option<Cid>,
+    regions: Option<RegionMap>,
+    fetched: BTreeSet<Cid>,
+    playhead: u32,
+    hint: RealtimePolicyHint,
+    last: Option<FetchPlan>,
+}
+
+impl PrefetchScheduler {
+    pub fn new(manifest: &Value, regions: Option<RegionMap>) -> Result<Self> {
+        let grid = TileGrid::from_manifest(manifest)?;
+        let timebase = unc1::timebase(
+            manifest
+                .get("timebase")
+                .context("ManifestMissingTimebase")?,
+        )?;
+        let gots = manifest
+            .get("gots")
+            .and_then(Value::as_array)
+            .context("ManifestMissingGots")?;
+        let mut groups = Vec::with_capacity(gots.len());
+        for (g, got) in gots.iter().enumerate() {
+            let start = unc1::int_field::<u64>(got, "start_tick")
+                .with_context(|| format!("BadGot({})", g))?;
+            let mut tiles = Vec::new();
+            for (p, tile) in got["tiles"].as_array().into_iter().flatten().enumerate() {
+                let cid = tile
+                    .get("cid")
+                    .and_then(Value::as_str)
+                    .with_context(|| format!("TileRefMissingCid({})", g))?;
+                tiles.push(PlanTile {
+                    cid: cid.parse()?,
+                    base: tile.get("role").and_then(Value::as_str).unwrap_or("base") == "base",
+                    slot: grid.slot_of(tile, p)?,
+                });
+            }
+            tiles.sort_by_key(|t| t.slot);
+            groups.push(PlanGroup {
+                start_ms: ticks_to_ms(start, timebase),
+                tiles,
+            });
+        }
+        let audio = manifest
+            .get("audio")
+            .and_then(|a| a.get("cid"))
+            .and_then(Value::as_str)
+            .map(str::parse)
+            .transpose()?;
+        Ok(Self {
+            groups,
+            audio,
+            regions,
+            fetched: BTreeSet::new(),
+            playhead: 0,
+            hint: RealtimePolicyHint::for_mode(PolicyMode::Balanced),
+            last: None,
+        })
+    }
+
+    pub fn mark_fetched(&mut self, cid: &Cid) {
+        self.fetched.insert(*cid);
+    }
+
+    /// Plan for the current playhead and hint.
+    pub fn plan(&self) -> FetchPlan {
+        let start = (self.playhead as usize).min(self.groups.len());
+        let end = (start + self.hint.prefetch_depth_groups.max(1) as usize).min(self.groups.len());
+        let mut best: BTreeMap<Cid, (FetchItem, u32)> = BTreeMap::new();
+        let mut offer = |item: FetchItem, slot: u32| {
+            if self.fetched.contains(&item.cid) {
+                return;
+            }
+            match best.get(&item.cid) {
+                Some((prev, _))
+                    if (prev.class, prev.deadline_ms) <= (item.class, item.deadline_ms) => {}
+                _ => {
+                    best.insert(item.cid, (item, slot));
+                }
+            }
+        };
+
+        for (g, group) in self.groups.iter().enumerate().take(end).skip(start) {
+            let mut budget = self.hint.max_speculative_tiles_per_group;
+            for tile in &group.tiles {
+                let ghostable = self.regions.as_ref().is_some_and(|m| {
+                    matches!(
+                        m.decision(g as u32, tile.slot).strategy,
+                        VodRegionStrategy::CopyPrevious | VodRegionStrategy::HoldAndNoise
+                    )
+                });
+                let class = if ghostable && budget > 0 {
+                    budget -= 1;
+                    FetchClass::Speculative
+                } else if tile.base {
+                    FetchClass::Base
+                } else {
+                    FetchClass::Refinement
+                };
+                let item = FetchItem {
+                    cid: tile.cid,
+                    class,
+                    group: g as u32,
+                    deadline_ms: group.start_ms,
+                };
+                offer(item, tile.slot);
+            }
+        }
+        if let (Some(cid), Some(group)) = (&self.audio, self.groups.get(start)) {
+            let item = FetchItem {
+                cid: *cid,
+                class: FetchClass::Audio,
+                group: start as u32,
+                deadline_ms: group.start_ms,
+            };
+            offer(item, 0);
+        }
+
+        let mut items: Vec<(FetchItem, u32)> = best.into_values().collect();
+        items.sort_by(|(a, sa), (b, sb)| {
+            (a.class, a.deadline_ms, sa, &a.cid).cmp(&(b.class, b.deadline_ms, sb, &b.cid))
+        });
+        FetchPlan {
+            playhead_group: start as u32,
+            mode: self.hint.mode,
+            window_end_group: end as u32,
+            items: items.into_iter().map(|(item, _)| item).collect(),
+        }
+    }
+
+    /// Move the playhead and/or switch hints; returns the delta from the previous plan. Items
+    /// that are new or changed class are added; CIDs no longer planned and not yet fetched are
+    /// cancelled.
+    pub fn update(&mut self, playhead: u32, hint: &RealtimePolicyHint) -> FetchPlanDelta {
+        self.playhead = playhead;
+        self.hint = hint.clone();
+        let plan = self.plan();
+        let previous: BTreeMap<&Cid, &FetchItem> = self
+            .last
+            .iter()
+            .flat_map(|p| &p.items)
+            .map(|i| (&i.cid, i))
+            .collect();
+        let current: BTreeSet<&Cid> = plan.items.iter().map(|i| &i.cid).collect();
+        let delta = FetchPlanDelta {
+            added: plan
+                .items
+                .iter()
+                .filter(|i| previous.get(&i.cid).map(|p| p.class) != Some(i.class))
+                .cloned()
+                .collect(),
+            cancelled: previous
+                .keys()
+                .filter(|c| !current.contains(*c) && !self.fetched.contains(**c))
+                .map(|c| **c)
+                .collect(),
+        };
+        self.last = Some(plan);
+        delta
+    }
+
+    pub fn last_plan(&self) -> Option<&FetchPlan> {
+        self.last.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::streaming_protocol::VodRegionRule;
+    use serde_json::json;
+
+    fn cid(name: &str) -> Cid {
+        Cid::blake3(*blake3::hash(name.as_bytes()).as_bytes())
+    }
+
+    /// Four 500 ms groups of a 2x1 grid plus one refinement; slot 0 never changes.
+    fn manifest() -> Value {
+        let gots: Vec<Value> = (0..4)
+            .map(|g| {
+                let tile = |x: u32, name: String, role: &str| {
+                    json!({"cid": cid(&name), "role": role, "tile_x": unc1::int_value(x), "tile_y": unc1::int_value(0)})
+                };
+                json!({
+                    "start_tick": {"@num": "int/1", "v": (g * 45_000).to_string()},
+                    "dur_ticks": {"@num": "int/1", "v": "45000"},
+                    "tiles": [
+                        tile(1, format!("m{}", g), "base"),
+                        tile(0, "still".to_string(), "base"),
+                        tile(1, format!("r{}", g), "refine")
+                    ]
+                })
+            })
+            .collect();
+        json!({
+            "timebase": {"@num": "rat/1", "p": "1", "q": "90000"},
+            "audio": {"cid": cid("audio")},
+            "gots": gots
+        })
+    }
+
+    fn cids(items: &[FetchItem]) -> Vec<Cid> {
+        items.iter().map(|i| i.cid).collect()
+    }
+
+    #[test]
+    fn plan_orders_classes_and_dedups() {
+        let mut s = PrefetchScheduler::new(&manifest(), None).unwrap();
+        s.update(
+            1,
+            &RealtimePolicyHint::for_mode(PolicyMode::AggressiveGhost),
+        );
+        let plan = s.last_plan().unwrap();
+        assert_eq!((plan.playhead_group, plan.window_end_group), (1, 3));
+        assert_eq!(
+            cids(&plan.items),
+            ["still", "m1", "m2", "r1", "r2", "audio"].map(cid)
+        );
+        assert_eq!(plan.items[0].deadline_ms, 500);
+        assert_eq!(plan.items[5].class, FetchClass::Audio);
+
+        s.mark_fetched(&cid("audio"));
+        s.mark_fetched(&cid("still"));
+        assert_eq!(cids(&s.plan().items)[..2], ["m1", "m2"].map(cid));
+
+        let json = serde_json::to_value(s.plan()).unwrap();
+        assert_eq!(json["mode"], "aggressive_ghost");
+        assert_eq!(json["items"][0]["class"], "base");
+    }
+
+    #[test]
+    fn regions_cap_speculation_and_updates_are_incremental() {
+        let still = VodRegionRule {
+            region_id: "still".into(),
+            tile_range_start: 0,
+            tile_range_end: 1,
+            group_range_start: None,
+            group_range_end: None,
+            strategy: VodRegionStrategy::CopyPrevious,
+            confidence: 1.0,
+        };
+        let regions = RegionMap::new(&[still]).unwrap();
+        let mut s = PrefetchScheduler::new(&manifest(), Some(regions)).unwrap();
+        let mut hint = RealtimePolicyHint::for_mode(PolicyMode::Balanced);
+        hint.max_speculative_tiles_per_group = 1;
+
+        let first = s.update(0, &hint);
+        let plan = s.last_plan().unwrap().clone();
+        assert_eq!(first.added, plan.items);
+        // One ghost per group in slot order: slot 0 everywhere, so slot 1 is fetched as base.
+        let speculative: Vec<_> = plan
+            .items
+            .iter()
+            .filter(|i| i.class == FetchClass::Speculative)
+            .collect();
+        assert_eq!(speculative.len(), 1);
+        assert_eq!(speculative[0].cid, cid("still"));
+        assert_eq!(plan.items[0].cid, cid("m0"));
+
+        s.mark_fetched(&cid("m0"));
+        let moved = s.update(1, &hint);
+        assert_eq!(cids(&moved.added), ["m3", "r3"].map(cid));
+        assert_eq!(moved.cancelled, [cid("r0")]);
+
+        // download_first widens the window and its budget covers every ghostable tile.
+        let switched = s.update(1, &RealtimePolicyHint::for_mode(PolicyMode::DownloadFirst));
+        assert!(switched.cancelled.is_empty());
+        assert_eq!(s.last_plan().unwrap().window_end_group, 4);
+        assert_eq!(switched.added.len(), 6);
+        assert!(switched
+            .added
+            .iter()
+            .all(|i| i.class == FetchClass::Speculative));
+    }
+}
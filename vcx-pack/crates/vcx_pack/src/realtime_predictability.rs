@@ -1,11 +1,25 @@
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 
+use crate::streaming_protocol::RealtimePredictabilitySidecar;
+use crate::unc1;
+
+/// Mode switching uses hysteresis: `high_threshold` / `low_threshold` enter
+/// `AggressiveGhost` / `DownloadFirst`, and the mode is kept until the score crosses the
+/// matching exit threshold. After a transition the mode is held for `min_dwell_groups`
+/// groups, unless a per-group volatility of at least `volatility_spike` forces
+/// `DownloadFirst` immediately. The defaults turn all three off: exit thresholds equal to the
+/// enter thresholds, no dwell and no spike.
 #[derive(Debug, Clone, Copy)]
 pub struct PredictabilityConfig {
     pub alpha: f64,
     pub high_threshold: f64,
     pub low_threshold: f64,
+    pub high_exit_threshold: f64,
+    pub low_exit_threshold: f64,
     pub min_samples: u64,
+    pub min_dwell_groups: u64,
+    pub volatility_spike: Option<f64>,
 }
 
 impl Default for PredictabilityConfig {
@@ -14,7 +28,11 @@ impl Default for PredictabilityConfig {
             alpha: 0.25,
             high_threshold: 0.85,
             low_threshold: 0.60,
+            high_exit_threshold: 0.85,
+            low_exit_threshold: 0.60,
             min_samples: 4,
+            min_dwell_groups: 0,
+            volatility_spike: None,
         }
     }
 }
@@ -27,22 +45,180 @@ pub enum PolicyMode {
     DownloadFirst,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Why the mode changed on this group.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TransitionReason {
+    /// Score crossed the enter threshold of the new mode.
+    EnterThreshold,
+    /// Score crossed the exit threshold of the previous mode.
+    ExitThreshold,
+    /// Per-group volatility spike forced `DownloadFirst`, bypassing dwell.
+    VolatilitySpike,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ModeTransition {
+    pub from: PolicyMode,
+    pub reason: TransitionReason,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub struct GroupObservation {
     pub guessed_tiles: u64,
     pub correct_tiles: u64,
 }
 
+impl GroupObservation {
+    /// `correct / guessed`, with `correct` capped at `guessed` and an empty group counted as one.
+    pub fn hit_rate(&self) -> f64 {
+        let guessed = self.guessed_tiles.max(1);
+        let correct = self.correct_tiles.min(guessed);
+        correct as f64 / guessed as f64
+    }
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct RealtimePredictabilityMetrics {
+    #[serde(with = "unc1::dec")]
     pub instant_hit_rate: f64,
+    #[serde(with = "unc1::dec")]
     pub ewma_hit_rate: f64,
+    #[serde(with = "unc1::dec")]
     pub ewma_volatility: f64,
+    #[serde(with = "unc1::dec")]
     pub predictability_score: f64,
+    #[serde(with = "unc1::int")]
     pub samples: u64,
     pub mode: PolicyMode,
+    /// Set on the group where `mode` changed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transition: Option<ModeTransition>,
+    /// Groups observed since the last transition (0 on the transition group).
+    #[serde(default, with = "unc1::int")]
+    pub dwell_groups: u64,
 }
 
+/// Identifier of the normative VSEP-1 EWMA model.
+pub const EWMA_MODEL_ID: &str = "vsep1/ewma";
+
+/// A source of predictability scores. Implementations are fed one observation per group and
+/// report the metrics published in the realtime sidecar, whose `model` field carries
+/// `model_id()`.
+pub trait PredictabilityModel {
+    fn model_id(&self) -> &str;
+    fn observe(&mut self, observation: GroupObservation) -> RealtimePredictabilityMetrics;
+}
+
+/// Mode policy shared by models: warmup, enter/exit thresholds, dwell and volatility spikes.
+#[derive(Debug, Clone)]
+pub struct ModeSelector {
+    cfg: PredictabilityConfig,
+    mode: PolicyMode,
+    dwell_groups: u64,
+}
+
+impl ModeSelector {
+    pub fn new(cfg: PredictabilityConfig) -> Self {
+        Self {
+            cfg,
+            mode: PolicyMode::Balanced,
+            dwell_groups: 0,
+        }
+    }
+
+    /// Continue from a known mode and dwell, e.g. after a restart.
+    pub fn resume(cfg: PredictabilityConfig, mode: PolicyMode, dwell_groups: u64) -> Self {
+        Self {
+            cfg,
+            mode,
+            dwell_groups,
+        }
+    }
+
+    pub fn mode(&self) -> PolicyMode {
+        self.mode
+    }
+
+    pub fn dwell_groups(&self) -> u64 {
+        self.dwell_groups
+    }
+
+    /// Advance one group. `samples` counts groups seen including this one and `volatility` is
+    /// this group's instantaneous volatility.
+    pub fn select(
+        &mut self,
+        samples: u64,
+        score: f64,
+        volatility: f64,
+    ) -> (PolicyMode, Option<ModeTransition>) {
+        self.dwell_groups += 1;
+        let spike = self.cfg.volatility_spike.is_some_and(|t| volatility >= t);
+        let next = if samples < self.cfg.min_samples {
+            None
+        } else if spike && self.mode != PolicyMode::DownloadFirst {
+            Some((PolicyMode::DownloadFirst, TransitionReason::VolatilitySpike))
+        } else if self.dwell_groups > self.cfg.min_dwell_groups {
+            self.next_mode(score)
+        } else {
+            None
+        };
+        let transition = next.map(|(mode, reason)| {
+            let from = self.mode;
+            self.mode = mode;
+            self.dwell_groups = 0;
+            ModeTransition { from, reason }
+        });
+        (self.mode, transition)
+    }
+
+    fn next_mode(&self, score: f64) -> Option<(PolicyMode, TransitionReason)> {
+        let c = &self.cfg;
+        match self.mode {
+            PolicyMode::AggressiveGhost if score < c.low_threshold => {
+                Some((PolicyMode::DownloadFirst, TransitionReason::EnterThreshold))
+            }
+            PolicyMode::AggressiveGhost if score < c.high_exit_threshold => {
+                Some((PolicyMode::Balanced, TransitionReason::ExitThreshold))
+            }
+            PolicyMode::DownloadFirst if score >= c.high_threshold => Some((
+                PolicyMode::AggressiveGhost,
+                TransitionReason::EnterThreshold,
+            )),
+            PolicyMode::DownloadFirst if score >= c.low_exit_threshold => {
+                Some((PolicyMode::Balanced, TransitionReason::ExitThreshold))
+            }
+            PolicyMode::Balanced if score >= c.high_threshold => Some((
+                PolicyMode::AggressiveGhost,
+                TransitionReason::EnterThreshold,
+            )),
+            PolicyMode::Balanced if score < c.low_threshold => {
+                Some((PolicyMode::DownloadFirst, TransitionReason::EnterThreshold))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Complete `RealtimePredictor` state, for resuming on restart or failover. Rates are UNC-1
+/// `dec/1`, so the JSON form round-trips without relying on float parsing.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PredictorSnapshot {
+    pub model: String,
+    #[serde(with = "unc1::opt_dec")]
+    pub prev_hit_rate: Option<f64>,
+    #[serde(with = "unc1::dec")]
+    pub ewma_hit_rate: f64,
+    #[serde(with = "unc1::dec")]
+    pub ewma_volatility: f64,
+    #[serde(with = "unc1::int")]
+    pub samples: u64,
+    pub mode: PolicyMode,
+    #[serde(with = "unc1::int")]
+    pub dwell_groups: u64,
+}
+
+/// The normative VSEP-1 model: `score = ewma_hit * (1 - ewma_vol)`.
 #[derive(Debug, Clone)]
 pub struct RealtimePredictor {
     cfg: PredictabilityConfig,
@@ -50,6 +226,7 @@ pub struct RealtimePredictor {
     ewma_hit_rate: f64,
     ewma_volatility: f64,
     samples: u64,
+    selector: ModeSelector,
 }
 
 impl RealtimePredictor {
@@ -60,13 +237,66 @@ impl RealtimePredictor {
             ewma_hit_rate: 0.0,
             ewma_volatility: 0.0,
             samples: 0,
+            selector: ModeSelector::new(cfg),
+        }
+    }
+
+    pub fn snapshot(&self) -> PredictorSnapshot {
+        PredictorSnapshot {
+            model: EWMA_MODEL_ID.to_string(),
+            prev_hit_rate: self.prev_hit_rate,
+            ewma_hit_rate: self.ewma_hit_rate,
+            ewma_volatility: self.ewma_volatility,
+            samples: self.samples,
+            mode: self.selector.mode(),
+            dwell_groups: self.selector.dwell_groups(),
         }
     }
 
+    pub fn restore(cfg: PredictabilityConfig, snapshot: &PredictorSnapshot) -> Result<Self> {
+        if snapshot.model != EWMA_MODEL_ID {
+            bail!("PredictorModelMismatch({})", snapshot.model);
+        }
+        if snapshot.samples == 0 {
+            return Ok(Self::new(cfg));
+        }
+        Ok(Self {
+            cfg,
+            prev_hit_rate: snapshot.prev_hit_rate,
+            ewma_hit_rate: snapshot.ewma_hit_rate,
+            ewma_volatility: snapshot.ewma_volatility,
+            samples: snapshot.samples,
+            selector: ModeSelector::resume(cfg, snapshot.mode, snapshot.dwell_groups),
+        })
+    }
+
+    /// Rebuild the state after the group reported by `sidecar`, so a new node continues the
+    /// same score and mode sequence.
+    pub fn from_sidecar(
+        cfg: PredictabilityConfig,
+        sidecar: &RealtimePredictabilitySidecar,
+    ) -> Result<Self> {
+        sidecar.validate()?;
+        let m = &sidecar.metrics;
+        Self::restore(
+            cfg,
+            &PredictorSnapshot {
+                model: sidecar.model.clone(),
+                prev_hit_rate: Some(m.instant_hit_rate),
+                ewma_hit_rate: m.ewma_hit_rate,
+                ewma_volatility: m.ewma_volatility,
+                samples: m.samples,
+                mode: m.mode,
+                dwell_groups: m.dwell_groups,
+            },
+        )
+    }
+
+    /// Every step is rounded to the UNC-1 `dec/1` scale, as the normative `vsep1/ewma` formulas
+    /// specify, so the state is exactly what the sidecar publishes and a predictor resumed from
+    /// a sidecar continues bit-identically.
     pub fn observe(&mut self, observation: GroupObservation) -> RealtimePredictabilityMetrics {
-        let guessed = observation.guessed_tiles.max(1);
-        let correct = observation.correct_tiles.min(guessed);
-        let instant_hit_rate = correct as f64 / guessed as f64;
+        let instant_hit_rate = unc1::quantize(observation.hit_rate());
 
         let vol = match self.prev_hit_rate {
             Some(prev) => (instant_hit_rate - prev).abs(),
@@ -78,25 +308,19 @@ impl RealtimePredictor {
             self.ewma_hit_rate = instant_hit_rate;
             self.ewma_volatility = vol;
         } else {
-            self.ewma_hit_rate = a * instant_hit_rate + (1.0 - a) * self.ewma_hit_rate;
-            self.ewma_volatility = a * vol + (1.0 - a) * self.ewma_volatility;
+            self.ewma_hit_rate =
+                unc1::quantize(a * instant_hit_rate + (1.0 - a) * self.ewma_hit_rate);
+            self.ewma_volatility = unc1::quantize(a * vol + (1.0 - a) * self.ewma_volatility);
         }
 
         self.prev_hit_rate = Some(instant_hit_rate);
         self.samples += 1;
 
         let raw_score = self.ewma_hit_rate * (1.0 - self.ewma_volatility);
-        let predictability_score = raw_score.clamp(0.0, 1.0);
-
-        let mode = if self.samples < self.cfg.min_samples {
-            PolicyMode::Balanced
-        } else if predictability_score >= self.cfg.high_threshold {
-            PolicyMode::AggressiveGhost
-        } else if predictability_score < self.cfg.low_threshold {
-            PolicyMode::DownloadFirst
-        } else {
-            PolicyMode::Balanced
-        };
+        let predictability_score = unc1::quantize(raw_score.clamp(0.0, 1.0));
+        let (mode, transition) = self
+            .selector
+            .select(self.samples, predictability_score, vol);
 
         RealtimePredictabilityMetrics {
             instant_hit_rate,
@@ -105,62 +329,329 @@ impl RealtimePredictor {
             predictability_score,
             samples: self.samples,
             mode,
+            transition,
+            dwell_groups: self.selector.dwell_groups(),
         }
     }
 }
 
+impl PredictabilityModel for RealtimePredictor {
+    fn model_id(&self) -> &str {
+        EWMA_MODEL_ID
+    }
+
+    fn observe(&mut self, observation: GroupObservation) -> RealtimePredictabilityMetrics {
+        RealtimePredictor::observe(self, observation)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::realtime_sim::fixtures;
 
-    #[test]
-    fn stable_stream_converges_to_aggressive_ghost() {
+    fn replay(trace: &[GroupObservation]) -> RealtimePredictabilityMetrics {
         let mut predictor = RealtimePredictor::new(PredictabilityConfig::default());
-
-        let mut last = predictor.observe(GroupObservation {
-            guessed_tiles: 100,
-            correct_tiles: 94,
-        });
-        for _ in 0..9 {
-            last = predictor.observe(GroupObservation {
-                guessed_tiles: 100,
-                correct_tiles: 95,
-            });
+        let mut last = None;
+        for obs in trace {
+            last = Some(predictor.observe(*obs));
         }
+        last.unwrap()
+    }
 
+    #[test]
+    fn stable_stream_converges_to_aggressive_ghost() {
+        let last = replay(&fixtures::stable());
         assert!(last.predictability_score >= 0.85);
         assert_eq!(last.mode, PolicyMode::AggressiveGhost);
     }
 
     #[test]
     fn volatile_stream_falls_back_to_download_first() {
-        let mut predictor = RealtimePredictor::new(PredictabilityConfig::default());
+        let last = replay(&fixtures::volatile());
+        assert!(last.predictability_score < 0.60);
+        assert_eq!(last.mode, PolicyMode::DownloadFirst);
+    }
 
-        let observations = [95, 20, 92, 18, 90, 25, 93, 15];
-        let mut last = predictor.observe(GroupObservation {
+    fn feed(predictor: &mut RealtimePredictor, correct: u64) -> RealtimePredictabilityMetrics {
+        predictor.observe(GroupObservation {
             guessed_tiles: 100,
-            correct_tiles: observations[0],
-        });
-        for value in observations.into_iter().skip(1) {
-            last = predictor.observe(GroupObservation {
-                guessed_tiles: 100,
-                correct_tiles: value,
-            });
+            correct_tiles: correct,
+        })
+    }
+
+    #[test]
+    fn hysteresis_holds_mode_around_enter_threshold() {
+        let cfg = PredictabilityConfig {
+            high_exit_threshold: 0.80,
+            low_exit_threshold: 0.65,
+            ..PredictabilityConfig::default()
+        };
+        let mut predictor = RealtimePredictor::new(cfg);
+        let mut m = feed(&mut predictor, 90);
+        while m.mode != PolicyMode::AggressiveGhost {
+            m = feed(&mut predictor, 90);
         }
+        assert_eq!(
+            m.transition,
+            Some(ModeTransition {
+                from: PolicyMode::Balanced,
+                reason: TransitionReason::EnterThreshold,
+            })
+        );
 
-        assert!(last.predictability_score < 0.60);
-        assert_eq!(last.mode, PolicyMode::DownloadFirst);
+        // Hovering just under the enter threshold stays above the exit threshold.
+        let mut dipped = false;
+        for correct in [84, 86, 84, 86, 84, 86] {
+            let m = feed(&mut predictor, correct);
+            dipped |= m.predictability_score < 0.85;
+            assert_eq!(m.mode, PolicyMode::AggressiveGhost);
+            assert_eq!(m.transition, None);
+        }
+        assert!(dipped);
+
+        let mut m = feed(&mut predictor, 75);
+        while m.transition.is_none() {
+            m = feed(&mut predictor, 75);
+        }
+        assert_eq!(m.mode, PolicyMode::Balanced);
+        assert_eq!(
+            m.transition.unwrap().reason,
+            TransitionReason::ExitThreshold
+        );
+
+        // Without hysteresis, the default leaves on the first dip.
+        let mut predictor = RealtimePredictor::new(PredictabilityConfig::default());
+        while feed(&mut predictor, 90).mode != PolicyMode::AggressiveGhost {}
+        let m = [84, 86, 84, 86, 84, 86]
+            .into_iter()
+            .map(|correct| feed(&mut predictor, correct))
+            .find(|m| m.predictability_score < 0.85)
+            .unwrap();
+        assert_eq!(m.mode, PolicyMode::Balanced);
+        assert_eq!(
+            m.transition.unwrap().reason,
+            TransitionReason::ExitThreshold
+        );
     }
 
     #[test]
-    fn warmup_keeps_balanced_before_min_samples() {
+    fn every_step_is_rounded_to_the_dec_scale() {
+        let q = unc1::quantize;
         let mut predictor = RealtimePredictor::new(PredictabilityConfig::default());
-        for _ in 0..3 {
+        let (mut prev, mut ewma_hit, mut ewma_vol) = (None, 0.0, 0.0);
+        for (g, correct) in [1u64, 2, 6, 3, 5].into_iter().enumerate() {
             let m = predictor.observe(GroupObservation {
-                guessed_tiles: 100,
-                correct_tiles: 99,
+                guessed_tiles: 7,
+                correct_tiles: correct,
             });
-            assert_eq!(m.mode, PolicyMode::Balanced);
+            let hit = q(correct as f64 / 7.0);
+            let vol = prev.map_or(0.0, |p: f64| (hit - p).abs());
+            if g == 0 {
+                (ewma_hit, ewma_vol) = (hit, vol);
+            } else {
+                ewma_hit = q(0.25 * hit + 0.75 * ewma_hit);
+                ewma_vol = q(0.25 * vol + 0.75 * ewma_vol);
+            }
+            prev = Some(hit);
+            let score = q((ewma_hit * (1.0 - ewma_vol)).clamp(0.0, 1.0));
+            assert_eq!(
+                [
+                    m.instant_hit_rate,
+                    m.ewma_hit_rate,
+                    m.ewma_volatility,
+                    m.predictability_score
+                ]
+                .map(f64::to_bits),
+                [hit, ewma_hit, ewma_vol, score].map(f64::to_bits),
+                "group {}",
+                g
+            );
+        }
+    }
+
+    #[test]
+    fn dwell_delays_switch_but_spike_drops_immediately() {
+        let cfg = PredictabilityConfig {
+            min_dwell_groups: 5,
+            volatility_spike: None,
+            ..PredictabilityConfig::default()
+        };
+        let mut predictor = RealtimePredictor::new(cfg);
+        for _ in 0..4 {
+            feed(&mut predictor, 99);
+        }
+        let entered = (0..10)
+            .map(|_| feed(&mut predictor, 99))
+            .position(|m| m.transition.is_some())
+            .unwrap();
+        assert_eq!(entered, 1, "dwell counts groups since the stream started");
+
+        // After entering, the mode is held for `min_dwell_groups` groups however low the score.
+        let cfg = PredictabilityConfig {
+            min_dwell_groups: 3,
+            volatility_spike: None,
+            ..PredictabilityConfig::default()
+        };
+        let mut predictor = RealtimePredictor::new(cfg);
+        let m = (0..10)
+            .map(|_| feed(&mut predictor, 99))
+            .find(|m| m.transition.is_some())
+            .unwrap();
+        assert_eq!(m.mode, PolicyMode::AggressiveGhost);
+        let after: Vec<_> = (0..5).map(|_| feed(&mut predictor, 50)).collect();
+        for (i, m) in after[..3].iter().enumerate() {
+            assert!(m.predictability_score < cfg.high_exit_threshold);
+            assert_eq!((m.mode, m.transition), (PolicyMode::AggressiveGhost, None));
+            assert_eq!(m.dwell_groups, i as u64 + 1);
+        }
+        assert!(after[3].transition.is_some());
+        assert_ne!(after[3].mode, PolicyMode::AggressiveGhost);
+
+        let cfg = PredictabilityConfig {
+            min_dwell_groups: 2,
+            volatility_spike: Some(0.5),
+            ..PredictabilityConfig::default()
+        };
+        let mut predictor = RealtimePredictor::new(cfg);
+        for _ in 0..6 {
+            feed(&mut predictor, 99);
+        }
+        let m = feed(&mut predictor, 30);
+        assert_eq!(m.mode, PolicyMode::DownloadFirst);
+        assert_eq!(
+            m.transition,
+            Some(ModeTransition {
+                from: PolicyMode::AggressiveGhost,
+                reason: TransitionReason::VolatilitySpike,
+            })
+        );
+    }
+
+    /// Sliding-window mean of hit rate, scored without a volatility penalty.
+    struct WindowMean {
+        window: std::collections::VecDeque<f64>,
+        samples: u64,
+        selector: ModeSelector,
+    }
+
+    impl PredictabilityModel for WindowMean {
+        fn model_id(&self) -> &str {
+            "test/window-mean"
+        }
+
+        fn observe(&mut self, observation: GroupObservation) -> RealtimePredictabilityMetrics {
+            let hit = observation.hit_rate();
+            let vol = self.window.back().map_or(0.0, |p| (hit - p).abs());
+            self.window.push_back(hit);
+            if self.window.len() > 3 {
+                self.window.pop_front();
+            }
+            self.samples += 1;
+            let mean = self.window.iter().sum::<f64>() / self.window.len() as f64;
+            let (mode, transition) = self.selector.select(self.samples, mean, vol);
+            RealtimePredictabilityMetrics {
+                instant_hit_rate: hit,
+                ewma_hit_rate: mean,
+                ewma_volatility: vol,
+                predictability_score: mean,
+                samples: self.samples,
+                mode,
+                transition,
+                dwell_groups: self.selector.dwell_groups(),
+            }
+        }
+    }
+
+    #[test]
+    fn models_are_pluggable_behind_the_trait() {
+        let mut models: Vec<Box<dyn PredictabilityModel>> = vec![
+            Box::new(RealtimePredictor::new(PredictabilityConfig::default())),
+            Box::new(WindowMean {
+                window: Default::default(),
+                samples: 0,
+                selector: ModeSelector::new(PredictabilityConfig::default()),
+            }),
+        ];
+        for model in models.iter_mut() {
+            let mut last = None;
+            for _ in 0..8 {
+                last = Some(model.observe(GroupObservation {
+                    guessed_tiles: 50,
+                    correct_tiles: 49,
+                }));
+            }
+            assert_eq!(last.unwrap().mode, PolicyMode::AggressiveGhost);
+        }
+        assert_eq!(models[0].model_id(), EWMA_MODEL_ID);
+        assert_eq!(models[1].model_id(), "test/window-mean");
+    }
+
+    #[test]
+    fn snapshot_and_sidecar_resume_continue_identically() {
+        use crate::streaming_protocol::{RealtimeObserved, RealtimeWindow};
+
+        let trace = [95, 20, 92, 18, 90, 25, 93, 15, 88, 91, 90, 89, 92];
+        let cfg = PredictabilityConfig::default();
+        let mut reference = RealtimePredictor::new(cfg);
+        let expected: Vec<RealtimePredictabilityMetrics> =
+            trace.iter().map(|&c| feed(&mut reference, c)).collect();
+
+        for cut in [1, 4, 7] {
+            let mut primary = RealtimePredictor::new(cfg);
+            let mut last = None;
+            for &c in &trace[..cut] {
+                last = Some(feed(&mut primary, c));
+            }
+
+            let json = serde_json::to_value(primary.snapshot()).unwrap();
+            crate::validate_no_json_numbers(&json).unwrap();
+            let snap: PredictorSnapshot = serde_json::from_value(json).unwrap();
+            let mut restored = RealtimePredictor::restore(cfg, &snap).unwrap();
+
+            let sidecar = RealtimePredictabilitySidecar::from_metrics(
+                "a/ep/t/live".into(),
+                crate::Cid::blake3([1; 32]),
+                cut as u64,
+                RealtimeWindow {
+                    groups: 1,
+                    duration_ms: 500,
+                },
+                RealtimeObserved {
+                    guessed_tiles: 100,
+                    correct_tiles: trace[cut - 1],
+                    corrected_tiles: 100 - trace[cut - 1],
+                },
+                EWMA_MODEL_ID,
+                last.unwrap(),
+            );
+            let wire = serde_json::to_string(&sidecar).unwrap();
+            let sidecar: RealtimePredictabilitySidecar = serde_json::from_str(&wire).unwrap();
+            let mut failover = RealtimePredictor::from_sidecar(cfg, &sidecar).unwrap();
+
+            for (i, &c) in trace.iter().enumerate().skip(cut) {
+                for p in [&mut restored, &mut failover] {
+                    let m = feed(p, c);
+                    assert_eq!(
+                        m.predictability_score.to_bits(),
+                        expected[i].predictability_score.to_bits()
+                    );
+                    assert_eq!(m.mode, expected[i].mode);
+                    assert_eq!(m.transition, expected[i].transition);
+                }
+            }
+        }
+
+        let mut snap = reference.snapshot();
+        snap.model = "other".into();
+        assert!(RealtimePredictor::restore(cfg, &snap).is_err());
+    }
+
+    #[test]
+    fn warmup_keeps_balanced_before_min_samples() {
+        let mut predictor = RealtimePredictor::new(PredictabilityConfig::default());
+        for obs in fixtures::warmup() {
+            assert_eq!(predictor.observe(obs).mode, PolicyMode::Balanced);
         }
     }
 }
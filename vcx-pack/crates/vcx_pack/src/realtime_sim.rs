@@ -0,0 +1,303 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::chip::Chip;
+use crate::realtime_predictability::{
+    GroupObservation, PolicyMode, PredictabilityConfig, RealtimePredictor, EWMA_MODEL_ID,
+};
+use crate::streaming_protocol::{
+    PolicyHintConfig, RealtimeObserved, RealtimePredictabilitySidecar, RealtimeWindow,
+};
+use crate::Cid;
+
+/// Named observation traces shared by unit tests, the simulator and the CLI (`--fixture`).
+pub mod fixtures {
+    use super::GroupObservation;
+
+    pub const NAMES: [&str; 4] = ["stable", "volatile", "warmup", "hover-high"];
+
+    fn of(correct: &[u64]) -> Vec<GroupObservation> {
+        correct
+            .iter()
+            .map(|&c| GroupObservation {
+                guessed_tiles: 100,
+                correct_tiles: c,
+            })
+            .collect()
+    }
+
+    /// Steady ~95% hit rate; converges to `aggressive_ghost`.
+    pub fn stable() -> Vec<GroupObservation> {
+        let mut v = vec![94];
+        v.extend([95; 9]);
+        of(&v)
+    }
+
+    /// Alternating hits and misses; falls back to `download_first`.
+    pub fn volatile() -> Vec<GroupObservation> {
+        of(&[95, 20, 92, 18, 90, 25, 93, 15])
+    }
+
+    /// Fewer groups than `min_samples`; stays `balanced`.
+    pub fn warmup() -> Vec<GroupObservation> {
+        of(&[99, 99, 99])
+    }
+
+    /// Enters `aggressive_ghost`, then hovers just below the enter threshold.
+    pub fn hover_high() -> Vec<GroupObservation> {
+        let mut v = vec![90; 6];
+        v.extend([84, 86, 84, 86, 84, 86]);
+        of(&v)
+    }
+
+    pub fn by_name(name: &str) -> Option<Vec<GroupObservation>> {
+        Some(match name {
+            "stable" => stable(),
+            "volatile" => volatile(),
+            "warmup" => warmup(),
+            "hover-high" => hover_high(),
+            _ => return None,
+        })
+    }
+}
+
+/// Synthetic trace shapes.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SyntheticPattern {
+    /// ~95% hit rate with small noise.
+    Stable,
+    /// Alternating good and bad groups.
+    Volatile,
+    /// Stable shots broken by a cut every 24 groups (one bad group, one recovering).
+    SceneCut,
+}
+
+impl SyntheticPattern {
+    pub fn parse(name: &str) -> Result<Self> {
+        Ok(match name {
+            "stable" => Self::Stable,
+            "volatile" => Self::Volatile,
+            "scene-cut" | "scene_cut" => Self::SceneCut,
+            other => bail!("UnknownSyntheticPattern({})", other),
+        })
+    }
+}
+
+/// Deterministic trace for `pattern`; the same seed always yields the same trace.
+pub fn synthetic_trace(
+    pattern: SyntheticPattern,
+    groups: usize,
+    tiles_per_group: u64,
+    seed: u64,
+) -> Vec<GroupObservation> {
+    let mut state = seed;
+    let mut noise =
+        |amp: f64| ((splitmix64(&mut state) >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0) * amp;
+    (0..groups)
+        .map(|g| {
+            let hit = match pattern {
+                SyntheticPattern::Stable => 0.95 + noise(0.02),
+                SyntheticPattern::Volatile if g % 2 == 0 => 0.90 + noise(0.05),
+                SyntheticPattern::Volatile => 0.20 + noise(0.05),
+                SyntheticPattern::SceneCut => match g % 24 {
+                    0 if g > 0 => 0.15 + noise(0.05),
+                    1 if g > 1 => 0.60 + noise(0.05),
+                    _ => 0.93 + noise(0.02),
+                },
+            };
+            let correct = (hit.clamp(0.0, 1.0) * tiles_per_group as f64).round() as u64;
+            GroupObservation {
+                guessed_tiles: tiles_per_group,
+                correct_tiles: correct.min(tiles_per_group),
+            }
+        })
+        .collect()
+}
+
+/// splitmix64 step; the shared deterministic PRNG of the simulators and ghost synthesis.
+pub(crate) fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Parse an NDJSON trace: one `{"guessed_tiles":..,"correct_tiles":..}` per line; blank lines
+/// are skipped.
+pub fn parse_trace(text: &str) -> Result<Vec<GroupObservation>> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(i, l)| serde_json::from_str(l).with_context(|| format!("BadTraceLine({})", i + 1)))
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct SimConfig {
+    pub predictor: PredictabilityConfig,
+    pub hints: PolicyHintConfig,
+    pub group_duration_ms: u64,
+    pub world: String,
+    pub target_manifest: Cid,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            predictor: PredictabilityConfig::default(),
+            hints: PolicyHintConfig::default(),
+            group_duration_ms: 500,
+            world: "a/sim/t/realtime".to_string(),
+            target_manifest: Cid::blake3([0; 32]),
+        }
+    }
+}
+
+/// Aggregate outcome of a replay. Speculation for a group follows the hint published for the
+/// previous group (the player applies it on the next cycle); nothing is speculated before the
+/// first hint. Wasted tiles are the speculated share that the group's hit rate says was wrong.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct SimSummary {
+    pub groups: u64,
+    pub aggressive_ghost_groups: u64,
+    pub balanced_groups: u64,
+    pub download_first_groups: u64,
+    pub transitions: u64,
+    pub speculated_tiles: u64,
+    pub wasted_speculative_tiles: u64,
+    pub mean_score: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimReport {
+    pub model: String,
+    pub summary: SimSummary,
+    pub sidecars: Vec<RealtimePredictabilitySidecar>,
+}
+
+/// Replay `trace` through a fresh `RealtimePredictor`, emitting one validated, sealed realtime
+/// sidecar per group.
+pub fn simulate(trace: &[GroupObservation], cfg: &SimConfig) -> Result<SimReport> {
+    let mut predictor = RealtimePredictor::new(cfg.predictor);
+    let mut summary = SimSummary::default();
+    let mut sidecars = Vec::with_capacity(trace.len());
+    let mut budget = 0u64;
+    let mut score_sum = 0.0;
+
+    for (seq, obs) in trace.iter().enumerate() {
+        let metrics = predictor.observe(*obs);
+        let correct = obs.correct_tiles.min(obs.guessed_tiles);
+
+        let speculated = budget.min(obs.guessed_tiles);
+        let hit = speculated * correct / obs.guessed_tiles.max(1);
+        summary.speculated_tiles += speculated;
+        summary.wasted_speculative_tiles += speculated - hit;
+
+        summary.groups += 1;
+        match metrics.mode {
+            PolicyMode::AggressiveGhost => summary.aggressive_ghost_groups += 1,
+            PolicyMode::Balanced => summary.balanced_groups += 1,
+            PolicyMode::DownloadFirst => summary.download_first_groups += 1,
+        }
+        summary.transitions += metrics.transition.is_some() as u64;
+        score_sum += metrics.predictability_score;
+
+        let mut sidecar = RealtimePredictabilitySidecar::from_metrics(
+            cfg.world.clone(),
+            cfg.target_manifest,
+            seq as u64,
+            RealtimeWindow {
+                groups: 1,
+                duration_ms: cfg.group_duration_ms,
+            },
+            RealtimeObserved {
+                guessed_tiles: obs.guessed_tiles,
+                correct_tiles: correct,
+                corrected_tiles: obs.guessed_tiles - correct,
+            },
+            EWMA_MODEL_ID,
+            metrics,
+        )
+        .with_hint_config(&cfg.hints);
+        sidecar
+            .validate()
+            .with_context(|| format!("InvalidSimulatedSidecar({})", seq))?;
+        sidecar.seal()?;
+        budget = sidecar.policy_hint.max_speculative_tiles_per_group as u64;
+        sidecars.push(sidecar);
+    }
+    if summary.groups > 0 {
+        summary.mean_score = score_sum / summary.groups as f64;
+    }
+    Ok(SimReport {
+        model: EWMA_MODEL_ID.to_string(),
+        summary,
+        sidecars,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixtures_replay_to_expected_summaries() {
+        let cfg = SimConfig::default();
+        let stable = simulate(&fixtures::stable(), &cfg).unwrap();
+        assert_eq!(stable.sidecars.len(), 10);
+        for sidecar in &stable.sidecars {
+            sidecar.verify_id().unwrap();
+        }
+        assert_eq!(stable.summary.transitions, 1);
+        assert_eq!(
+            stable.sidecars.last().unwrap().metrics.mode,
+            PolicyMode::AggressiveGhost
+        );
+
+        let volatile = simulate(&fixtures::volatile(), &cfg).unwrap();
+        assert!(volatile.summary.download_first_groups > 0);
+        assert!(volatile.summary.wasted_speculative_tiles > 0);
+
+        let warmup = simulate(&fixtures::warmup(), &cfg).unwrap();
+        assert_eq!(warmup.summary.balanced_groups, 3);
+        assert_eq!(warmup.summary.transitions, 0);
+
+        for name in fixtures::NAMES {
+            assert!(fixtures::by_name(name).is_some(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn synthetic_traces_are_deterministic_and_parse_back() {
+        for pattern in [
+            SyntheticPattern::Stable,
+            SyntheticPattern::Volatile,
+            SyntheticPattern::SceneCut,
+        ] {
+            let a = synthetic_trace(pattern, 60, 200, 7);
+            assert_eq!(a, synthetic_trace(pattern, 60, 200, 7));
+            assert!(a.iter().all(|o| o.correct_tiles <= o.guessed_tiles));
+
+            let ndjson: String = a
+                .iter()
+                .map(|o| serde_json::to_string(o).unwrap() + "\n")
+                .collect();
+            assert_eq!(parse_trace(&ndjson).unwrap(), a);
+        }
+        let err = parse_trace("{\"guessed_tiles\":1,\"correct_tiles\":1}\n\nnope\n").unwrap_err();
+        assert!(err.to_string().contains("BadTraceLine(3)"));
+
+        let cfg = SimConfig::default();
+        let stable =
+            simulate(&synthetic_trace(SyntheticPattern::Stable, 60, 200, 1), &cfg).unwrap();
+        let cuts = simulate(
+            &synthetic_trace(SyntheticPattern::SceneCut, 60, 200, 1),
+            &cfg,
+        )
+        .unwrap();
+        assert!(stable.summary.aggressive_ghost_groups > 50);
+        assert!(cuts.summary.transitions >= 2);
+    }
+}
@@ -0,0 +1,235 @@
+use anyhow::{anyhow, bail, Result};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::Cursor;
+use std::ops::RangeInclusive;
+
+use crate::manifest::Manifest;
+use crate::{
+    read_and_verify_redacted_pack, Cid, IndexEntry, MimeTag, Pack, INDEX_FLAG_REDACTED,
+    INDEX_HEADER_LEN,
+};
+
+/// A tombstoned payload and where the manifest refers to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redaction {
+    pub cid: Cid,
+    pub mime_tag: MimeTag,
+    pub payload_len: u64,
+    /// Manifest paths of the references (`gots[3].tiles[5]`, `audio`, ...); `None` when the
+    /// manifest does not decode with the typed schema.
+    pub paths: Option<Vec<String>>,
+}
+
+impl fmt::Display for Redaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let at = match &self.paths {
+            None => "unknown paths".to_string(),
+            Some(p) if p.is_empty() => "unreferenced".to_string(),
+            Some(p) => p.join(", "),
+        };
+        write!(
+            f,
+            "Redacted({} {:?} len={} at {})",
+            self.cid, self.mime_tag, self.payload_len, at
+        )
+    }
+}
+
+/// The redactions of a pack read with [`read_and_verify_redacted_pack`], in index order. The
+/// entries are listed without paths when the manifest is not a typed [`Manifest`].
+pub fn redactions(pack: &Pack) -> Vec<Redaction> {
+    if pack.redacted_entries().next().is_none() {
+        return Vec::new();
+    }
+    let manifest = Manifest::from_nrf(&pack.manifest_bytes).ok();
+    let refs = manifest.as_ref().map(Manifest::references);
+    pack.redacted_entries()
+        .map(|e| Redaction {
+            cid: e.cid,
+            mime_tag: e.mime_tag,
+            payload_len: e.payload_len,
+            paths: refs.as_ref().map(|refs| {
+                refs.iter()
+                    .filter(|r| r.cid == e.cid)
+                    .map(|r| r.path.clone())
+                    .collect()
+            }),
+        })
+        .collect()
+}
+
+/// Tile CIDs of a frame region: groups `groups`, tiles with `tile_x` in `xs` and `tile_y` in
+/// `ys`. Tiles without coordinates are never selected. A CID is a payload, so redacting one
+/// also blanks every other tile with the same bytes.
+pub fn region_cids(
+    manifest: &Manifest,
+    groups: RangeInclusive<usize>,
+    xs: RangeInclusive<u32>,
+    ys: RangeInclusive<u32>,
+) -> Vec<Cid> {
+    let mut out = BTreeSet::new();
+    for got in manifest
+        .gots
+        .iter()
+        .skip(*groups.start())
+        .take(groups.clone().count())
+    {
+        for tile in &got.tiles {
+            if let (Some(x), Some(y)) = (tile.tile_x, tile.tile_y) {
+                if xs.contains(&x) && ys.contains(&y) {
+                    out.insert(tile.cid);
+                }
+            }
+        }
+    }
+    out.into_iter().collect()
+}
+
+/// Tombstone the payloads `cids`: zero their bytes in place and set [`INDEX_FLAG_REDACTED`]
+/// on their index entries. Layout, manifest and payload hashes stay as they are and the index
+/// leaf ignores the flag, so the Merkle root (and any proof or signature over it) stays valid.
+/// Payloads cannot be cut out of the file instead: the index commits to every payload offset.
+/// Redacting an already redacted payload changes nothing.
+pub fn redact_pack(pack_bytes: &[u8], cids: &[Cid]) -> Result<Vec<u8>> {
+    let pack = read_and_verify_redacted_pack(Cursor::new(pack_bytes), true)?;
+    let mut out = pack_bytes.to_vec();
+    for cid in cids {
+        let i = pack
+            .index_entries
+            .binary_search_by(|e| e.cid.cmp(cid))
+            .map_err(|_| anyhow!("RedactionTargetNotInIndex({})", cid))?;
+        let e = &pack.index_entries[i];
+        out[e.payload_off as usize..(e.payload_off + e.payload_len) as usize].fill(0);
+        let at = pack.header.index_off as usize
+            + INDEX_HEADER_LEN
+            + i * IndexEntry::LEN
+            + IndexEntry::FLAGS_OFFSET;
+        out[at..at + 2].copy_from_slice(&(e.flags | INDEX_FLAG_REDACTED).to_le_bytes());
+    }
+    let redacted = read_and_verify_redacted_pack(Cursor::new(&out), true)?;
+    if redacted.merkle.root != pack.merkle.root {
+        bail!("Internal:RedactionChangedRoot");
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::{build_pack, cid_for_payload_bytes, read_and_verify_pack};
+
+    fn tile(f: u8, x: u32) -> Vec<u8> {
+        vec![f * 16 + x as u8 + 1; 40]
+    }
+
+    /// Two groups of two tiles side by side, plus audio.
+    fn pack() -> (Manifest, Vec<u8>) {
+        let groups: Vec<Vec<Cid>> = (0..2u8)
+            .map(|f| {
+                (0..2)
+                    .map(|x| cid_for_payload_bytes(&tile(f, x)).unwrap())
+                    .collect()
+            })
+            .collect();
+        let manifest = Manifest {
+            audio: Some(fixtures::opus(
+                cid_for_payload_bytes(b"OggS audio").unwrap(),
+            )),
+            ..fixtures::manifest(&groups, 3000)
+        };
+        let mut out = Cursor::new(Vec::new());
+        build_pack(&mut out, &manifest.to_json().unwrap(), payloads(), true).unwrap();
+        (manifest, out.into_inner())
+    }
+
+    fn payloads() -> Vec<(MimeTag, Vec<u8>)> {
+        let mut payloads = vec![(MimeTag::Opus, b"OggS audio".to_vec())];
+        for f in 0..2 {
+            for x in 0..2 {
+                payloads.push((MimeTag::Ic0Tile, tile(f, x)));
+            }
+        }
+        payloads
+    }
+
+    #[test]
+    fn redaction_keeps_the_root_and_is_reported() {
+        let (manifest, bytes) = pack();
+        let original = read_and_verify_pack(Cursor::new(&bytes), true).unwrap();
+
+        let region = region_cids(&manifest, 1..=1, 1..=1, 0..=0);
+        assert_eq!(region, [manifest.gots[1].tiles[1].cid]);
+        let audio = manifest.audio.as_ref().unwrap().cid;
+        let redacted = redact_pack(&bytes, &[region[0], audio]).unwrap();
+        assert_eq!(redacted.len(), bytes.len());
+
+        assert!(read_and_verify_pack(Cursor::new(&redacted), false)
+            .unwrap_err()
+            .to_string()
+            .contains("PackHasRedactions(2)"));
+        let pack = read_and_verify_redacted_pack(Cursor::new(&redacted), true).unwrap();
+        assert_eq!(pack.merkle.root, original.merkle.root);
+        for e in pack.redacted_entries() {
+            let range = e.payload_off as usize..(e.payload_off + e.payload_len) as usize;
+            assert!(redacted[range].iter().all(|b| *b == 0));
+        }
+        let report = redactions(&pack);
+        let shown: Vec<String> = report.iter().map(|r| r.to_string()).collect();
+        assert_eq!(report.len(), 2);
+        assert!(shown.iter().any(|s| s.ends_with("Opus len=10 at audio)")));
+        assert!(shown.iter().any(|s| s.ends_with("at gots[1].tiles[1])")));
+        assert!(pack.verify_references().unwrap().is_ok());
+
+        // A manifest outside the typed schema still lists its redactions, without paths.
+        let mut loose = manifest.to_json().unwrap();
+        loose["x-note"] = serde_json::json!("not in the schema");
+        let mut out = Cursor::new(Vec::new());
+        build_pack(&mut out, &loose, payloads(), true).unwrap();
+        let loose = redact_pack(&out.into_inner(), &[audio]).unwrap();
+        let pack = read_and_verify_redacted_pack(Cursor::new(&loose), true).unwrap();
+        let report = redactions(&pack);
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].paths, None);
+        assert!(report[0].to_string().ends_with("at unknown paths)"));
+
+        // Idempotent, and unknown CIDs are refused.
+        assert_eq!(redact_pack(&redacted, &[audio]).unwrap(), redacted);
+        assert!(redact_pack(&bytes, &[Cid::blake3([0; 32])]).is_err());
+    }
+
+    #[test]
+    fn forged_redactions_are_rejected() {
+        let (manifest, bytes) = pack();
+        let target = manifest.gots[0].tiles[0].cid;
+        let redacted = redact_pack(&bytes, &[target]).unwrap();
+        let pack = read_and_verify_redacted_pack(Cursor::new(&redacted), false).unwrap();
+        let i = pack
+            .index_entries
+            .iter()
+            .position(|e| e.cid == target)
+            .unwrap();
+        let flags_at = pack.header.index_off as usize
+            + INDEX_HEADER_LEN
+            + i * IndexEntry::LEN
+            + IndexEntry::FLAGS_OFFSET;
+
+        // Flagged but not zeroed: the bytes are still there.
+        let mut flagged = bytes.clone();
+        flagged[flags_at] |= INDEX_FLAG_REDACTED as u8;
+        let err = read_and_verify_redacted_pack(Cursor::new(&flagged), true).unwrap_err();
+        assert!(err.to_string().contains("RedactedPayloadNotZeroed"));
+
+        // Zeroed but not flagged: an ordinary hash mismatch.
+        let mut zeroed = redacted.clone();
+        zeroed[flags_at] &= !(INDEX_FLAG_REDACTED as u8);
+        assert!(read_and_verify_redacted_pack(Cursor::new(&zeroed), true).is_err());
+
+        // Other flag bits are not defined.
+        let mut unknown = redacted;
+        unknown[flags_at] |= 0x02;
+        let err = read_and_verify_redacted_pack(Cursor::new(&unknown), false).unwrap_err();
+        assert!(err.to_string().contains("UnsupportedIndexEntryFlags"));
+    }
+}
@@ -1,91 +1,238 @@
 use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 
-use crate::realtime_predictability::{PolicyMode, RealtimePredictabilityMetrics};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::chip::Chip;
+use crate::realtime_predictability::{
+    PolicyMode, PredictabilityConfig, RealtimePredictabilityMetrics, EWMA_MODEL_ID,
+};
+use crate::tile_index::{RegionMap, TileGrid};
+use crate::unc1;
+use crate::{B3Digest, Cid};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RealtimeWindow {
+    #[serde(with = "unc1::int")]
     pub groups: u32,
+    #[serde(with = "unc1::int")]
     pub duration_ms: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RealtimeObserved {
+    #[serde(with = "unc1::int")]
     pub guessed_tiles: u64,
+    #[serde(with = "unc1::int")]
     pub correct_tiles: u64,
+    #[serde(with = "unc1::int")]
     pub corrected_tiles: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct RealtimePolicyHint {
     pub mode: PolicyMode,
+    #[serde(with = "unc1::int")]
     pub max_speculative_tiles_per_group: u32,
+    #[serde(with = "unc1::int")]
     pub prefetch_depth_groups: u32,
 }
 
+/// VSEP-1 (speculative tiles, prefetch depth) for download_first, balanced, aggressive_ghost.
+const HINT_ANCHORS: [(u32, u32); 3] = [(32, 6), (192, 3), (384, 2)];
+
+impl RealtimePolicyHint {
+    /// Default three-step hint for `mode`.
+    pub fn for_mode(mode: PolicyMode) -> Self {
+        let (max_speculative_tiles_per_group, prefetch_depth_groups) = match mode {
+            PolicyMode::DownloadFirst => HINT_ANCHORS[0],
+            PolicyMode::Balanced => HINT_ANCHORS[1],
+            PolicyMode::AggressiveGhost => HINT_ANCHORS[2],
+        };
+        Self {
+            mode,
+            max_speculative_tiles_per_group,
+            prefetch_depth_groups,
+        }
+    }
+}
+
+/// Bandwidth available to the player relative to what one group costs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BandwidthEstimate {
+    pub bits_per_second: u64,
+    pub bytes_per_group: u64,
+    pub group_duration_ms: u64,
+}
+
+impl BandwidthEstimate {
+    /// Fraction of a group's bytes that can be fetched within one group duration.
+    pub fn coverage(&self) -> f64 {
+        let fetchable = self.bits_per_second as f64 * self.group_duration_ms as f64 / 8000.0;
+        fetchable / self.bytes_per_group.max(1) as f64
+    }
+}
+
+/// Derivation of `policy_hint` from metrics. The default is the VSEP-1 three-step mapping with
+/// absolute budgets (384/2, 192/3, 32/6), so sidecars stay comparable across producers.
+///
+/// With `tiles_per_group` set, the speculative budget is scaled by
+/// `tiles_per_group / reference_tiles_per_group` and capped at `tiles_per_group`. With
+/// `continuous`, budgets are interpolated linearly on `predictability_score` between the
+/// `download_first` anchor at `low_score`, the `balanced` anchor half way and the
+/// `aggressive_ghost` anchor at `high_score`. A `bandwidth` estimate below full coverage raises
+/// the speculative budget to the tiles that cannot arrive in time and shortens prefetch depth
+/// in the same proportion.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PolicyHintConfig {
+    pub tiles_per_group: Option<u32>,
+    pub reference_tiles_per_group: u32,
+    pub continuous: bool,
+    pub low_score: f64,
+    pub high_score: f64,
+    pub bandwidth: Option<BandwidthEstimate>,
+}
+
+impl Default for PolicyHintConfig {
+    fn default() -> Self {
+        let cfg = PredictabilityConfig::default();
+        Self {
+            tiles_per_group: None,
+            reference_tiles_per_group: 512,
+            continuous: false,
+            low_score: cfg.low_threshold,
+            high_score: cfg.high_threshold,
+            bandwidth: None,
+        }
+    }
+}
+
+impl PolicyHintConfig {
+    /// Use the largest tile count of any group in `manifest`.
+    pub fn with_manifest(mut self, manifest: &Value) -> Self {
+        self.tiles_per_group = manifest
+            .get("gots")
+            .and_then(Value::as_array)
+            .and_then(|gots| {
+                gots.iter()
+                    .filter_map(|g| g.get("tiles").and_then(Value::as_array))
+                    .map(|t| t.len() as u32)
+                    .max()
+            });
+        self
+    }
+
+    pub fn derive(&self, metrics: &RealtimePredictabilityMetrics) -> RealtimePolicyHint {
+        let (spec, depth) = if self.continuous {
+            let span = (self.high_score - self.low_score).max(f64::EPSILON);
+            let t = ((metrics.predictability_score - self.low_score) / span).clamp(0.0, 1.0) * 2.0;
+            let (lo, hi) = if t < 1.0 {
+                (HINT_ANCHORS[0], HINT_ANCHORS[1])
+            } else {
+                (HINT_ANCHORS[1], HINT_ANCHORS[2])
+            };
+            let f = if t < 1.0 { t } else { t - 1.0 };
+            let lerp = |a: u32, b: u32| a as f64 + (b as f64 - a as f64) * f;
+            (lerp(lo.0, hi.0), lerp(lo.1, hi.1))
+        } else {
+            let step = RealtimePolicyHint::for_mode(metrics.mode);
+            (
+                step.max_speculative_tiles_per_group as f64,
+                step.prefetch_depth_groups as f64,
+            )
+        };
+
+        let mut spec = match self.tiles_per_group {
+            Some(tiles) => (spec * tiles as f64 / self.reference_tiles_per_group.max(1) as f64)
+                .min(tiles as f64),
+            None => spec,
+        };
+        let mut depth = depth;
+        if let Some(bw) = self.bandwidth {
+            let coverage = bw.coverage();
+            if coverage < 1.0 {
+                if let Some(tiles) = self.tiles_per_group {
+                    spec = spec.max((1.0 - coverage) * tiles as f64);
+                }
+                depth = (depth * coverage).max(1.0);
+            }
+        }
+
+        RealtimePolicyHint {
+            mode: metrics.mode,
+            max_speculative_tiles_per_group: spec.round() as u32,
+            prefetch_depth_groups: depth.round() as u32,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RealtimePredictabilitySidecar {
     #[serde(rename = "@type")]
     pub chip_type: String,
-    #[serde(rename = "@id")]
-    pub chip_id: String,
+    /// The chip's CID, set by `Chip::seal`; absent until then.
+    #[serde(rename = "@id", default, skip_serializing_if = "Option::is_none")]
+    pub chip_id: Option<Cid>,
     #[serde(rename = "@ver")]
     pub chip_ver: String,
     #[serde(rename = "@world")]
     pub world: String,
 
-    pub target_manifest: String,
+    pub target_manifest: Cid,
+    #[serde(with = "unc1::int")]
     pub group_seq: u64,
     pub window: RealtimeWindow,
     pub observed: RealtimeObserved,
+    /// Predictability model that produced `metrics`; absent means the VSEP-1 EWMA model.
+    #[serde(default = "default_model")]
+    pub model: String,
     pub metrics: RealtimePredictabilityMetrics,
     pub policy_hint: RealtimePolicyHint,
 }
 
+fn default_model() -> String {
+    EWMA_MODEL_ID.to_string()
+}
+
 impl RealtimePredictabilitySidecar {
+    /// Unsealed: `@id` is empty until [`Chip::seal`](crate::chip::Chip::seal) is called on
+    /// the finished sidecar.
+    #[allow(clippy::too_many_arguments)]
     pub fn from_metrics(
-        chip_id: String,
         world: String,
-        target_manifest: String,
+        target_manifest: Cid,
         group_seq: u64,
         window: RealtimeWindow,
         observed: RealtimeObserved,
+        model: &str,
         metrics: RealtimePredictabilityMetrics,
     ) -> Self {
-        let policy_hint = match metrics.mode {
-            PolicyMode::AggressiveGhost => RealtimePolicyHint {
-                mode: PolicyMode::AggressiveGhost,
-                max_speculative_tiles_per_group: 384,
-                prefetch_depth_groups: 2,
-            },
-            PolicyMode::Balanced => RealtimePolicyHint {
-                mode: PolicyMode::Balanced,
-                max_speculative_tiles_per_group: 192,
-                prefetch_depth_groups: 3,
-            },
-            PolicyMode::DownloadFirst => RealtimePolicyHint {
-                mode: PolicyMode::DownloadFirst,
-                max_speculative_tiles_per_group: 32,
-                prefetch_depth_groups: 6,
-            },
-        };
+        let policy_hint = PolicyHintConfig::default().derive(&metrics);
 
         Self {
-            chip_type: "vcx/sidecar.predictability.realtime".to_string(),
-            chip_id,
+            chip_type: Self::CHIP_TYPE.to_string(),
+            chip_id: None,
             chip_ver: "1.0".to_string(),
             world,
             target_manifest,
             group_seq,
             window,
             observed,
+            model: model.to_string(),
             metrics,
             policy_hint,
         }
     }
 
+    /// Re-derive `policy_hint` with a non-default configuration.
+    pub fn with_hint_config(mut self, cfg: &PolicyHintConfig) -> Self {
+        self.policy_hint = cfg.derive(&self.metrics);
+        self
+    }
+
     pub fn validate(&self) -> Result<()> {
-        if self.chip_type != "vcx/sidecar.predictability.realtime" {
+        if self.chip_type != Self::CHIP_TYPE {
             bail!("UnexpectedRealtimeChipType({})", self.chip_type);
         }
         if self.window.groups == 0 || self.window.duration_ms == 0 {
@@ -94,6 +241,9 @@ impl RealtimePredictabilitySidecar {
         if self.observed.correct_tiles > self.observed.guessed_tiles {
             bail!("InvalidObservedCounters(correct>guessed)");
         }
+        if self.model.is_empty() {
+            bail!("MissingPredictabilityModel");
+        }
         if !(0.0..=1.0).contains(&self.metrics.predictability_score) {
             bail!("InvalidPredictabilityScore");
         }
@@ -101,7 +251,7 @@ impl RealtimePredictabilitySidecar {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum VodRegionStrategy {
     HoldAndNoise,
@@ -111,51 +261,93 @@ pub enum VodRegionStrategy {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VodGlobalStats {
+    #[serde(with = "unc1::dec")]
     pub volatility_score: f64,
+    #[serde(with = "unc1::int")]
     pub average_shot_length_ms: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VodRegionRule {
     pub region_id: String,
+    /// Inclusive slot range; see [`crate::tile_index::TileGrid`].
+    #[serde(with = "unc1::int")]
     pub tile_range_start: u32,
+    #[serde(with = "unc1::int")]
     pub tile_range_end: u32,
+    /// Inclusive group range; absent means every group.
+    #[serde(
+        default,
+        with = "unc1::opt_int",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub group_range_start: Option<u32>,
+    #[serde(
+        default,
+        with = "unc1::opt_int",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub group_range_end: Option<u32>,
     pub strategy: VodRegionStrategy,
+    #[serde(with = "unc1::dec")]
     pub confidence: f64,
 }
 
+impl VodRegionRule {
+    pub fn group_range(&self) -> Result<(u32, u32)> {
+        match (self.group_range_start, self.group_range_end) {
+            (None, None) => Ok((0, u32::MAX)),
+            (Some(s), Some(e)) if s <= e => Ok((s, e)),
+            _ => bail!("InvalidGroupRange({})", self.region_id),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VodPredictabilitySidecar {
     #[serde(rename = "@type")]
     pub chip_type: String,
-    #[serde(rename = "@id")]
-    pub chip_id: String,
+    /// The chip's CID, set by `Chip::seal`; absent until then.
+    #[serde(rename = "@id", default, skip_serializing_if = "Option::is_none")]
+    pub chip_id: Option<Cid>,
     #[serde(rename = "@ver")]
     pub chip_ver: String,
     #[serde(rename = "@world")]
     pub world: String,
 
-    pub target_manifest: String,
+    pub target_manifest: Cid,
     pub global_stats: VodGlobalStats,
     pub regions: Vec<VodRegionRule>,
 }
 
 impl VodPredictabilitySidecar {
     pub fn validate(&self) -> Result<()> {
-        if self.chip_type != "vcx/sidecar.predictability.vod" {
+        if self.chip_type != Self::CHIP_TYPE {
             bail!("UnexpectedVodChipType({})", self.chip_type);
         }
         if !(0.0..=1.0).contains(&self.global_stats.volatility_score) {
             bail!("InvalidVodVolatilityScore");
         }
         for r in &self.regions {
-            if r.tile_range_start > r.tile_range_end {
-                bail!("InvalidTileRange({})", r.region_id);
-            }
             if !(0.0..=1.0).contains(&r.confidence) {
                 bail!("InvalidVodConfidence({})", r.region_id);
             }
         }
+        RegionMap::new(&self.regions)?;
+        Ok(())
+    }
+
+    /// `validate` plus every region inside the target manifest's grid.
+    pub fn validate_for_grid(&self, grid: &TileGrid) -> Result<()> {
+        self.validate()?;
+        for r in &self.regions {
+            if r.tile_range_end >= grid.slots_per_group() {
+                bail!("TileRangeOutsideGrid({})", r.region_id);
+            }
+            if r.group_range_end.is_some_and(|g| g >= grid.groups) {
+                bail!("GroupRangeOutsideGrid({})", r.region_id);
+            }
+        }
         Ok(())
     }
 }
@@ -164,63 +356,274 @@ impl VodPredictabilitySidecar {
 #[serde(tag = "kind", rename_all = "snake_case")]
 pub enum EditOperation {
     Trim {
+        #[serde(with = "unc1::int")]
         start_ms: u64,
+        #[serde(with = "unc1::int")]
         end_ms: u64,
     },
     SpliceInsert {
+        #[serde(with = "unc1::int")]
         at_ms: u64,
-        source_manifest: String,
+        source_manifest: Cid,
     },
     SwapTrackRef {
         track: String,
-        from_cid: String,
-        to_cid: String,
+        from_cid: Cid,
+        to_cid: Cid,
     },
     OverlayRef {
-        asset_cid: String,
+        asset_cid: Cid,
+        #[serde(with = "unc1::int")]
         x: i32,
+        #[serde(with = "unc1::int")]
         y: i32,
+        #[serde(with = "unc1::int")]
         from_ms: u64,
+        #[serde(with = "unc1::int")]
         to_ms: u64,
     },
+    /// Tile-aligned region of interest, in tile units of the manifest grid.
+    Crop {
+        #[serde(with = "unc1::int")]
+        tile_x: u32,
+        #[serde(with = "unc1::int")]
+        tile_y: u32,
+        #[serde(with = "unc1::int")]
+        tiles_w: u32,
+        #[serde(with = "unc1::int")]
+        tiles_h: u32,
+    },
+    /// Concatenate the given segments of the current timeline in order.
+    Reorder { segments: Vec<EditSegment> },
+    /// Fixed-ratio speed change: an integer ratio decimates groups, a reciprocal duplicates them.
+    SpeedChange {
+        #[serde(with = "unc1::int")]
+        ratio_num: u32,
+        #[serde(with = "unc1::int")]
+        ratio_den: u32,
+    },
+    MuteAudio {
+        #[serde(with = "unc1::int")]
+        start_ms: u64,
+        #[serde(with = "unc1::int")]
+        end_ms: u64,
+    },
+    ReplaceAudio {
+        #[serde(with = "unc1::int")]
+        start_ms: u64,
+        #[serde(with = "unc1::int")]
+        end_ms: u64,
+        source_cid: Cid,
+    },
+    Crossfade {
+        #[serde(with = "unc1::int")]
+        at_ms: u64,
+        #[serde(with = "unc1::int")]
+        duration_ms: u64,
+    },
+    /// Replace the timeline and tracks with those of a referenced manifest (undo).
+    Revert { manifest: Cid },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EditSegment {
+    #[serde(with = "unc1::int")]
+    pub start_ms: u64,
+    #[serde(with = "unc1::int")]
+    pub end_ms: u64,
+}
+
+impl EditOperation {
+    /// Whether the operation can be applied by rewriting references only. Operations that
+    /// regenerate pixels or audio samples force `reencode_required`.
+    pub fn by_reference(&self) -> bool {
+        match self {
+            EditOperation::Trim { .. }
+            | EditOperation::SpliceInsert { .. }
+            | EditOperation::SwapTrackRef { .. }
+            | EditOperation::OverlayRef { .. }
+            | EditOperation::Crop { .. }
+            | EditOperation::Reorder { .. }
+            | EditOperation::SpeedChange { .. }
+            | EditOperation::Revert { .. } => true,
+            EditOperation::MuteAudio { .. }
+            | EditOperation::ReplaceAudio { .. }
+            | EditOperation::Crossfade { .. } => false,
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            EditOperation::Trim { start_ms, end_ms } if start_ms >= end_ms => {
+                bail!("InvalidTrimRange");
+            }
+            EditOperation::OverlayRef { from_ms, to_ms, .. } if from_ms >= to_ms => {
+                bail!("InvalidOverlayRange");
+            }
+            EditOperation::Crop {
+                tiles_w, tiles_h, ..
+            } if *tiles_w == 0 || *tiles_h == 0 => {
+                bail!("InvalidCropSize");
+            }
+            EditOperation::Reorder { segments } => {
+                if segments.is_empty() {
+                    bail!("ReorderMustHaveSegments");
+                }
+                let mut sorted = segments.clone();
+                sorted.sort_by_key(|s| s.start_ms);
+                for s in &sorted {
+                    if s.start_ms >= s.end_ms {
+                        bail!("InvalidReorderSegment");
+                    }
+                }
+                if sorted.windows(2).any(|w| w[1].start_ms < w[0].end_ms) {
+                    bail!("OverlappingReorderSegments");
+                }
+            }
+            EditOperation::SpeedChange {
+                ratio_num,
+                ratio_den,
+            } => {
+                if *ratio_num == 0 || *ratio_den == 0 {
+                    bail!("InvalidSpeedRatio");
+                }
+                if ratio_num % ratio_den != 0 && ratio_den % ratio_num != 0 {
+                    bail!("SpeedRatioNotIntegral({}/{})", ratio_num, ratio_den);
+                }
+            }
+            EditOperation::MuteAudio { start_ms, end_ms } if start_ms >= end_ms => {
+                bail!("InvalidMuteRange");
+            }
+            EditOperation::ReplaceAudio {
+                start_ms, end_ms, ..
+            } if start_ms >= end_ms => {
+                bail!("InvalidReplaceAudioRange");
+            }
+            EditOperation::Crossfade { duration_ms, .. } if *duration_ms == 0 => {
+                bail!("InvalidCrossfadeDuration");
+            }
+            EditOperation::Crossfade { at_ms, duration_ms }
+                if at_ms.checked_add(*duration_ms).is_none() =>
+            {
+                bail!("CrossfadeOutOfRange({}+{})", at_ms, duration_ms);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EditDecisionChip {
     #[serde(rename = "@type")]
     pub chip_type: String,
-    #[serde(rename = "@id")]
-    pub chip_id: String,
+    /// The chip's CID, set by `Chip::seal`; absent until then.
+    #[serde(rename = "@id", default, skip_serializing_if = "Option::is_none")]
+    pub chip_id: Option<Cid>,
     #[serde(rename = "@ver")]
     pub chip_ver: String,
     #[serde(rename = "@world")]
     pub world: String,
 
-    pub input_manifest: String,
-    pub output_manifest: String,
+    pub input_manifest: Cid,
+    /// Absent until the decision has been applied.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_manifest: Option<Cid>,
     pub operations: Vec<EditOperation>,
     pub reencode_required: bool,
-    pub editorial_receipt_cid: String,
+    pub editorial_receipt_cid: Cid,
 }
 
 impl EditDecisionChip {
     pub fn validate(&self) -> Result<()> {
-        if self.chip_type != "vcx/edit.decision" {
+        if self.chip_type != Self::CHIP_TYPE {
             bail!("UnexpectedEditDecisionType({})", self.chip_type);
         }
         if self.operations.is_empty() {
             bail!("EditDecisionMustHaveOperations");
         }
         for op in &self.operations {
-            match op {
-                EditOperation::Trim { start_ms, end_ms } if start_ms >= end_ms => {
-                    bail!("InvalidTrimRange");
-                }
-                EditOperation::OverlayRef { from_ms, to_ms, .. } if from_ms >= to_ms => {
-                    bail!("InvalidOverlayRange");
-                }
-                _ => {}
-            }
+            op.validate()?;
+        }
+        Ok(())
+    }
+
+    /// [`Chip::cid`] of the decision as submitted, i.e. without the fields applying it fills
+    /// in (`output_manifest`, `reencode_required`). It is the same before and after
+    /// resolution, so the output manifest's lineage can name it.
+    pub fn decision_cid(&self) -> Result<Cid> {
+        let mut decision = self.clone();
+        decision.output_manifest = None;
+        decision.reencode_required = false;
+        decision.cid()
+    }
+}
+
+/// What was encoded: the source file as the encoder read it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ReceiptInput {
+    /// File name only; the path is in `parameters`.
+    pub name: String,
+    /// BLAKE3 of the raw file bytes.
+    pub hash_b3: B3Digest,
+    #[serde(with = "unc1::int")]
+    pub size_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ReceiptEncoder {
+    pub name: String,
+    pub version: String,
+    pub profile: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SelfVerifyResult {
+    /// Payload bytes were re-read and re-hashed (`verify --full`).
+    pub full: bool,
+    pub passed: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Release evidence for one encode: enough to re-run it and compare the outcome.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuildReceiptChip {
+    #[serde(rename = "@type")]
+    pub chip_type: String,
+    /// The chip's CID, set by `Chip::seal`; absent until then.
+    #[serde(rename = "@id", default, skip_serializing_if = "Option::is_none")]
+    pub chip_id: Option<Cid>,
+    #[serde(rename = "@ver")]
+    pub chip_ver: String,
+    #[serde(rename = "@world")]
+    pub world: String,
+
+    pub input: ReceiptInput,
+    pub encoder: ReceiptEncoder,
+    /// Every command-line parameter by long option name, defaults included.
+    pub parameters: BTreeMap<String, String>,
+    /// External tools by name, with the first line of their `-version` output.
+    pub tools: BTreeMap<String, String>,
+    pub manifest: Cid,
+    /// Pack Merkle root; absent when the self-verify failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merkle_root: Option<B3Digest>,
+    pub self_verify: SelfVerifyResult,
+}
+
+impl BuildReceiptChip {
+    pub fn validate(&self) -> Result<()> {
+        if self.chip_type != Self::CHIP_TYPE {
+            bail!("UnexpectedBuildReceiptType({})", self.chip_type);
+        }
+        if self.encoder.name.is_empty() || self.encoder.version.is_empty() {
+            bail!("MissingReceiptEncoder");
+        }
+        // A passing run has a root and no error; a failing one says why.
+        let v = &self.self_verify;
+        if v.passed != v.error.is_none() || (v.passed && self.merkle_root.is_none()) {
+            bail!("InconsistentSelfVerifyResult");
         }
         Ok(())
     }
@@ -235,10 +638,10 @@ mod tests {
     fn realtime_sidecar_validation_works() {
         let sidecar = RealtimePredictabilitySidecar {
             chip_type: "vcx/sidecar.predictability.realtime".into(),
-            chip_id: "b3:abc".into(),
+            chip_id: None,
             chip_ver: "1.0".into(),
             world: "a/episode/t/live".into(),
-            target_manifest: "b3:manifest".into(),
+            target_manifest: Cid::blake3([1; 32]),
             group_seq: 10,
             window: RealtimeWindow {
                 groups: 16,
@@ -249,6 +652,7 @@ mod tests {
                 correct_tiles: 90,
                 corrected_tiles: 10,
             },
+            model: EWMA_MODEL_ID.into(),
             metrics: RealtimePredictabilityMetrics {
                 instant_hit_rate: 0.9,
                 ewma_hit_rate: 0.88,
@@ -256,6 +660,8 @@ mod tests {
                 predictability_score: 0.836,
                 samples: 10,
                 mode: PolicyMode::Balanced,
+                transition: None,
+                dwell_groups: 0,
             },
             policy_hint: RealtimePolicyHint {
                 mode: PolicyMode::Balanced,
@@ -267,12 +673,92 @@ mod tests {
         sidecar.validate().unwrap();
     }
 
+    fn metrics(score: f64, mode: PolicyMode) -> RealtimePredictabilityMetrics {
+        RealtimePredictabilityMetrics {
+            instant_hit_rate: score,
+            ewma_hit_rate: score,
+            ewma_volatility: 0.0,
+            predictability_score: score,
+            samples: 10,
+            mode,
+            transition: None,
+            dwell_groups: 3,
+        }
+    }
+
+    #[test]
+    fn policy_hint_config_scales_interpolates_and_covers_bandwidth() {
+        let stepped = PolicyHintConfig::default();
+        for (mode, spec, depth) in [
+            (PolicyMode::AggressiveGhost, 384, 2),
+            (PolicyMode::Balanced, 192, 3),
+            (PolicyMode::DownloadFirst, 32, 6),
+        ] {
+            let hint = stepped.derive(&metrics(0.7, mode));
+            assert_eq!(
+                (
+                    hint.max_speculative_tiles_per_group,
+                    hint.prefetch_depth_groups
+                ),
+                (spec, depth)
+            );
+        }
+
+        let manifest = serde_json::json!({
+            "gots": [{"tiles": vec![serde_json::json!({}); 60]}]
+        });
+        let small = PolicyHintConfig::default().with_manifest(&manifest);
+        assert_eq!(small.tiles_per_group, Some(60));
+        let big = PolicyHintConfig {
+            tiles_per_group: Some(2_000),
+            ..PolicyHintConfig::default()
+        };
+        let aggressive = metrics(0.9, PolicyMode::AggressiveGhost);
+        assert_eq!(
+            small.derive(&aggressive).max_speculative_tiles_per_group,
+            45
+        );
+        assert_eq!(
+            big.derive(&aggressive).max_speculative_tiles_per_group,
+            1_500
+        );
+
+        let continuous = PolicyHintConfig {
+            continuous: true,
+            ..PolicyHintConfig::default()
+        };
+        let budgets: Vec<u32> = [0.5, 0.6, 0.66, 0.725, 0.8, 0.85, 0.95]
+            .iter()
+            .map(|&s| {
+                continuous
+                    .derive(&metrics(s, PolicyMode::Balanced))
+                    .max_speculative_tiles_per_group
+            })
+            .collect();
+        assert_eq!(budgets.first(), Some(&32));
+        assert_eq!(budgets[3], 192);
+        assert_eq!(budgets.last(), Some(&384));
+        assert!(budgets.windows(2).all(|w| w[0] <= w[1]));
+
+        let starved = PolicyHintConfig {
+            tiles_per_group: Some(100),
+            bandwidth: Some(BandwidthEstimate {
+                bits_per_second: 2_000_000,
+                bytes_per_group: 250_000,
+                group_duration_ms: 500,
+            }),
+            ..PolicyHintConfig::default()
+        };
+        let hint = starved.derive(&metrics(0.5, PolicyMode::DownloadFirst));
+        assert_eq!(hint.max_speculative_tiles_per_group, 50);
+        assert_eq!(hint.prefetch_depth_groups, 3);
+    }
+
     #[test]
     fn from_metrics_assigns_mode_specific_hint() {
         let sidecar = RealtimePredictabilitySidecar::from_metrics(
-            "b3:id".into(),
             "a/ep/t/live".into(),
-            "b3:manifest".into(),
+            Cid::blake3([1; 32]),
             12,
             RealtimeWindow {
                 groups: 8,
@@ -283,6 +769,7 @@ mod tests {
                 correct_tiles: 118,
                 corrected_tiles: 2,
             },
+            EWMA_MODEL_ID,
             RealtimePredictabilityMetrics {
                 instant_hit_rate: 0.983,
                 ewma_hit_rate: 0.962,
@@ -290,10 +777,18 @@ mod tests {
                 predictability_score: 0.952,
                 samples: 20,
                 mode: PolicyMode::AggressiveGhost,
+                transition: None,
+                dwell_groups: 0,
             },
         );
 
         assert_eq!(sidecar.policy_hint.mode, PolicyMode::AggressiveGhost);
+        assert_eq!(sidecar.model, EWMA_MODEL_ID);
+
+        let mut legacy = serde_json::to_value(&sidecar).unwrap();
+        legacy.as_object_mut().unwrap().remove("model");
+        let legacy: RealtimePredictabilitySidecar = serde_json::from_value(legacy).unwrap();
+        assert_eq!(legacy.model, EWMA_MODEL_ID);
         assert!(sidecar.policy_hint.max_speculative_tiles_per_group >= 300);
     }
 
@@ -301,10 +796,10 @@ mod tests {
     fn vod_sidecar_rejects_invalid_confidence() {
         let s = VodPredictabilitySidecar {
             chip_type: "vcx/sidecar.predictability.vod".into(),
-            chip_id: "b3:x".into(),
+            chip_id: None,
             chip_ver: "1.0".into(),
             world: "a/vod".into(),
-            target_manifest: "b3:m".into(),
+            target_manifest: Cid::blake3([2; 32]),
             global_stats: VodGlobalStats {
                 volatility_score: 0.2,
                 average_shot_length_ms: 2000,
@@ -313,6 +808,8 @@ mod tests {
                 region_id: "r1".into(),
                 tile_range_start: 0,
                 tile_range_end: 10,
+                group_range_start: None,
+                group_range_end: None,
                 strategy: VodRegionStrategy::HoldAndNoise,
                 confidence: 1.2,
             }],
@@ -325,19 +822,162 @@ mod tests {
     fn edit_decision_requires_valid_ranges() {
         let chip = EditDecisionChip {
             chip_type: "vcx/edit.decision".into(),
-            chip_id: "b3:e".into(),
+            chip_id: None,
             chip_ver: "1.0".into(),
             world: "a/episode/t/edit".into(),
-            input_manifest: "b3:in".into(),
-            output_manifest: "b3:out".into(),
+            input_manifest: Cid::blake3([3; 32]),
+            output_manifest: Some(Cid::blake3([4; 32])),
             operations: vec![EditOperation::Trim {
                 start_ms: 2000,
                 end_ms: 1000,
             }],
             reencode_required: false,
-            editorial_receipt_cid: "b3:r".into(),
+            editorial_receipt_cid: Cid::blake3([5; 32]),
         };
 
         assert!(chip.validate().is_err());
     }
+
+    #[test]
+    fn richer_operations_validate_and_declare_cost() {
+        let n = crate::unc1::int_value::<u64>;
+        let ops: Vec<EditOperation> = serde_json::from_value(serde_json::json!([
+            {"kind": "crop", "tile_x": n(1), "tile_y": n(0), "tiles_w": n(4), "tiles_h": n(3)},
+            {"kind": "reorder", "segments": [
+                {"start_ms": n(1000), "end_ms": n(2000)},
+                {"start_ms": n(0), "end_ms": n(1000)}
+            ]},
+            {"kind": "speed_change", "ratio_num": n(1), "ratio_den": n(2)},
+            {"kind": "mute_audio", "start_ms": n(0), "end_ms": n(500)},
+            {"kind": "replace_audio", "start_ms": n(0), "end_ms": n(500), "source_cid": Cid::blake3([6; 32])},
+            {"kind": "crossfade", "at_ms": n(1000), "duration_ms": n(250)}
+        ]))
+        .unwrap();
+        for op in &ops {
+            op.validate().unwrap();
+        }
+        let by_ref: Vec<bool> = ops.iter().map(EditOperation::by_reference).collect();
+        assert_eq!(by_ref, [true, true, true, false, false, false]);
+
+        let bad = [
+            EditOperation::SpeedChange {
+                ratio_num: 3,
+                ratio_den: 2,
+            },
+            EditOperation::Reorder {
+                segments: vec![
+                    EditSegment {
+                        start_ms: 0,
+                        end_ms: 1000,
+                    },
+                    EditSegment {
+                        start_ms: 500,
+                        end_ms: 1500,
+                    },
+                ],
+            },
+            EditOperation::Crop {
+                tile_x: 0,
+                tile_y: 0,
+                tiles_w: 0,
+                tiles_h: 2,
+            },
+            EditOperation::Crossfade {
+                at_ms: u64::MAX,
+                duration_ms: 1,
+            },
+        ];
+        for op in &bad {
+            assert!(op.validate().is_err(), "{:?}", op);
+        }
+    }
+
+    #[test]
+    fn chips_serialize_as_strict_unc1() {
+        let realtime = RealtimePredictabilitySidecar::from_metrics(
+            "a/ep/t/live".into(),
+            Cid::blake3([1; 32]),
+            3,
+            RealtimeWindow {
+                groups: 8,
+                duration_ms: 4_000,
+            },
+            RealtimeObserved {
+                guessed_tiles: 7,
+                correct_tiles: 6,
+                corrected_tiles: 1,
+            },
+            EWMA_MODEL_ID,
+            RealtimePredictabilityMetrics {
+                instant_hit_rate: 6.0 / 7.0,
+                ewma_hit_rate: 0.9,
+                ewma_volatility: 0.01,
+                predictability_score: 0.89,
+                samples: 3,
+                mode: PolicyMode::Balanced,
+                transition: None,
+                dwell_groups: 1,
+            },
+        );
+        let vod = VodPredictabilitySidecar {
+            chip_type: "vcx/sidecar.predictability.vod".into(),
+            chip_id: None,
+            chip_ver: "1.0".into(),
+            world: "a/vod".into(),
+            target_manifest: Cid::blake3([2; 32]),
+            global_stats: VodGlobalStats {
+                volatility_score: 0.2,
+                average_shot_length_ms: 2000,
+            },
+            regions: vec![VodRegionRule {
+                region_id: "r1".into(),
+                tile_range_start: 0,
+                tile_range_end: 10,
+                group_range_start: Some(2),
+                group_range_end: Some(4),
+                strategy: VodRegionStrategy::HoldAndNoise,
+                confidence: 0.75,
+            }],
+        };
+        let edit = EditDecisionChip {
+            chip_type: "vcx/edit.decision".into(),
+            chip_id: None,
+            chip_ver: "1.0".into(),
+            world: "a/episode/t/edit".into(),
+            input_manifest: Cid::blake3([3; 32]),
+            output_manifest: Some(Cid::blake3([4; 32])),
+            operations: vec![
+                EditOperation::Trim {
+                    start_ms: 0,
+                    end_ms: 1000,
+                },
+                EditOperation::SpeedChange {
+                    ratio_num: 1,
+                    ratio_den: 2,
+                },
+            ],
+            reencode_required: false,
+            editorial_receipt_cid: Cid::blake3([5; 32]),
+        };
+
+        let values = [
+            serde_json::to_value(&realtime).unwrap(),
+            serde_json::to_value(&vod).unwrap(),
+            serde_json::to_value(&edit).unwrap(),
+        ];
+        for v in &values {
+            crate::validate_no_json_numbers(v).unwrap();
+            crate::cid_for_json_value(v).unwrap();
+        }
+        assert_eq!(values[0]["group_seq"]["v"], "3");
+        assert_eq!(values[1]["regions"][0]["confidence"]["m"], "750000000");
+
+        let back: RealtimePredictabilitySidecar =
+            serde_json::from_value(values[0].clone()).unwrap();
+        assert_eq!(back.metrics.predictability_score, 0.89);
+        let back: VodPredictabilitySidecar = serde_json::from_value(values[1].clone()).unwrap();
+        assert_eq!(back.regions[0].group_range().unwrap(), (2, 4));
+        let back: EditDecisionChip = serde_json::from_value(values[2].clone()).unwrap();
+        assert_eq!(serde_json::to_value(&back).unwrap(), values[2]);
+    }
 }
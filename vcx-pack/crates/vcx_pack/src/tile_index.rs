@@ -0,0 +1,298 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::streaming_protocol::{VodRegionRule, VodRegionStrategy};
+use crate::unc1;
+
+/// Position of one tile in a manifest.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TileAddress {
+    pub rendition: u32,
+    pub group: u32,
+    pub row: u32,
+    pub col: u32,
+}
+
+/// Tile grid of a manifest. Region rules address tiles by group and by slot, where
+/// `slot = (rendition * rows + row) * cols + col`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TileGrid {
+    pub renditions: u32,
+    pub groups: u32,
+    pub rows: u32,
+    pub cols: u32,
+}
+
+impl TileGrid {
+    /// Grid of a manifest JSON: rows and columns from the tiles' `tile_y`/`tile_x`, or a
+    /// single row of tile-list positions when no tile carries coordinates. Manifests carry one
+    /// rendition.
+    pub fn from_manifest(manifest: &Value) -> Result<Self> {
+        let gots = manifest
+            .get("gots")
+            .and_then(Value::as_array)
+            .context("ManifestMissingGots")?;
+        let (mut rows, mut cols, mut longest) = (0u32, 0u32, 0u32);
+        let mut positional = false;
+        for (g, got) in gots.iter().enumerate() {
+            let tiles = got
+                .get("tiles")
+                .and_then(Value::as_array)
+                .with_context(|| format!("GotMissingTiles({})", g))?;
+            longest = longest.max(tiles.len() as u32);
+            for tile in tiles {
+                match (coord(tile, "tile_x")?, coord(tile, "tile_y")?) {
+                    (Some(x), Some(y)) => {
+                        cols = cols.max(x + 1);
+                        rows = rows.max(y + 1);
+                    }
+                    _ => positional = true,
+                }
+            }
+        }
+        if positional || cols == 0 {
+            if cols > 0 {
+                bail!("MixedTileAddressing");
+            }
+            (rows, cols) = (longest.min(1), longest);
+        }
+        Ok(Self {
+            renditions: 1,
+            groups: gots.len() as u32,
+            rows,
+            cols,
+        })
+    }
+
+    pub fn slots_per_group(&self) -> u32 {
+        self.renditions * self.rows * self.cols
+    }
+
+    pub fn slot(&self, a: &TileAddress) -> Result<u32> {
+        if a.rendition >= self.renditions
+            || a.group >= self.groups
+            || a.row >= self.rows
+            || a.col >= self.cols
+        {
+            bail!(
+                "TileOutOfGrid({}/{}/{}/{})",
+                a.rendition,
+                a.group,
+                a.row,
+                a.col
+            );
+        }
+        Ok((a.rendition * self.rows + a.row) * self.cols + a.col)
+    }
+
+    pub fn address(&self, group: u32, slot: u32) -> Result<TileAddress> {
+        if group >= self.groups || slot >= self.slots_per_group() {
+            bail!("SlotOutOfGrid({}/{})", group, slot);
+        }
+        Ok(TileAddress {
+            rendition: slot / (self.rows * self.cols),
+            group,
+            row: slot / self.cols % self.rows,
+            col: slot % self.cols,
+        })
+    }
+
+    /// Slot of the tile at `position` in a group's tile list.
+    pub fn slot_of(&self, tile: &Value, position: usize) -> Result<u32> {
+        match (coord(tile, "tile_x")?, coord(tile, "tile_y")?) {
+            (Some(col), Some(row)) if row < self.rows && col < self.cols => {
+                Ok(row * self.cols + col)
+            }
+            (Some(col), Some(row)) => bail!("TileOutOfGrid(0/-/{}/{})", row, col),
+            _ => Ok(position as u32),
+        }
+    }
+}
+
+fn coord(tile: &Value, key: &str) -> Result<Option<u32>> {
+    match tile.get(key) {
+        None => Ok(None),
+        Some(v) => unc1::parse_int(v)
+            .map(Some)
+            .with_context(|| format!("BadTileField({})", key)),
+    }
+}
+
+/// What a player does with one tile of one group.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TileDecision {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region_id: Option<String>,
+    pub strategy: VodRegionStrategy,
+    pub confidence: f64,
+}
+
+impl TileDecision {
+    /// Tiles outside every region are downloaded.
+    pub fn uncovered() -> Self {
+        Self {
+            region_id: None,
+            strategy: VodRegionStrategy::DownloadAggressive,
+            confidence: 0.0,
+        }
+    }
+}
+
+/// Groups `[start, next band's start)` share the same set of rules, sorted by slot.
+#[derive(Debug, Clone)]
+struct Band {
+    start: u32,
+    rules: Vec<usize>,
+}
+
+/// Index over non-overlapping VOD region rules; a lookup is two binary searches.
+#[derive(Debug, Clone)]
+pub struct RegionMap {
+    rules: Vec<VodRegionRule>,
+    bands: Vec<Band>,
+}
+
+impl RegionMap {
+    /// Index `rules`, rejecting malformed ranges and overlapping regions.
+    pub fn new(rules: &[VodRegionRule]) -> Result<Self> {
+        let mut starts = vec![0u32];
+        for r in rules {
+            if r.tile_range_start > r.tile_range_end {
+                bail!("InvalidTileRange({})", r.region_id);
+            }
+            let (g0, g1) = r.group_range()?;
+            starts.push(g0);
+            if let Some(next) = g1.checked_add(1) {
+                starts.push(next);
+            }
+        }
+        starts.sort_unstable();
+        starts.dedup();
+
+        let mut bands = Vec::with_capacity(starts.len());
+        for start in starts {
+            let mut active = Vec::new();
+            for (i, r) in rules.iter().enumerate() {
+                let (g0, g1) = r.group_range()?;
+                if (g0..=g1).contains(&start) {
+                    active.push(i);
+                }
+            }
+            active.sort_by_key(|&i| rules[i].tile_range_start);
+            for pair in active.windows(2) {
+                let (a, b) = (&rules[pair[0]], &rules[pair[1]]);
+                if a.tile_range_end >= b.tile_range_start {
+                    bail!("RegionsOverlap({},{})", a.region_id, b.region_id);
+                }
+            }
+            bands.push(Band {
+                start,
+                rules: active,
+            });
+        }
+        Ok(Self {
+            rules: rules.to_vec(),
+            bands,
+        })
+    }
+
+    pub fn lookup(&self, group: u32, slot: u32) -> Option<&VodRegionRule> {
+        let band = &self.bands[self.bands.partition_point(|b| b.start <= group) - 1];
+        let i = band
+            .rules
+            .partition_point(|&r| self.rules[r].tile_range_start <= slot);
+        let rule = &self.rules[*band.rules.get(i.checked_sub(1)?)?];
+        (slot <= rule.tile_range_end).then_some(rule)
+    }
+
+    pub fn decision(&self, group: u32, slot: u32) -> TileDecision {
+        match self.lookup(group, slot) {
+            Some(r) => TileDecision {
+                region_id: Some(r.region_id.clone()),
+                strategy: r.strategy,
+                confidence: r.confidence,
+            },
+            None => TileDecision::uncovered(),
+        }
+    }
+
+    pub fn decision_at(&self, grid: &TileGrid, a: &TileAddress) -> Result<TileDecision> {
+        Ok(self.decision(a.group, grid.slot(a)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rule(id: &str, tiles: (u32, u32), groups: Option<(u32, u32)>) -> VodRegionRule {
+        VodRegionRule {
+            region_id: id.into(),
+            tile_range_start: tiles.0,
+            tile_range_end: tiles.1,
+            group_range_start: groups.map(|g| g.0),
+            group_range_end: groups.map(|g| g.1),
+            strategy: VodRegionStrategy::CopyPrevious,
+            confidence: 0.9,
+        }
+    }
+
+    #[test]
+    fn grid_addresses_round_trip() {
+        let tile = |x: u32, y: u32| json!({"cid": "b3:t", "tile_x": unc1::int_value(x), "tile_y": unc1::int_value(y)});
+        let got = json!({"tiles": [tile(0, 0), tile(1, 0), tile(2, 0), tile(0, 1), tile(1, 1), tile(2, 1)]});
+        let manifest = json!({"gots": [got.clone(), got]});
+        let grid = TileGrid::from_manifest(&manifest).unwrap();
+        assert_eq!((grid.groups, grid.rows, grid.cols), (2, 2, 3));
+        assert_eq!(grid.slot_of(&tile(1, 1), 0).unwrap(), 4);
+
+        for slot in 0..grid.slots_per_group() {
+            let a = grid.address(1, slot).unwrap();
+            assert_eq!(grid.slot(&a).unwrap(), slot);
+        }
+        assert!(grid.address(2, 0).is_err());
+        assert!(grid
+            .slot(&TileAddress {
+                rendition: 0,
+                group: 0,
+                row: 2,
+                col: 0
+            })
+            .is_err());
+
+        let positional = json!({"gots": [{"tiles": [{"cid": "b3:a"}, {"cid": "b3:b"}]}]});
+        let grid = TileGrid::from_manifest(&positional).unwrap();
+        assert_eq!((grid.rows, grid.cols), (1, 2));
+    }
+
+    #[test]
+    fn region_map_resolves_and_rejects_overlaps() {
+        let map = RegionMap::new(&[
+            rule("all", (0, 3), None),
+            rule("late", (4, 7), Some((10, 19))),
+            rule("later", (4, 5), Some((20, 20))),
+        ])
+        .unwrap();
+        assert_eq!(map.lookup(0, 2).unwrap().region_id, "all");
+        assert_eq!(map.lookup(500, 3).unwrap().region_id, "all");
+        assert!(map.lookup(9, 4).is_none());
+        assert_eq!(map.lookup(10, 7).unwrap().region_id, "late");
+        assert_eq!(map.lookup(20, 5).unwrap().region_id, "later");
+        assert!(map.lookup(20, 6).is_none());
+        assert_eq!(map.decision(21, 4), TileDecision::uncovered());
+
+        let err = RegionMap::new(&[rule("a", (0, 3), None), rule("b", (3, 4), Some((5, 6)))])
+            .unwrap_err();
+        assert!(err.to_string().contains("RegionsOverlap(a,b)"));
+        assert!(RegionMap::new(&[
+            rule("a", (0, 3), Some((1, 2))),
+            rule("b", (0, 3), Some((3, 4)))
+        ])
+        .is_ok());
+        let mut half = rule("h", (0, 1), Some((0, 1)));
+        half.group_range_end = None;
+        assert!(RegionMap::new(&[half]).is_err());
+    }
+}
@@ -0,0 +1,673 @@
+use anyhow::{bail, Context, Result};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Map, Value};
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+/// Fractional digits of every `dec/1` a chip writes. UNC-1 integers are
+/// `{"@num":"int/1","v":"<digits>"}`, rationals `{"@num":"rat/1","p":"<digits>","q":"<digits>"}`
+/// and decimals `{"@num":"dec/1","m":"<digits>","s":<scale>}` (value `m * 10^-s`); the scale
+/// is the only bare JSON number [`validate`] allows. Every reader here is strict: bare JSON
+/// numbers and non-canonical digits are rejected.
+pub const DEC_SCALE: u32 = 9;
+
+/// Largest `dec/1` scale accepted.
+pub const MAX_DEC_SCALE: u32 = 18;
+
+const DEC_UNIT: f64 = 1e9;
+
+/// Round `v` to [`DEC_SCALE`] so that writing and reading it back yields the same bits.
+pub fn quantize(v: f64) -> f64 {
+    (v * DEC_UNIT).round() / DEC_UNIT
+}
+
+/// Parse canonical decimal digits: optional `-`, no `+`, no leading zeros, no `-0`.
+pub(crate) fn parse_digits(s: &str) -> Result<i128> {
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        bail!("UncNotDecimal({:?})", s);
+    }
+    if (digits.len() > 1 && digits.starts_with('0')) || s == "-0" {
+        bail!("UncNotCanonical({:?})", s);
+    }
+    s.parse().with_context(|| format!("UncOutOfRange({:?})", s))
+}
+
+/// The members of a UNC-1 object, checking its tag and exact key set.
+fn unc_fields<'a>(v: &'a Value, tag: &str, keys: &[&str]) -> Result<&'a Map<String, Value>> {
+    let o = v
+        .as_object()
+        .with_context(|| format!("ExpectedUnc({})", tag))?;
+    match o.get("@num").and_then(Value::as_str) {
+        Some(t) if t == tag => {}
+        Some(t) => bail!("UncTagMismatch({} != {})", t, tag),
+        None => bail!("ExpectedUnc({})", tag),
+    }
+    if let Some(k) = o
+        .keys()
+        .find(|k| *k != "@num" && !keys.contains(&k.as_str()))
+    {
+        bail!("UncUnexpectedKey({})", k);
+    }
+    Ok(o)
+}
+
+fn digits_field(o: &Map<String, Value>, key: &str) -> Result<i128> {
+    let s = o
+        .get(key)
+        .and_then(Value::as_str)
+        .with_context(|| format!("UncMissingDigits({})", key))?;
+    parse_digits(s).with_context(|| format!("BadUncField({})", key))
+}
+
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    a = a.abs();
+    b = b.abs();
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// UNC-1 `int/1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UncInt(pub i128);
+
+impl UncInt {
+    /// Strict parse of a canonical `int/1` object.
+    pub fn from_json(v: &Value) -> Result<Self> {
+        let o = unc_fields(v, "int/1", &["v"])?;
+        Ok(UncInt(digits_field(o, "v")?))
+    }
+
+    pub fn to_json(self) -> Value {
+        int_value(self.0)
+    }
+
+    /// Narrow to a machine integer.
+    pub fn get<T: TryFrom<i128>>(self) -> Result<T> {
+        T::try_from(self.0)
+            .ok()
+            .with_context(|| format!("UncIntOutOfRange({})", self.0))
+    }
+}
+
+impl FromStr for UncInt {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        parse_digits(s).map(UncInt)
+    }
+}
+
+impl Display for UncInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+macro_rules! unc_int_from {
+    ($($t:ty),*) => {$(
+        impl From<$t> for UncInt {
+            fn from(v: $t) -> Self {
+                UncInt(v as i128)
+            }
+        }
+    )*};
+}
+unc_int_from!(u8, u16, u32, u64, usize, i8, i16, i32, i64);
+
+/// UNC-1 `rat/1`, always reduced with a positive denominator. Arithmetic is exact and
+/// returns `None` on overflow or division by zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UncRat {
+    p: i128,
+    q: i128,
+}
+
+impl UncRat {
+    /// `p/q` reduced to lowest terms.
+    pub fn new(p: i128, q: i128) -> Result<Self> {
+        if q == 0 {
+            bail!("UncRatZeroDenominator");
+        }
+        Self::reduced(p, q).context("UncOutOfRange")
+    }
+
+    pub fn from_int(n: i128) -> Self {
+        UncRat { p: n, q: 1 }
+    }
+
+    fn reduced(p: i128, q: i128) -> Option<Self> {
+        let g = gcd(p, q).max(1);
+        let (p, q) = (p / g, q / g);
+        if q < 0 {
+            Some(UncRat {
+                p: p.checked_neg()?,
+                q: q.checked_neg()?,
+            })
+        } else {
+            Some(UncRat { p, q })
+        }
+    }
+
+    /// Strict parse of canonical digits: `q` positive and `p/q` already reduced.
+    pub fn parse(p: &str, q: &str) -> Result<Self> {
+        let p = parse_digits(p).context("BadUncField(p)")?;
+        let q = parse_digits(q).context("BadUncField(q)")?;
+        if q <= 0 {
+            bail!("UncRatNonPositiveDenominator({})", q);
+        }
+        if gcd(p, q) != 1 {
+            bail!("UncRatNotReduced({}/{})", p, q);
+        }
+        Ok(UncRat { p, q })
+    }
+
+    /// Strict parse of a canonical `rat/1` object.
+    pub fn from_json(v: &Value) -> Result<Self> {
+        let o = unc_fields(v, "rat/1", &["p", "q"])?;
+        let part = |k: &str| {
+            o.get(k)
+                .and_then(Value::as_str)
+                .with_context(|| format!("UncMissingDigits({})", k))
+        };
+        Self::parse(part("p")?, part("q")?)
+    }
+
+    pub fn to_json(self) -> Value {
+        json!({"@num": "rat/1", "p": self.p.to_string(), "q": self.q.to_string()})
+    }
+
+    pub fn p(self) -> i128 {
+        self.p
+    }
+
+    pub fn q(self) -> i128 {
+        self.q
+    }
+
+    pub fn is_positive(self) -> bool {
+        self.p > 0
+    }
+
+    pub fn is_integer(self) -> bool {
+        self.q == 1
+    }
+
+    /// Largest integer not above the value.
+    pub fn floor(self) -> i128 {
+        self.p.div_euclid(self.q)
+    }
+
+    pub fn recip(self) -> Option<Self> {
+        if self.p == 0 {
+            return None;
+        }
+        Self::reduced(self.q, self.p)
+    }
+
+    pub fn checked_add(self, o: Self) -> Option<Self> {
+        let g = gcd(self.q, o.q);
+        let q = (self.q / g).checked_mul(o.q)?;
+        let p = self
+            .p
+            .checked_mul(o.q / g)?
+            .checked_add(o.p.checked_mul(self.q / g)?)?;
+        Self::reduced(p, q)
+    }
+
+    pub fn checked_sub(self, o: Self) -> Option<Self> {
+        self.checked_add(UncRat {
+            p: o.p.checked_neg()?,
+            q: o.q,
+        })
+    }
+
+    pub fn checked_mul(self, o: Self) -> Option<Self> {
+        // Cross-reduce first so reduced operands only overflow when the result does.
+        let g1 = gcd(self.p, o.q).max(1);
+        let g2 = gcd(o.p, self.q).max(1);
+        let p = (self.p / g1).checked_mul(o.p / g2)?;
+        let q = (self.q / g2).checked_mul(o.q / g1)?;
+        Self::reduced(p, q)
+    }
+
+    pub fn checked_div(self, o: Self) -> Option<Self> {
+        self.checked_mul(o.recip()?)
+    }
+}
+
+impl Ord for UncRat {
+    fn cmp(&self, o: &Self) -> std::cmp::Ordering {
+        // Denominators are positive, so compare cross products; widen through the sign of
+        // the difference when they would overflow.
+        match (self.p.checked_mul(o.q), o.p.checked_mul(self.q)) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            _ => self
+                .checked_sub(*o)
+                .map(|d| d.p.cmp(&0))
+                .unwrap_or_else(|| self.floor().cmp(&o.floor())),
+        }
+    }
+}
+
+impl PartialOrd for UncRat {
+    fn partial_cmp(&self, o: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(o))
+    }
+}
+
+impl Display for UncRat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.p, self.q)
+    }
+}
+
+/// UNC-1 `dec/1`: `m * 10^-s`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UncDec {
+    m: i64,
+    s: u32,
+}
+
+impl UncDec {
+    pub fn new(m: i64, s: u32) -> Result<Self> {
+        if s > MAX_DEC_SCALE {
+            bail!("UncDecScaleTooLarge({})", s);
+        }
+        Ok(UncDec { m, s })
+    }
+
+    /// `v` rounded to [`DEC_SCALE`].
+    pub fn from_f64(v: f64) -> Result<Self> {
+        if !v.is_finite() || (v * DEC_UNIT).abs() >= 9.0e15 {
+            bail!("DecOutOfRange({})", v);
+        }
+        Ok(UncDec {
+            m: (v * DEC_UNIT).round() as i64,
+            s: DEC_SCALE,
+        })
+    }
+
+    /// Strict parse of a canonical `dec/1` object.
+    pub fn from_json(v: &Value) -> Result<Self> {
+        let o = unc_fields(v, "dec/1", &["m", "s"])?;
+        let m = i64::try_from(digits_field(o, "m")?)
+            .ok()
+            .context("BadUncField(m)")?;
+        let s = o
+            .get("s")
+            .and_then(Value::as_u64)
+            .context("BadUncField(s)")?;
+        Self::new(m, u32::try_from(s).unwrap_or(u32::MAX))
+    }
+
+    pub fn to_json(self) -> Value {
+        json!({"@num": "dec/1", "m": self.m.to_string(), "s": self.s})
+    }
+
+    pub fn m(self) -> i64 {
+        self.m
+    }
+
+    pub fn s(self) -> u32 {
+        self.s
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.m as f64 / 10f64.powi(self.s as i32)
+    }
+
+    /// Exact value as a rational.
+    pub fn to_rat(self) -> UncRat {
+        UncRat::reduced(self.m as i128, 10i128.pow(self.s)).expect("dec/1 scale is bounded")
+    }
+}
+
+impl Display for UncDec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.m.unsigned_abs().to_string();
+        let sign = if self.m < 0 { "-" } else { "" };
+        let s = self.s as usize;
+        if s == 0 {
+            return write!(f, "{}{}", sign, digits);
+        }
+        let padded = format!("{:0>width$}", digits, width = s + 1);
+        let (int, frac) = padded.split_at(padded.len() - s);
+        write!(f, "{}{}.{}", sign, int, frac)
+    }
+}
+
+macro_rules! unc_serde {
+    ($($t:ty),*) => {$(
+        impl Serialize for $t {
+            fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                self.to_json().serialize(s)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $t {
+            fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+                <$t>::from_json(&Value::deserialize(d)?).map_err(D::Error::custom)
+            }
+        }
+    )*};
+}
+unc_serde!(UncInt, UncRat, UncDec);
+
+/// Full UNC-1 check: every `{"@num": ..}` object must be a canonical `int/1`, `rat/1` or
+/// `dec/1` with exactly its own keys, and there must be no other JSON numbers.
+pub fn validate(value: &Value) -> Result<()> {
+    fn walk(v: &Value, path: &mut Vec<String>) -> Result<()> {
+        match v {
+            Value::Number(_) => bail!("ManifestHasJsonNumber at {}", path.join(".")),
+            Value::Array(a) => {
+                for (i, x) in a.iter().enumerate() {
+                    path.push(format!("[{}]", i));
+                    walk(x, path)?;
+                    path.pop();
+                }
+            }
+            Value::Object(o) if o.contains_key("@num") => {
+                let checked = match o["@num"].as_str() {
+                    Some("int/1") => UncInt::from_json(v).map(drop),
+                    Some("rat/1") => UncRat::from_json(v).map(drop),
+                    Some("dec/1") => UncDec::from_json(v).map(drop),
+                    _ => Err(anyhow::anyhow!("UnknownUncTag({})", o["@num"])),
+                };
+                checked.with_context(|| format!("BadUnc1 at {}", path.join(".")))?;
+            }
+            Value::Object(o) => {
+                for (k, x) in o.iter() {
+                    path.push(k.clone());
+                    walk(x, path)?;
+                    path.pop();
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+    let mut path = vec!["$".to_string()];
+    walk(value, &mut path)
+}
+
+/// Milliseconds in `ticks` of a `timebase` (seconds per tick), rounded down and saturating.
+pub fn ticks_to_ms(ticks: u64, timebase: UncRat) -> u64 {
+    saturate(
+        UncRat::from_int(ticks as i128)
+            .checked_mul(timebase)
+            .and_then(|t| t.checked_mul(UncRat::from_int(1000))),
+    )
+}
+
+/// Ticks of a `timebase` in `ms` milliseconds, rounded down and saturating.
+pub fn ms_to_ticks(ms: u64, timebase: UncRat) -> u64 {
+    saturate(
+        UncRat::new(ms as i128, 1000)
+            .ok()
+            .and_then(|s| s.checked_div(timebase)),
+    )
+}
+
+/// Convert a tick count between timebases, rounded down; the flag reports whether it was exact.
+pub fn convert_ticks(ticks: u64, from: UncRat, to: UncRat) -> (u64, bool) {
+    let r = UncRat::from_int(ticks as i128)
+        .checked_mul(from)
+        .and_then(|t| t.checked_div(to));
+    (saturate(r), r.is_some_and(UncRat::is_integer))
+}
+
+fn saturate(r: Option<UncRat>) -> u64 {
+    r.map_or(u64::MAX, |r| r.floor().clamp(0, u64::MAX as i128) as u64)
+}
+
+pub fn int_value<T: Display>(v: T) -> Value {
+    json!({"@num": "int/1", "v": v.to_string()})
+}
+
+pub fn dec_value(v: f64) -> Result<Value> {
+    UncDec::from_f64(v).map(UncDec::to_json)
+}
+
+/// A canonical `int/1` narrowed to `T`.
+pub fn parse_int<T: TryFrom<i128>>(v: &Value) -> Result<T> {
+    UncInt::from_json(v).context("BadUncInt")?.get()
+}
+
+/// A canonical `dec/1` as `f64`.
+pub fn parse_dec(v: &Value) -> Result<f64> {
+    UncDec::from_json(v)
+        .map(UncDec::to_f64)
+        .context("BadUncDec")
+}
+
+/// The `int/1` member `key` of an object.
+pub fn int_field<T: TryFrom<i128>>(v: &Value, key: &str) -> Result<T> {
+    let o = v
+        .get(key)
+        .with_context(|| format!("MissingField({})", key))?;
+    parse_int(o).with_context(|| format!("BadUncInt({})", key))
+}
+
+/// A positive `rat/1` timebase (seconds per tick).
+pub fn timebase(v: &Value) -> Result<UncRat> {
+    let tb = UncRat::from_json(v).context("BadTimebase")?;
+    if !tb.is_positive() {
+        bail!("ZeroTimebase");
+    }
+    Ok(tb)
+}
+
+/// `serde(with)` for integer fields.
+pub mod int {
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+    use serde_json::Value;
+    use std::fmt::Display;
+
+    pub fn serialize<T: Display, S: Serializer>(v: &T, s: S) -> Result<S::Ok, S::Error> {
+        super::int_value(v).serialize(s)
+    }
+
+    pub fn deserialize<'de, T: TryFrom<i128>, D: Deserializer<'de>>(d: D) -> Result<T, D::Error> {
+        super::parse_int(&Value::deserialize(d)?).map_err(D::Error::custom)
+    }
+}
+
+/// `serde(with)` for optional integer fields (pair with `default` and `skip_serializing_if`).
+pub mod opt_int {
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+    use serde_json::Value;
+    use std::fmt::Display;
+
+    pub fn serialize<T: Display, S: Serializer>(v: &Option<T>, s: S) -> Result<S::Ok, S::Error> {
+        v.as_ref().map(super::int_value).serialize(s)
+    }
+
+    pub fn deserialize<'de, T: TryFrom<i128>, D: Deserializer<'de>>(
+        d: D,
+    ) -> Result<Option<T>, D::Error> {
+        match Value::deserialize(d)? {
+            Value::Null => Ok(None),
+            v => super::parse_int(&v).map(Some).map_err(D::Error::custom),
+        }
+    }
+}
+
+/// `serde(with)` for `f64` fields, written at [`super::DEC_SCALE`].
+pub mod dec {
+    use serde::{
+        de::Error as _, ser::Error as _, Deserialize, Deserializer, Serialize, Serializer,
+    };
+    use serde_json::Value;
+
+    pub fn serialize<S: Serializer>(v: &f64, s: S) -> Result<S::Ok, S::Error> {
+        super::dec_value(*v).map_err(S::Error::custom)?.serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<f64, D::Error> {
+        super::parse_dec(&Value::deserialize(d)?).map_err(D::Error::custom)
+    }
+}
+
+/// `serde(with)` for optional `f64` fields, written at [`super::DEC_SCALE`].
+pub mod opt_dec {
+    use serde::{
+        de::Error as _, ser::Error as _, Deserialize, Deserializer, Serialize, Serializer,
+    };
+    use serde_json::Value;
+
+    pub fn serialize<S: Serializer>(v: &Option<f64>, s: S) -> Result<S::Ok, S::Error> {
+        v.map(super::dec_value)
+            .transpose()
+            .map_err(S::Error::custom)?
+            .serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<f64>, D::Error> {
+        match Value::deserialize(d)? {
+            Value::Null => Ok(None),
+            v => super::parse_dec(&v).map(Some).map_err(D::Error::custom),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validate_no_json_numbers;
+
+    #[test]
+    fn values_round_trip_and_pass_strict_check() {
+        for v in [0.0, 1.0, 0.123456789, 0.8571428571428571, 1e-9, 0.5e-9] {
+            let q = quantize(v);
+            let enc = dec_value(q).unwrap();
+            validate_no_json_numbers(&enc).unwrap();
+            assert_eq!(parse_dec(&enc).unwrap().to_bits(), q.to_bits());
+        }
+        assert_eq!(dec_value(0.25).unwrap()["m"], "250000000");
+        assert!(dec_value(f64::NAN).is_err());
+
+        let enc = int_value(u64::MAX);
+        validate_no_json_numbers(&enc).unwrap();
+        assert_eq!(parse_int::<u64>(&enc).unwrap(), u64::MAX);
+        assert_eq!(parse_int::<i32>(&int_value(-7)).unwrap(), -7);
+        assert!(parse_int::<u32>(&int_value(-7)).is_err());
+
+        assert_eq!(
+            parse_dec(&json!({"@num": "dec/1", "m": "-15", "s": 1})).unwrap(),
+            -1.5
+        );
+        // The serde adapters read only canonical UNC-1 of their own kind.
+        for bad in [
+            json!(12),
+            json!("12"),
+            json!({"@num": "int/1", "v": "012"}),
+            json!({"@num": "int/1", "v": "+12"}),
+        ] {
+            assert!(parse_int::<u64>(&bad).is_err(), "{}", bad);
+        }
+        for bad in [json!(0.5), json!("0.5"), int_value(1)] {
+            assert!(parse_dec(&bad).is_err(), "{}", bad);
+        }
+        #[derive(Deserialize)]
+        struct Fields {
+            #[serde(with = "int")]
+            n: u32,
+            #[serde(default, with = "opt_int")]
+            o: Option<u32>,
+            #[serde(with = "dec")]
+            d: f64,
+        }
+        let f: Fields =
+            serde_json::from_value(json!({"n": int_value(3), "d": dec_value(0.5).unwrap()}))
+                .unwrap();
+        assert_eq!((f.n, f.o, f.d), (3, None, 0.5));
+        for bad in [
+            json!({"n": 3, "d": dec_value(0.5).unwrap()}),
+            json!({"n": int_value(3), "o": 4, "d": dec_value(0.5).unwrap()}),
+            json!({"n": int_value(3), "d": int_value(1)}),
+        ] {
+            assert!(
+                serde_json::from_value::<Fields>(bad.clone()).is_err(),
+                "{}",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn typed_values_are_canonical() {
+        let tb: UncRat =
+            serde_json::from_value(json!({"@num": "rat/1", "p": "1", "q": "90000"})).unwrap();
+        assert_eq!((tb.p(), tb.q()), (1, 90_000));
+        assert_eq!(serde_json::to_value(tb).unwrap()["q"], "90000");
+        assert_eq!(
+            UncRat::new(-30_000, -1001).unwrap().to_string(),
+            "30000/1001"
+        );
+        assert_eq!(UncRat::new(6, -4).unwrap().to_string(), "-3/2");
+        assert!(UncRat::new(1, 0).is_err());
+        assert_eq!(UncInt::from_json(&int_value(-7)).unwrap(), UncInt(-7));
+        assert_eq!(UncInt::from(u64::MAX).get::<u64>().unwrap(), u64::MAX);
+        assert!(UncInt(-1).get::<u64>().is_err());
+
+        let dec = UncDec::from_json(&json!({"@num": "dec/1", "m": "-15", "s": 1})).unwrap();
+        assert_eq!((dec.to_f64(), dec.to_string()), (-1.5, "-1.5".to_string()));
+        assert_eq!(UncDec::new(5, 3).unwrap().to_string(), "0.005");
+        assert_eq!(
+            UncDec::new(5, 3).unwrap().to_rat(),
+            UncRat::new(1, 200).unwrap()
+        );
+
+        for bad in [
+            json!({"@num": "rat/1", "q": "0"}),
+            json!({"@num": "rat/1", "p": "1", "q": "0"}),
+            json!({"@num": "rat/1", "p": "1", "q": "-2"}),
+            json!({"@num": "rat/1", "p": "2", "q": "4"}),
+            json!({"@num": "int/1", "v": "0x10"}),
+            json!({"@num": "int/1", "v": "007"}),
+            json!({"@num": "int/1", "v": "-0"}),
+            json!({"@num": "int/1", "v": "+1"}),
+            json!({"@num": "int/1", "v": ""}),
+            json!({"@num": "int/1", "v": 7}),
+            json!({"@num": "int/1", "v": "7", "extra": "1"}),
+            json!({"@num": "dec/1", "m": "1", "s": 19}),
+            json!({"@num": "dec/1", "m": "1", "s": -1}),
+            json!({"@num": "dec/1", "m": "1"}),
+            json!({"@num": "flt/1", "v": "1"}),
+        ] {
+            assert!(validate(&json!({"x": [bad.clone()]})).is_err(), "{}", bad);
+        }
+        validate(&json!({"x": [tb, dec, UncInt(3)], "s": "not a number"})).unwrap();
+        assert!(validate(&json!({"s": 1})).is_err());
+    }
+
+    #[test]
+    fn timebase_arithmetic_is_exact() {
+        let ntsc = UncRat::new(1001, 30_000).unwrap();
+        let tick = UncRat::new(1, 90_000).unwrap();
+        assert_eq!(ms_to_ticks(1000, tick), 90_000);
+        assert_eq!(ticks_to_ms(45_000, tick), 500);
+        assert_eq!(ticks_to_ms(3, ntsc), 100);
+        assert_eq!(convert_ticks(30, ntsc, tick), (90_090, true));
+        assert_eq!(convert_ticks(1, tick, ntsc), (0, false));
+        assert_eq!(ms_to_ticks(u64::MAX, tick), u64::MAX);
+
+        let half = UncRat::new(1, 2).unwrap();
+        let third = UncRat::new(1, 3).unwrap();
+        assert_eq!(half.checked_add(third).unwrap(), UncRat::new(5, 6).unwrap());
+        assert_eq!(
+            third.checked_sub(half).unwrap(),
+            UncRat::new(-1, 6).unwrap()
+        );
+        assert_eq!(half.checked_div(third).unwrap().floor(), 1);
+        assert_eq!(UncRat::new(-1, 2).unwrap().floor(), -1);
+        assert!(third < half && UncRat::from_int(i128::MAX) > half);
+        assert!(UncRat::from_int(0).recip().is_none());
+        assert!(UncRat::from_int(i128::MAX)
+            .checked_mul(UncRat::from_int(2))
+            .is_none());
+    }
+}
@@ -0,0 +1,380 @@
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io::{Read, Seek};
+
+use crate::chip::Chip;
+use crate::nrf_json::{decode_json, json_to_nrf};
+use crate::streaming_protocol::{
+    VodGlobalStats, VodPredictabilitySidecar, VodRegionRule, VodRegionStrategy,
+};
+use crate::tile_index::TileGrid;
+use crate::unc1::{self, ticks_to_ms, UncRat};
+use crate::{read_and_verify_pack, read_payload, Cid};
+
+/// IC0 tile header length (magic, version, profile, frame, position, crop, size, reserved).
+pub(crate) const IC0_HEADER_LEN: usize = 26;
+
+#[derive(Debug, Clone, Copy)]
+pub struct VodAnalyzerConfig {
+    /// Mean tile change at or below which the previous tile is reused verbatim.
+    pub copy_threshold: f64,
+    /// Mean tile change at or below which the previous tile is held and dithered.
+    pub noise_threshold: f64,
+    /// Frame-wide mean change at or above which a group transition counts as a shot cut.
+    pub cut_threshold: f64,
+}
+
+impl Default for VodAnalyzerConfig {
+    fn default() -> Self {
+        Self {
+            copy_threshold: 0.01,
+            noise_threshold: 0.08,
+            cut_threshold: 0.35,
+        }
+    }
+}
+
+/// One group of the timeline with its tile payloads by grid slot ([`TileGrid::slot_of`]).
+#[derive(Debug, Clone)]
+pub struct VodGroup {
+    pub dur_ticks: u64,
+    pub tiles: BTreeMap<u32, Vec<u8>>,
+}
+
+/// Measure tile-to-tile change between consecutive groups and derive global stats and
+/// per-region strategies. Each tile is compared with the previous group's tile in the same
+/// slot, and region tile ranges are slot ranges.
+pub fn analyze_groups(
+    groups: &[VodGroup],
+    timebase: UncRat,
+    cfg: &VodAnalyzerConfig,
+) -> (VodGlobalStats, Vec<VodRegionRule>) {
+    let mut per_tile: BTreeMap<u32, Vec<f64>> = groups
+        .iter()
+        .flat_map(|g| g.tiles.keys())
+        .map(|&slot| (slot, Vec::new()))
+        .collect();
+    let mut frame_changes = Vec::with_capacity(groups.len().saturating_sub(1));
+
+    for pair in groups.windows(2) {
+        let (prev, cur) = (&pair[0], &pair[1]);
+        let (mut sum, mut common) = (0.0, 0usize);
+        for (slot, tile) in &cur.tiles {
+            let Some(before) = prev.tiles.get(slot) else {
+                continue;
+            };
+            let c = tile_change(before, tile);
+            per_tile.entry(*slot).or_default().push(c);
+            sum += c;
+            common += 1;
+        }
+        if common > 0 {
+            frame_changes.push(sum / common as f64);
+        }
+    }
+
+    let volatility_score = if frame_changes.is_empty() {
+        0.0
+    } else {
+        (frame_changes.iter().sum::<f64>() / frame_changes.len() as f64).clamp(0.0, 1.0)
+    };
+    let cuts = frame_changes
+        .iter()
+        .filter(|c| **c >= cfg.cut_threshold)
+        .count() as u64;
+    let total_ticks: u64 = groups.iter().map(|g| g.dur_ticks).sum();
+    let total_ms = ticks_to_ms(total_ticks, timebase);
+
+    let global_stats = VodGlobalStats {
+        volatility_score,
+        average_shot_length_ms: total_ms / (cuts + 1),
+    };
+
+    let mut regions: Vec<VodRegionRule> = Vec::new();
+    for (&tile, changes) in &per_tile {
+        let (strategy, confidence) = classify_tile(changes, cfg);
+        match regions.last_mut() {
+            Some(last) if last.strategy == strategy && last.tile_range_end + 1 == tile => {
+                last.tile_range_end = tile;
+                last.confidence = last.confidence.min(confidence);
+            }
+            _ => regions.push(VodRegionRule {
+                region_id: format!("r{}", regions.len()),
+                tile_range_start: tile,
+                tile_range_end: tile,
+                group_range_start: None,
+                group_range_end: None,
+                strategy,
+                confidence,
+            }),
+        }
+    }
+
+    (global_stats, regions)
+}
+
+/// Walk an encoded pack and emit a sealed `vcx/sidecar.predictability.vod` chip targeting its
+/// manifest.
+pub fn vod_sidecar_for_pack<R: Read + Seek>(
+    mut r: R,
+    cfg: &VodAnalyzerConfig,
+) -> Result<VodPredictabilitySidecar> {
+    let pack = read_and_verify_pack(&mut r, true)?;
+    let manifest = decode_json(&pack.manifest_bytes).context("ManifestNrfDecodeFailed")?;
+    let manifest_cid = Cid::blake3(*blake3::hash(&pack.manifest_bytes).as_bytes());
+
+    let world = manifest
+        .get("@world")
+        .and_then(Value::as_str)
+        .context("ManifestMissingAnchor(@world)")?
+        .to_string();
+    let timebase = unc1::timebase(
+        manifest
+            .get("timebase")
+            .context("ManifestMissingTimebase")?,
+    )?;
+    let gots = manifest
+        .get("gots")
+        .and_then(Value::as_array)
+        .context("ManifestMissingGots")?;
+    let grid = TileGrid::from_manifest(&manifest)?;
+
+    let mut groups = Vec::with_capacity(gots.len());
+    for (g, got) in gots.iter().enumerate() {
+        let dur_ticks =
+            unc1::int_field(got, "dur_ticks").with_context(|| format!("BadGotDuration({})", g))?;
+        let refs = got
+            .get("tiles")
+            .and_then(Value::as_array)
+            .with_context(|| format!("GotMissingTiles({})", g))?;
+        let mut tiles = BTreeMap::new();
+        for (p, tile) in refs.iter().enumerate() {
+            let cid = tile_cid(tile).with_context(|| format!("TileRefMissingCid({})", g))?;
+            let entry = pack
+                .find_entry(&cid)
+                .with_context(|| format!("TileNotInIndex({})", cid))?;
+            let slot = grid.slot_of(tile, p)?;
+            if tiles.insert(slot, read_payload(&mut r, entry)?).is_some() {
+                bail!("DuplicateTileSlot({}/{})", g, slot);
+            }
+        }
+        groups.push(VodGroup { dur_ticks, tiles });
+    }
+
+    let (global_stats, regions) = analyze_groups(&groups, timebase, cfg);
+    let mut sidecar = VodPredictabilitySidecar {
+        chip_type: VodPredictabilitySidecar::CHIP_TYPE.to_string(),
+        chip_id: None,
+        chip_ver: "1.0".to_string(),
+        world,
+        target_manifest: manifest_cid,
+        global_stats,
+        regions,
+    };
+    sidecar.validate()?;
+    sidecar.seal()?;
+    Ok(sidecar)
+}
+
+fn classify_tile(changes: &[f64], cfg: &VodAnalyzerConfig) -> (VodRegionStrategy, f64) {
+    if changes.is_empty() {
+        return (VodRegionStrategy::DownloadAggressive, 0.0);
+    }
+    let n = changes.len() as f64;
+    let mean = changes.iter().sum::<f64>() / n;
+    let within = |t: f64| changes.iter().filter(|c| **c <= t).count() as f64 / n;
+    if mean <= cfg.copy_threshold {
+        (VodRegionStrategy::CopyPrevious, within(cfg.copy_threshold))
+    } else if mean <= cfg.noise_threshold {
+        (VodRegionStrategy::HoldAndNoise, within(cfg.noise_threshold))
+    } else {
+        (
+            VodRegionStrategy::DownloadAggressive,
+            1.0 - within(cfg.noise_threshold),
+        )
+    }
+}
+
+/// Normalized mean absolute sample difference in `[0,1]`. IC0 headers are skipped so the
+/// frame index does not count as change; length differences count as full change.
+pub(crate) fn tile_change(prev: &[u8], cur: &[u8]) -> f64 {
+    let (a, b) = (tile_samples(prev), tile_samples(cur));
+    let n = a.len().max(b.len());
+    if n == 0 {
+        return 0.0;
+    }
+    let common = a.len().min(b.len());
+    let diff: u64 = a[..common]
+        .iter()
+        .zip(&b[..common])
+        .map(|(x, y)| x.abs_diff(*y) as u64)
+        .sum::<u64>()
+        + 255 * (n - common) as u64;
+    diff as f64 / (255.0 * n as f64)
+}
+
+pub(crate) fn tile_samples(payload: &[u8]) -> &[u8] {
+    if payload.len() >= IC0_HEADER_LEN && payload.starts_with(b"IC0T") {
+        &payload[IC0_HEADER_LEN..]
+    } else {
+        payload
+    }
+}
+
+/// The `cid` of a manifest tile or audio reference, in string or byte form.
+pub(crate) fn tile_cid(v: &Value) -> Result<Cid> {
+    Cid::from_nrf(&json_to_nrf(v.get("cid").context("MissingField(cid)")?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{build_pack, cid_for_payload_bytes, MimeTag};
+    use serde_json::{json, Value};
+    use std::io::Cursor;
+
+    fn ic0(frame: u32, fill: u8, noise: u8) -> Vec<u8> {
+        let mut out = b"IC0T".to_vec();
+        out.extend_from_slice(&[1, 1]);
+        out.extend_from_slice(&frame.to_le_bytes());
+        out.extend_from_slice(&[0u8; 16]);
+        out.extend((0..64u8).map(|i| fill.wrapping_add(if i % 2 == 0 { noise } else { 0 })));
+        out
+    }
+
+    #[test]
+    fn static_tiles_copy_and_moving_tiles_download() {
+        let groups: Vec<VodGroup> = (0..6u32)
+            .map(|f| VodGroup {
+                dur_ticks: 3000,
+                tiles: [
+                    ic0(f, 10, 0),
+                    ic0(f, 10, 0),
+                    ic0(f, 40, (f % 2) as u8 * 20),
+                    ic0(f, (f as u8) * 40, 0),
+                ]
+                .into_iter()
+                .enumerate()
+                .map(|(slot, t)| (slot as u32, t))
+                .collect(),
+            })
+            .collect();
+
+        let (stats, regions) = analyze_groups(
+            &groups,
+            UncRat::new(1, 90_000).unwrap(),
+            &VodAnalyzerConfig::default(),
+        );
+        assert_eq!(regions.len(), 3);
+        assert_eq!(regions[0].strategy, VodRegionStrategy::CopyPrevious);
+        assert_eq!(
+            (regions[0].tile_range_start, regions[0].tile_range_end),
+            (0, 1)
+        );
+        assert_eq!(regions[0].confidence, 1.0);
+        assert_eq!(regions[1].strategy, VodRegionStrategy::HoldAndNoise);
+        assert_eq!(regions[2].strategy, VodRegionStrategy::DownloadAggressive);
+        assert!(stats.volatility_score > 0.0 && stats.volatility_score < 1.0);
+        assert_eq!(stats.average_shot_length_ms, 200);
+    }
+
+    #[test]
+    fn sidecar_from_pack_targets_manifest_and_validates() {
+        let payloads: Vec<Vec<u8>> = (0..3u32).map(|f| ic0(f, 50, 0)).collect();
+        let gots: Vec<Value> = payloads
+            .iter()
+            .map(|p| {
+                let cid = cid_for_payload_bytes(p).unwrap();
+                json!({
+                    "start_tick": {"@num": "int/1", "v": "0"},
+                    "dur_ticks": {"@num": "int/1", "v": "45000"},
+                    "tiles": [{"cid": cid, "mime": "application/vcx-ic0t", "role": "base"}]
+                })
+            })
+            .collect();
+        let manifest = json!({
+            "@type": "vcx/manifest",
+            "@id": "m:vod",
+            "@ver": "1.0",
+            "@world": "a/vod/t/prod",
+            "timebase": {"@num": "rat/1", "p": "1", "q": "90000"},
+            "duration_ticks": {"@num": "int/1", "v": "135000"},
+            "gots": gots
+        });
+
+        let mut out = Cursor::new(Vec::<u8>::new());
+        let entries = payloads
+            .into_iter()
+            .map(|p| (MimeTag::Ic0Tile, p))
+            .collect();
+        build_pack(&mut out, &manifest, entries, true).unwrap();
+        out.set_position(0);
+
+        let sidecar = vod_sidecar_for_pack(&mut out, &VodAnalyzerConfig::default()).unwrap();
+        assert_eq!(sidecar.world, "a/vod/t/prod");
+        sidecar.verify_id().unwrap();
+        assert_eq!(sidecar.global_stats.average_shot_length_ms, 1500);
+        assert_eq!(sidecar.regions.len(), 1);
+        assert_eq!(sidecar.regions[0].strategy, VodRegionStrategy::CopyPrevious);
+    }
+
+    #[test]
+    fn regions_follow_grid_slots_not_list_order() {
+        // 2x1 grid: the tile at x=0 is static, the one at x=1 changes every group, and the
+        // tile lists alternate their order.
+        let unc = |v: u64| unc1::int_value(v);
+        let mut payloads = Vec::new();
+        let gots: Vec<Value> = (0..4u32)
+            .map(|f| {
+                let tiles: Vec<(u64, Vec<u8>)> = vec![(0, ic0(f, 50, 0)), (1, ic0(f, f as u8 * 80, 0))];
+                let mut refs: Vec<Value> = tiles
+                    .iter()
+                    .map(|(x, p)| {
+                        json!({
+                            "cid": cid_for_payload_bytes(p).unwrap(),
+                            "mime": "application/vcx-ic0t",
+                            "tile_x": unc(*x),
+                            "tile_y": unc(0)
+                        })
+                    })
+                    .collect();
+                if f % 2 == 1 {
+                    refs.reverse();
+                }
+                payloads.extend(tiles.into_iter().map(|(_, p)| p));
+                json!({"start_tick": unc(f as u64 * 45_000), "dur_ticks": unc(45_000), "tiles": refs})
+            })
+            .collect();
+        let manifest = json!({
+            "@type": "vcx/manifest",
+            "@id": "m:grid",
+            "@ver": "1.0",
+            "@world": "a/vod/t/prod",
+            "timebase": {"@num": "rat/1", "p": "1", "q": "90000"},
+            "duration_ticks": unc(180_000),
+            "gots": gots
+        });
+        let mut out = Cursor::new(Vec::<u8>::new());
+        let entries = payloads
+            .into_iter()
+            .map(|p| (MimeTag::Ic0Tile, p))
+            .collect();
+        build_pack(&mut out, &manifest, entries, true).unwrap();
+        out.set_position(0);
+
+        let sidecar = vod_sidecar_for_pack(&mut out, &VodAnalyzerConfig::default()).unwrap();
+        let rules: Vec<(u32, u32, VodRegionStrategy)> = sidecar
+            .regions
+            .iter()
+            .map(|r| (r.tile_range_start, r.tile_range_end, r.strategy))
+            .collect();
+        assert_eq!(
+            rules,
+            [
+                (0, 0, VodRegionStrategy::CopyPrevious),
+                (1, 1, VodRegionStrategy::DownloadAggressive)
+            ]
+        );
+    }
+}
@@ -3,15 +3,32 @@ use clap::Parser;
 use serde::Deserialize;
 use serde_json::{Map, Value};
 use std::cmp::min;
+use std::collections::BTreeMap;
 use std::fs::{self, File};
-use std::io::{BufReader, BufWriter, Read, Write};
+use std::io::{BufReader, Cursor, Read, Write};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use vcx_pack::{build_pack, cid_for_payload_bytes, read_and_verify_pack, MimeTag};
-
-#[derive(Parser, Debug)]
+use vcx_pack::chip::Chip;
+use vcx_pack::manifest::{
+    AudioRef, Got, Lineage, LineageReason, LineageSource, Manifest, SidecarRef, TileRef, VideoInfo,
+    MANIFEST_TYPE,
+};
+use vcx_pack::streaming_protocol::{
+    BuildReceiptChip, ReceiptEncoder, ReceiptInput, SelfVerifyResult,
+};
+use vcx_pack::unc1::{self, UncRat};
+use vcx_pack::{
+    build_pack, cid_for_json_value, cid_for_payload_bytes, read_and_verify_pack,
+    validate_no_json_numbers, B3Digest, Cid, MimeTag, Pack, PackHeader,
+};
+
+mod reproduce;
+
+const PROFILE: &str = "vcx-ic0-alpha-luma-raw/v1";
+
+#[derive(Parser, Debug, Clone)]
 #[command(
     author,
     version,
@@ -19,14 +36,14 @@ use vcx_pack::{build_pack, cid_for_payload_bytes, read_and_verify_pack, MimeTag}
 )]
 struct Cli {
     /// Input MP4 file
-    #[arg(long)]
-    input: PathBuf,
+    #[arg(long, required_unless_present = "reproduce")]
+    input: Option<PathBuf>,
     /// Output VCX pack file
-    #[arg(long)]
-    out: PathBuf,
+    #[arg(long, required_unless_present = "reproduce")]
+    out: Option<PathBuf>,
     /// UBL world anchor (e.g. a/demo/t/prod)
-    #[arg(long)]
-    world: String,
+    #[arg(long, required_unless_present = "reproduce")]
+    world: Option<String>,
     /// Optional manifest id (default derived from input hash)
     #[arg(long)]
     manifest_id: Option<String>,
@@ -45,6 +62,10 @@ struct Cli {
     /// Write generated manifest JSON to this path
     #[arg(long)]
     manifest_out: Option<PathBuf>,
+    /// Write the `vcx/receipt.build` chip to this path (default: <out>.receipt.json); its
+    /// canonical NRF-1.1 bytes go next to it with an `.nrf` extension
+    #[arg(long)]
+    receipt_out: Option<PathBuf>,
     /// Disable strict UNC-1 check when building pack
     #[arg(long, default_value_t = false)]
     no_strict_unc1: bool,
@@ -54,6 +75,74 @@ struct Cli {
     /// ffprobe binary name/path
     #[arg(long, default_value = "ffprobe")]
     ffprobe_bin: String,
+    /// Threads for ffmpeg decoding and tile encoding
+    #[arg(long, default_value_t = 1)]
+    threads: u32,
+    /// Re-run the encode recorded in a build receipt (or a pack) and print a JSON diff
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "out", "world", "manifest_id", "max_frames", "tile_size", "no_audio",
+            "manifest_out", "receipt_out", "no_strict_unc1", "threads",
+        ]
+    )]
+    reproduce: Option<PathBuf>,
+    /// Encode again in-process with this many threads and fail if anything differs
+    #[arg(long)]
+    check_determinism: Option<u32>,
+    /// Write the JSON diff of --reproduce/--check-determinism here instead of stdout
+    #[arg(long)]
+    diff_out: Option<PathBuf>,
+}
+
+impl Cli {
+    // `--input`, `--out` and `--world` are required unless `--reproduce`, whose replay sets them.
+    fn input(&self) -> &Path {
+        self.input.as_deref().expect("clap requires --input")
+    }
+
+    fn out(&self) -> &Path {
+        self.out.as_deref().expect("clap requires --out")
+    }
+
+    fn world(&self) -> &str {
+        self.world.as_deref().expect("clap requires --world")
+    }
+
+    /// Every parameter that shapes the encode, by long option name, as the receipt records
+    /// them. Where the results are written and the determinism check are left out.
+    fn parameters(&self) -> BTreeMap<String, String> {
+        let mut out = BTreeMap::from([
+            ("input".to_string(), self.input().display().to_string()),
+            ("world".to_string(), self.world().to_string()),
+            ("max_frames".to_string(), self.max_frames.to_string()),
+            ("tile_size".to_string(), self.tile_size.to_string()),
+            ("no_audio".to_string(), self.no_audio.to_string()),
+            ("audio_bitrate".to_string(), self.audio_bitrate.clone()),
+            (
+                "no_strict_unc1".to_string(),
+                self.no_strict_unc1.to_string(),
+            ),
+            ("ffmpeg_bin".to_string(), self.ffmpeg_bin.clone()),
+            ("ffprobe_bin".to_string(), self.ffprobe_bin.clone()),
+            ("threads".to_string(), self.threads.to_string()),
+        ]);
+        if let Some(id) = &self.manifest_id {
+            out.insert("manifest_id".to_string(), id.clone());
+        }
+        out
+    }
+
+    fn receipt_path(&self) -> PathBuf {
+        self.receipt_out
+            .clone()
+            .unwrap_or_else(|| receipt_path_for(self.out()))
+    }
+}
+
+/// Where a pack's build receipt goes by default.
+fn receipt_path_for(pack: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.receipt.json", pack.display()))
 }
 
 #[derive(Debug, Deserialize)]
@@ -110,7 +199,23 @@ struct TilePayload {
     crop_w: u16,
     crop_h: u16,
     bytes: Vec<u8>,
-    cid: String,
+    cid: Cid,
+}
+
+/// One encode, held in memory until it is written or compared.
+struct Encoded {
+    input_hash: [u8; 32],
+    /// External tools by name, with their version line.
+    tools: BTreeMap<String, String>,
+    meta: VideoMeta,
+    frame_count: usize,
+    tile_count: usize,
+    sidecar_cid: Cid,
+    audio_cid: Option<Cid>,
+    manifest_id: String,
+    manifest: Value,
+    header: PackHeader,
+    pack: Vec<u8>,
 }
 
 fn main() -> Result<()> {
@@ -119,30 +224,51 @@ fn main() -> Result<()> {
 }
 
 fn run(cli: Cli) -> Result<()> {
+    if let Some(reference) = &cli.reproduce {
+        return reproduce::run(&cli, reference);
+    }
+    let encoded = encode(&cli, cli.threads)?;
+    if let Some(threads) = cli.check_determinism {
+        reproduce::check_determinism(&cli, &encoded, threads)?;
+    }
+    write_outputs(&cli, &encoded)
+}
+
+fn encode(cli: &Cli, threads: u32) -> Result<Encoded> {
     if cli.max_frames == 0 {
         bail!("--max-frames must be >= 1");
     }
     if cli.tile_size == 0 {
         bail!("--tile-size must be >= 1");
     }
-    if cli.world.trim().is_empty() {
+    if threads == 0 {
+        bail!("--threads must be >= 1");
+    }
+    if cli.world().trim().is_empty() {
         bail!("--world cannot be empty");
     }
-    if !cli.input.exists() {
-        bail!("input not found: {}", cli.input.display());
+    let input = cli.input();
+    if !input.exists() {
+        bail!("input not found: {}", input.display());
     }
 
-    let input_hash = hash_file_blake3(&cli.input)?;
-    let meta = probe_video_meta(&cli.ffprobe_bin, &cli.input)?;
+    let input_hash = hash_file_blake3(input)?;
+    let tools = BTreeMap::from([
+        ("ffmpeg".to_string(), tool_version(&cli.ffmpeg_bin)?),
+        ("ffprobe".to_string(), tool_version(&cli.ffprobe_bin)?),
+    ]);
+    let meta = probe_video_meta(&cli.ffprobe_bin, input)?;
 
     let frames = decode_luma_frames(
         &cli.ffmpeg_bin,
-        &cli.input,
+        input,
         meta.width,
         meta.height,
         cli.max_frames,
+        threads,
     )?;
-    let tile_payloads = build_tile_payloads(&frames, meta.width, meta.height, cli.tile_size)?;
+    let tile_payloads =
+        build_tile_payloads(&frames, meta.width, meta.height, cli.tile_size, threads)?;
     if tile_payloads.is_empty() {
         bail!("no IC0 tile payloads were produced");
     }
@@ -152,25 +278,24 @@ fn run(cli: Cli) -> Result<()> {
     } else {
         maybe_extract_audio_opus(
             &cli.ffmpeg_bin,
-            &cli.input,
+            input,
             meta.audio_codec.is_some(),
             &cli.audio_bitrate,
         )?
     };
 
     let sidecar_payload = build_sidecar_payload(
-        &cli.input,
+        input,
         &input_hash,
         &meta,
         frames.len() as u64,
         cli.tile_size,
         audio_bytes.is_some(),
     )?;
-    let (_sidecar_cid_bytes, sidecar_cid) = cid_for_payload_bytes(&sidecar_payload)?;
+    let sidecar_cid = cid_for_payload_bytes(&sidecar_payload)?;
 
     let audio_cid = if let Some(bytes) = audio_bytes.as_ref() {
-        let (_audio_cid_bytes, cid) = cid_for_payload_bytes(bytes)?;
-        Some(cid)
+        Some(cid_for_payload_bytes(bytes)?)
     } else {
         None
     };
@@ -182,66 +307,106 @@ fn run(cli: Cli) -> Result<()> {
 
     let frame_tick = ticks_per_frame(meta.fps_num, meta.fps_den, 90_000);
     let manifest = build_manifest(
-        &cli.world,
+        cli.world(),
         &manifest_id,
+        &input_hash,
         &meta,
         frames.len() as u64,
         frame_tick,
         cli.tile_size,
         &tile_payloads,
         &sidecar_cid,
-        audio_cid.as_deref(),
+        audio_cid.as_ref(),
     )?;
 
-    if let Some(manifest_out) = cli.manifest_out.as_ref() {
-        write_pretty_json(manifest_out, &manifest)?;
-    }
-
+    let tile_count = tile_payloads.len();
     let mut payloads = Vec::new();
-    for tile in &tile_payloads {
-        payloads.push((MimeTag::Ic0Tile, tile.bytes.clone()));
+    for tile in tile_payloads {
+        payloads.push((MimeTag::Ic0Tile, tile.bytes));
     }
     payloads.push((MimeTag::Sidecar, sidecar_payload));
     if let Some(bytes) = audio_bytes {
         payloads.push((MimeTag::Opus, bytes));
     }
 
-    if let Some(parent) = cli.out.parent() {
+    let mut pack = Cursor::new(Vec::new());
+    let header = build_pack(&mut pack, &manifest, payloads, !cli.no_strict_unc1)?;
+
+    Ok(Encoded {
+        input_hash,
+        tools,
+        meta,
+        frame_count: frames.len(),
+        tile_count,
+        sidecar_cid,
+        audio_cid,
+        manifest_id,
+        manifest,
+        header,
+        pack: pack.into_inner(),
+    })
+}
+
+fn write_outputs(cli: &Cli, encoded: &Encoded) -> Result<()> {
+    let out = cli.out();
+    if let Some(manifest_out) = cli.manifest_out.as_ref() {
+        write_pretty_json(manifest_out, &encoded.manifest)?;
+    }
+
+    if let Some(parent) = out.parent() {
         if !parent.as_os_str().is_empty() {
             fs::create_dir_all(parent)
                 .with_context(|| format!("create output directory {}", parent.display()))?;
         }
     }
-
-    let mut writer = BufWriter::new(
-        File::create(&cli.out).with_context(|| format!("create {}", cli.out.display()))?,
-    );
-    let header = build_pack(&mut writer, &manifest, payloads, !cli.no_strict_unc1)?;
-    writer.flush()?;
-
-    let _pack = read_and_verify_pack(
-        BufReader::new(
-            File::open(&cli.out).with_context(|| format!("open {}", cli.out.display()))?,
-        ),
-        true,
-    )
-    .context("final self-verify failed (--full)")?;
-
-    eprintln!("ok: wrote {}", cli.out.display());
+    fs::write(out, &encoded.pack).with_context(|| format!("create {}", out.display()))?;
+
+    let verified = File::open(out)
+        .with_context(|| format!("open {}", out.display()))
+        .and_then(|f| read_and_verify_pack(BufReader::new(f), true));
+
+    let input = cli.input();
+    let mut receipt = BuildReceiptChip {
+        chip_type: BuildReceiptChip::CHIP_TYPE.to_string(),
+        chip_id: None,
+        chip_ver: "1.0".to_string(),
+        world: cli.world().to_string(),
+        input: ReceiptInput {
+            name: input_name(input),
+            hash_b3: B3Digest(encoded.input_hash),
+            size_bytes: fs::metadata(input)?.len(),
+        },
+        encoder: encoder_info(),
+        parameters: cli.parameters(),
+        tools: encoded.tools.clone(),
+        manifest: cid_for_json_value(&encoded.manifest)?.0,
+        merkle_root: verified.as_ref().ok().map(merkle_root),
+        self_verify: SelfVerifyResult {
+            full: true,
+            passed: verified.is_ok(),
+            error: verified.as_ref().err().map(|e| format!("{:#}", e)),
+        },
+    };
+    receipt.validate()?;
+    receipt.seal()?;
+    let receipt_path = cli.receipt_path();
+    write_pretty_json(&receipt_path, &receipt.to_json()?)?;
+    let receipt_nrf = receipt_path.with_extension("nrf");
+    fs::write(&receipt_nrf, receipt.to_nrf()?)
+        .with_context(|| format!("create {}", receipt_nrf.display()))?;
+    verified.context("final self-verify failed (--full)")?;
+
+    let (meta, header) = (&encoded.meta, &encoded.header);
+    eprintln!("ok: wrote {}", out.display());
     eprintln!("ok: deterministic verify --full passed");
     eprintln!(
         "video: {}x{} codec={} fps={}/{} frames={}",
-        meta.width,
-        meta.height,
-        meta.video_codec,
-        meta.fps_num,
-        meta.fps_den,
-        frames.len()
+        meta.width, meta.height, meta.video_codec, meta.fps_num, meta.fps_den, encoded.frame_count
     );
     eprintln!(
         "payloads: tiles={} sidecar=1 audio={}",
-        tile_payloads.len(),
-        if audio_cid.is_some() { 1 } else { 0 }
+        encoded.tile_count,
+        if encoded.audio_cid.is_some() { 1 } else { 0 }
     );
     eprintln!(
         "layout: manifest({},{}) index({},{}) payload({},{}) trailer({},{})",
@@ -254,14 +419,23 @@ fn run(cli: Cli) -> Result<()> {
         header.trailer_off,
         header.trailer_len
     );
-    eprintln!("manifest @id: {}", manifest_id);
-    eprintln!("sidecar cid: {}", sidecar_cid);
-    if let Some(cid) = audio_cid {
+    eprintln!("manifest @id: {}", encoded.manifest_id);
+    eprintln!("manifest cid: {}", receipt.manifest);
+    if let Some(root) = &receipt.merkle_root {
+        eprintln!("merkle root: {}", root);
+    }
+    eprintln!("receipt: {} ({})", receipt_path.display(), receipt.cid()?);
+    eprintln!("sidecar cid: {}", encoded.sidecar_cid);
+    if let Some(cid) = encoded.audio_cid {
         eprintln!("audio cid: {}", cid);
     }
     Ok(())
 }
 
+fn merkle_root(pack: &Pack) -> B3Digest {
+    B3Digest(pack.merkle.root)
+}
+
 fn hash_file_blake3(path: &Path) -> Result<[u8; 32]> {
     let mut hasher = blake3::Hasher::new();
     let mut file = File::open(path).with_context(|| format!("open {}", path.display()))?;
@@ -276,6 +450,31 @@ fn hash_file_blake3(path: &Path) -> Result<[u8; 32]> {
     Ok(*hasher.finalize().as_bytes())
 }
 
+/// First line of `<bin> -version`, which names the build (e.g. `ffmpeg version 6.1.1 ...`).
+fn tool_version(bin: &str) -> Result<String> {
+    let out = Command::new(bin)
+        .arg("-version")
+        .output()
+        .with_context(|| format!("run {} -version", bin))?;
+    if !out.status.success() {
+        bail!("{} -version failed", bin);
+    }
+    String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .next()
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty())
+        .with_context(|| format!("{} -version printed nothing", bin))
+}
+
+fn input_name(input: &Path) -> String {
+    input
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("input.mp4")
+        .to_string()
+}
+
 fn default_manifest_id(hash: &[u8; 32]) -> String {
     let hex_hash = hex::encode(hash);
     format!("m:mp4:{}", &hex_hash[..24])
@@ -386,6 +585,7 @@ fn decode_luma_frames(
     width: u32,
     height: u32,
     max_frames: u32,
+    threads: u32,
 ) -> Result<Vec<Vec<u8>>> {
     let frame_size = (width as usize)
         .checked_mul(height as usize)
@@ -400,16 +600,10 @@ fn decode_luma_frames(
         .args(["-v", "error", "-nostdin", "-i"])
         .arg(input)
         .args([
-            "-map",
-            "0:v:0",
-            "-pix_fmt",
-            "yuv420p",
-            "-vsync",
-            "0",
-            "-threads",
-            "1",
-            "-frames:v",
+            "-map", "0:v:0", "-pix_fmt", "yuv420p", "-vsync", "0", "-threads",
         ])
+        .arg(threads.to_string())
+        .arg("-frames:v")
         .arg(max_frames.to_string())
         .args(["-f", "rawvideo", "pipe:1"])
         .stdout(Stdio::piped())
@@ -489,6 +683,11 @@ fn maybe_extract_audio_opus(
             "20",
             "-compression_level",
             "10",
+            // No random Ogg stream serial, no version string in the Opus tags.
+            "-flags:a",
+            "+bitexact",
+            "-fflags",
+            "+bitexact",
             "-f",
             "opus",
             "-y",
@@ -524,11 +723,37 @@ fn unique_tmp_path(prefix: &str, ext: &str) -> PathBuf {
     std::env::temp_dir().join(format!("{}_{}_{}.{}", prefix, std::process::id(), ts, ext))
 }
 
+/// Tiles of every frame, in frame order; runs of frames are encoded on `threads` workers.
 fn build_tile_payloads(
     frames: &[Vec<u8>],
     width: u32,
     height: u32,
     tile_size: u16,
+    threads: u32,
+) -> Result<Vec<TilePayload>> {
+    let per_worker = frames.len().div_ceil(threads.max(1) as usize).max(1);
+    std::thread::scope(|s| {
+        let workers: Vec<_> = frames
+            .chunks(per_worker)
+            .enumerate()
+            .map(|(i, run)| {
+                s.spawn(move || frame_run_tiles(run, i * per_worker, width, height, tile_size))
+            })
+            .collect();
+        let mut out = Vec::new();
+        for worker in workers {
+            out.extend(worker.join().expect("tile worker panicked")?);
+        }
+        Ok(out)
+    })
+}
+
+fn frame_run_tiles(
+    frames: &[Vec<u8>],
+    first_frame: usize,
+    width: u32,
+    height: u32,
+    tile_size: u16,
 ) -> Result<Vec<TilePayload>> {
     let frame_w = width as usize;
     let frame_h = height as usize;
@@ -540,7 +765,8 @@ fn build_tile_payloads(
         .context("frame dimensions overflow when building tiles")?;
 
     let mut out = Vec::with_capacity(frames.len() * cols * rows);
-    for (frame_index, y_plane) in frames.iter().enumerate() {
+    for (i, y_plane) in frames.iter().enumerate() {
+        let frame_index = first_frame + i;
         if y_plane.len() != expected_y_len {
             bail!(
                 "decoded frame {} has wrong Y size: {} (expected {})",
@@ -563,7 +789,7 @@ fn build_tile_payloads(
                     tile_y_u16,
                     tile_size,
                 );
-                let (_cid_bytes, cid) = cid_for_payload_bytes(&payload)?;
+                let cid = cid_for_payload_bytes(&payload)?;
                 let x0 = tile_x * tile;
                 let y0 = tile_y * tile;
                 let crop_w = min(tile, frame_w.saturating_sub(x0)) as u16;
@@ -630,16 +856,7 @@ fn build_sidecar_payload(
     has_audio_payload: bool,
 ) -> Result<Vec<u8>> {
     let mut source = Map::new();
-    source.insert(
-        "name".to_string(),
-        Value::String(
-            input
-                .file_name()
-                .and_then(|s| s.to_str())
-                .unwrap_or("input.mp4")
-                .to_string(),
-        ),
-    );
+    source.insert("name".to_string(), Value::String(input_name(input)));
     source.insert(
         "hash_b3".to_string(),
         Value::String(format!("b3:{}", hex::encode(input_hash))),
@@ -654,14 +871,14 @@ fn build_sidecar_payload(
         "codec_out".to_string(),
         Value::String("VCX-IC0-ALPHA".to_string()),
     );
-    video.insert("width".to_string(), Value::from(meta.width));
-    video.insert("height".to_string(), Value::from(meta.height));
-    video.insert("fps_num".to_string(), Value::from(meta.fps_num));
-    video.insert("fps_den".to_string(), Value::from(meta.fps_den));
-    video.insert("frames_encoded".to_string(), Value::from(frame_count));
-    video.insert("tile_size".to_string(), Value::from(tile_size));
+    video.insert("width".to_string(), unc1::int_value(meta.width as u64));
+    video.insert("height".to_string(), unc1::int_value(meta.height as u64));
+    video.insert("fps_num".to_string(), unc1::int_value(meta.fps_num as u64));
+    video.insert("fps_den".to_string(), unc1::int_value(meta.fps_den as u64));
+    video.insert("frames_encoded".to_string(), unc1::int_value(frame_count));
+    video.insert("tile_size".to_string(), unc1::int_value(tile_size as u64));
     if let Some(duration) = meta.duration_seconds {
-        video.insert("duration_seconds".to_string(), Value::from(duration));
+        video.insert("duration_seconds".to_string(), unc1::dec_value(duration)?);
     }
 
     let mut sidecar = Map::new();
@@ -673,10 +890,7 @@ fn build_sidecar_payload(
         "encoder".to_string(),
         Value::String(format!("vcx_enc_cli/{}", env!("CARGO_PKG_VERSION"))),
     );
-    sidecar.insert(
-        "profile".to_string(),
-        Value::String("vcx-ic0-alpha-luma-raw/v1".to_string()),
-    );
+    sidecar.insert("profile".to_string(), Value::String(PROFILE.to_string()));
     sidecar.insert("source".to_string(), Value::Object(source));
     sidecar.insert("video".to_string(), Value::Object(video));
     sidecar.insert(
@@ -690,121 +904,104 @@ fn build_sidecar_payload(
             .map(Value::String)
             .unwrap_or(Value::Null),
     );
-    serde_json::to_vec(&Value::Object(sidecar)).context("serialize sidecar payload")
+    let sidecar = Value::Object(sidecar);
+    validate_no_json_numbers(&sidecar)?;
+    serde_json::to_vec(&sidecar).context("serialize sidecar payload")
 }
 
 #[allow(clippy::too_many_arguments)]
 fn build_manifest(
     world: &str,
     manifest_id: &str,
+    input_hash: &[u8; 32],
     meta: &VideoMeta,
     frame_count: u64,
     frame_tick: u64,
     tile_size: u16,
     tile_payloads: &[TilePayload],
-    sidecar_cid: &str,
-    audio_cid: Option<&str>,
+    sidecar_cid: &Cid,
+    audio_cid: Option<&Cid>,
 ) -> Result<Value> {
     if frame_count == 0 {
         bail!("frame_count cannot be zero");
     }
     let duration_ticks = frame_tick.saturating_mul(frame_count);
 
-    let mut tiles_by_frame: Vec<Vec<&TilePayload>> = vec![Vec::new(); frame_count as usize];
+    let mut gots: Vec<Got> = (0..frame_count)
+        .map(|frame_idx| Got {
+            start_tick: frame_tick.saturating_mul(frame_idx),
+            dur_ticks: frame_tick,
+            tiles: Vec::new(),
+        })
+        .collect();
     for tile in tile_payloads {
-        let idx = tile.frame_index as usize;
-        if idx >= tiles_by_frame.len() {
-            bail!("tile frame index out of bounds: {}", tile.frame_index);
-        }
-        tiles_by_frame[idx].push(tile);
-    }
-
-    let mut gots = Vec::with_capacity(frame_count as usize);
-    for (frame_idx, tiles) in tiles_by_frame.iter().enumerate() {
-        let mut tile_refs = Vec::with_capacity(tiles.len());
-        for tile in tiles {
-            let mut item = Map::new();
-            item.insert("cid".to_string(), Value::String(tile.cid.clone()));
-            item.insert(
-                "mime".to_string(),
-                Value::String("application/vcx-ic0t".to_string()),
-            );
-            item.insert("role".to_string(), Value::String("base".to_string()));
-            item.insert("tile_x".to_string(), Value::String(tile.tile_x.to_string()));
-            item.insert("tile_y".to_string(), Value::String(tile.tile_y.to_string()));
-            item.insert("crop_w".to_string(), Value::String(tile.crop_w.to_string()));
-            item.insert("crop_h".to_string(), Value::String(tile.crop_h.to_string()));
-            tile_refs.push(Value::Object(item));
-        }
-
-        let mut got = Map::new();
-        got.insert(
-            "start_tick".to_string(),
-            unc_int(frame_tick.saturating_mul(frame_idx as u64)),
-        );
-        got.insert("dur_ticks".to_string(), unc_int(frame_tick));
-        got.insert("tiles".to_string(), Value::Array(tile_refs));
-        gots.push(Value::Object(got));
+        let got = gots
+            .get_mut(tile.frame_index as usize)
+            .with_context(|| format!("tile frame index out of bounds: {}", tile.frame_index))?;
+        got.tiles.push(TileRef {
+            cid: tile.cid,
+            mime: "application/vcx-ic0t".to_string(),
+            role: Some("base".to_string()),
+            tile_x: Some(tile.tile_x as u32),
+            tile_y: Some(tile.tile_y as u32),
+            crop_w: Some(tile.crop_w as u32),
+            crop_h: Some(tile.crop_h as u32),
+        });
     }
 
-    let mut video = Map::new();
-    video.insert(
-        "codec".to_string(),
-        Value::String("VCX-IC0-ALPHA".to_string()),
-    );
-    video.insert("width".to_string(), unc_int(meta.width as u64));
-    video.insert("height".to_string(), unc_int(meta.height as u64));
-    video.insert(
-        "fps".to_string(),
-        unc_rat(meta.fps_num as u64, meta.fps_den as u64),
-    );
-    video.insert("frames".to_string(), unc_int(frame_count));
-    video.insert("tile_size".to_string(), unc_int(tile_size as u64));
-
-    let mut sidecars = Vec::new();
-    let mut sc = Map::new();
-    sc.insert("cid".to_string(), Value::String(sidecar_cid.to_string()));
-    sc.insert(
-        "mime".to_string(),
-        Value::String("application/vcx-sidecar".to_string()),
-    );
-    sc.insert(
-        "type".to_string(),
-        Value::String("vcx/sidecar.media.import.v1".to_string()),
-    );
-    sidecars.push(Value::Object(sc));
-
-    let mut root = Map::new();
-    root.insert(
-        "@type".to_string(),
-        Value::String("vcx/manifest".to_string()),
-    );
-    root.insert("@id".to_string(), Value::String(manifest_id.to_string()));
-    root.insert("@ver".to_string(), Value::String("1.0".to_string()));
-    root.insert("@world".to_string(), Value::String(world.to_string()));
-    root.insert(
-        "profile".to_string(),
-        Value::String("vcx-ic0-alpha-luma-raw/v1".to_string()),
-    );
-    root.insert("timebase".to_string(), unc_rat(1, 90_000));
-    root.insert("duration_ticks".to_string(), unc_int(duration_ticks));
-    root.insert("video".to_string(), Value::Object(video));
-    root.insert("gots".to_string(), Value::Array(gots));
-    root.insert("sidecars".to_string(), Value::Array(sidecars));
-
-    if let Some(cid) = audio_cid {
-        let mut audio = Map::new();
-        audio.insert("codec".to_string(), Value::String("opus".to_string()));
-        audio.insert("cid".to_string(), Value::String(cid.to_string()));
-        audio.insert("mime".to_string(), Value::String("audio/opus".to_string()));
-        root.insert("audio".to_string(), Value::Object(audio));
-    }
+    let manifest = Manifest {
+        manifest_type: MANIFEST_TYPE.to_string(),
+        id: manifest_id.to_string(),
+        ver: "1.0".to_string(),
+        world: world.to_string(),
+        profile: Some(PROFILE.to_string()),
+        timebase: UncRat::new(1, 90_000)?,
+        duration_ticks,
+        video: Some(VideoInfo {
+            codec: Some("VCX-IC0-ALPHA".to_string()),
+            width: meta.width,
+            height: meta.height,
+            fps: Some(UncRat::new(meta.fps_num.into(), meta.fps_den.into())?),
+            frames: Some(frame_count),
+            tile_size: Some(tile_size as u32),
+        }),
+        gots,
+        audio: audio_cid.map(|cid| AudioRef {
+            codec: Some("opus".to_string()),
+            cid: *cid,
+            mime: "audio/opus".to_string(),
+        }),
+        sidecars: vec![SidecarRef {
+            cid: *sidecar_cid,
+            mime: "application/vcx-sidecar".to_string(),
+            sidecar_type: Some("vcx/sidecar.media.import.v1".to_string()),
+        }],
+        overlays: Vec::new(),
+        audio_edits: Vec::new(),
+        transitions: Vec::new(),
+        source_frame_hint: meta.frame_count_hint,
+        lineage: Some(Lineage {
+            reason: LineageReason::Encode,
+            parents: Vec::new(),
+            edit_decision: None,
+            source: Some(LineageSource {
+                hash_b3: B3Digest(*input_hash),
+                encoder: encoder_info(),
+            }),
+            note: None,
+        }),
+    };
+    manifest.validate().context("built manifest is invalid")?;
+    manifest.to_json()
+}
 
-    if let Some(hint) = meta.frame_count_hint {
-        root.insert("source_frame_hint".to_string(), unc_int(hint));
+/// This build, as recorded in the receipt and the manifest's lineage.
+fn encoder_info() -> ReceiptEncoder {
+    ReceiptEncoder {
+        name: "vcx_enc_cli".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        profile: PROFILE.to_string(),
     }
-
-    Ok(Value::Object(root))
 }
 
 fn ticks_per_frame(fps_num: u32, fps_den: u32, timebase: u64) -> u64 {
@@ -817,21 +1014,6 @@ fn ticks_per_frame(fps_num: u32, fps_den: u32, timebase: u64) -> u64 {
     rounded.max(1)
 }
 
-fn unc_int(v: u64) -> Value {
-    let mut o = Map::new();
-    o.insert("@num".to_string(), Value::String("int/1".to_string()));
-    o.insert("v".to_string(), Value::String(v.to_string()));
-    Value::Object(o)
-}
-
-fn unc_rat(p: u64, q: u64) -> Value {
-    let mut o = Map::new();
-    o.insert("@num".to_string(), Value::String("rat/1".to_string()));
-    o.insert("p".to_string(), Value::String(p.to_string()));
-    o.insert("q".to_string(), Value::String(q.to_string()));
-    Value::Object(o)
-}
-
 fn write_pretty_json(path: &Path, value: &Value) -> Result<()> {
     if let Some(parent) = path.parent() {
         if !parent.as_os_str().is_empty() {
@@ -868,6 +1050,44 @@ mod tests {
         assert!(a.starts_with(b"IC0T"));
     }
 
+    #[test]
+    fn import_sidecar_is_strict_unc1() {
+        let meta = VideoMeta {
+            width: 1280,
+            height: 720,
+            fps_num: 30000,
+            fps_den: 1001,
+            duration_seconds: Some(12.345),
+            frame_count_hint: None,
+            video_codec: "h264".to_string(),
+            audio_codec: None,
+        };
+        let bytes =
+            build_sidecar_payload(Path::new("in.mp4"), &[0u8; 32], &meta, 8, 64, false).unwrap();
+        let sidecar: Value = serde_json::from_slice(&bytes).unwrap();
+        validate_no_json_numbers(&sidecar).unwrap();
+        assert_eq!(sidecar["video"]["fps_num"]["v"], "30000");
+        assert_eq!(
+            unc1::parse_dec(&sidecar["video"]["duration_seconds"]).unwrap(),
+            12.345
+        );
+    }
+
+    #[test]
+    fn tile_payloads_do_not_depend_on_threads() {
+        let (w, h) = (20u32, 9u32);
+        let frames: Vec<Vec<u8>> = (0..5u8)
+            .map(|f| (0..w * h).map(|x| (x as u8).wrapping_mul(f)).collect())
+            .collect();
+        let one = build_tile_payloads(&frames, w, h, 8, 1).unwrap();
+        for threads in [2, 3, 8] {
+            let many = build_tile_payloads(&frames, w, h, 8, threads).unwrap();
+            let key = |t: &TilePayload| (t.frame_index, t.tile_x, t.tile_y, t.cid);
+            assert!(one.iter().map(key).eq(many.iter().map(key)), "{}", threads);
+        }
+        assert_eq!(one.len(), 5 * 3 * 2);
+    }
+
     #[test]
     fn ticks_rounding() {
         // 29.97 fps at 90kHz timebase -> 3003 ticks
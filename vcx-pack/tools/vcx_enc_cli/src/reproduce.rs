@@ -0,0 +1,640 @@
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use std::str::FromStr;
+
+use vcx_pack::chip::Chip;
+use vcx_pack::manifest::{Got, Manifest};
+use vcx_pack::streaming_protocol::BuildReceiptChip;
+use vcx_pack::{read_and_verify_pack, read_payload, B3Digest, Cid};
+
+use crate::{
+    encode, hash_file_blake3, merkle_root, receipt_path_for, write_pretty_json, Cli, Encoded,
+};
+
+const IMPORT_SIDECAR: &str = "vcx/sidecar.media.import.v1";
+
+/// What an encode produced, as far as it is known.
+struct Snapshot {
+    manifest_cid: Cid,
+    merkle_root: Option<B3Digest>,
+    /// Absent when only a receipt is known.
+    manifest: Option<Manifest>,
+    audio: Option<Vec<u8>>,
+    /// The `vcx/sidecar.media.import.v1` payload.
+    import: Option<Value>,
+}
+
+impl Snapshot {
+    fn from_pack(bytes: &[u8]) -> Result<Self> {
+        let pack = read_and_verify_pack(Cursor::new(bytes), true)?;
+        let manifest = Manifest::from_nrf(&pack.manifest_bytes)?;
+        let payload = |cid: &Cid| -> Result<Vec<u8>> {
+            let entry = pack
+                .find_entry(cid)
+                .with_context(|| format!("MissingPayload({})", cid))?;
+            read_payload(Cursor::new(bytes), entry)
+        };
+        let audio = manifest
+            .audio
+            .as_ref()
+            .map(|a| payload(&a.cid))
+            .transpose()?;
+        let import = manifest
+            .sidecars
+            .iter()
+            .find(|s| s.sidecar_type.as_deref() == Some(IMPORT_SIDECAR))
+            .map(|s| -> Result<Value> { Ok(serde_json::from_slice(&payload(&s.cid)?)?) })
+            .transpose()
+            .context("BadImportSidecar")?;
+        Ok(Self {
+            manifest_cid: Cid::blake3(*blake3::hash(&pack.manifest_bytes).as_bytes()),
+            merkle_root: Some(merkle_root(&pack)),
+            manifest: Some(manifest),
+            audio,
+            import,
+        })
+    }
+
+    fn from_receipt(receipt: &BuildReceiptChip) -> Self {
+        Self {
+            manifest_cid: receipt.manifest,
+            merkle_root: receipt.merkle_root,
+            manifest: None,
+            audio: None,
+            import: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct Pair<T> {
+    expected: T,
+    actual: T,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct TileDivergence {
+    frame: usize,
+    tile_x: u32,
+    tile_y: u32,
+    /// Absent when the tile only exists on the other side.
+    expected: Option<Cid>,
+    actual: Option<Cid>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct AudioDivergence {
+    expected: Option<Cid>,
+    actual: Option<Cid>,
+    /// First Ogg packet that differs; absent when only the container differs or either
+    /// stream cannot be read as Ogg.
+    first_packet: Option<usize>,
+    first_byte: Option<usize>,
+}
+
+/// Comparison of a reference encode with a re-run, down to the first divergent tile and
+/// audio packet.
+#[derive(Debug, Clone, Serialize)]
+struct ReproDiff {
+    identical: bool,
+    manifest: Pair<Cid>,
+    merkle_root: Pair<Option<B3Digest>>,
+    /// False when the reference is a receipt without its pack: only CID and root are known.
+    payloads_compared: bool,
+    /// Top-level manifest fields that differ, payload references aside.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    fields: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frames: Option<Pair<usize>>,
+    tiles_differing: usize,
+    /// In frame order, then row-major within the frame.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    first_tile: Option<TileDivergence>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sidecars: Option<Pair<Vec<Cid>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    audio: Option<AudioDivergence>,
+    /// Tool version lines that changed since the receipt.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    tools: BTreeMap<String, Pair<String>>,
+    /// The re-run against a second in-process encode with another thread count.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    determinism: Option<Box<ReproDiff>>,
+}
+
+fn compare(expected: &Snapshot, actual: &Snapshot) -> ReproDiff {
+    let mut diff = ReproDiff {
+        identical: expected.manifest_cid == actual.manifest_cid
+            && expected.merkle_root == actual.merkle_root,
+        manifest: Pair {
+            expected: expected.manifest_cid,
+            actual: actual.manifest_cid,
+        },
+        merkle_root: Pair {
+            expected: expected.merkle_root,
+            actual: actual.merkle_root,
+        },
+        payloads_compared: false,
+        fields: Vec::new(),
+        frames: None,
+        tiles_differing: 0,
+        first_tile: None,
+        sidecars: None,
+        audio: None,
+        tools: BTreeMap::new(),
+        determinism: None,
+    };
+    let (Some(em), Some(am)) = (&expected.manifest, &actual.manifest) else {
+        return diff;
+    };
+    diff.payloads_compared = true;
+    diff.fields = differing_fields(em, am);
+    diff.frames = Some(Pair {
+        expected: em.gots.len(),
+        actual: am.gots.len(),
+    });
+
+    for frame in 0..em.gots.len().max(am.gots.len()) {
+        let (e, a) = (tile_cids(em.gots.get(frame)), tile_cids(am.gots.get(frame)));
+        let keys: BTreeSet<_> = e.keys().chain(a.keys()).collect();
+        for &(tile_y, tile_x) in keys {
+            let (ec, ac) = (e.get(&(tile_y, tile_x)), a.get(&(tile_y, tile_x)));
+            if ec == ac {
+                continue;
+            }
+            diff.tiles_differing += 1;
+            diff.first_tile.get_or_insert(TileDivergence {
+                frame,
+                tile_x,
+                tile_y,
+                expected: ec.copied(),
+                actual: ac.copied(),
+            });
+        }
+    }
+
+    let sidecars = |m: &Manifest| m.sidecars.iter().map(|s| s.cid).collect::<Vec<_>>();
+    if sidecars(em) != sidecars(am) {
+        diff.sidecars = Some(Pair {
+            expected: sidecars(em),
+            actual: sidecars(am),
+        });
+    }
+
+    let (ea, aa) = (
+        em.audio.as_ref().map(|a| a.cid),
+        am.audio.as_ref().map(|a| a.cid),
+    );
+    if ea != aa {
+        let bytes = expected.audio.as_deref().zip(actual.audio.as_deref());
+        diff.audio = Some(AudioDivergence {
+            expected: ea,
+            actual: aa,
+            first_packet: bytes.and_then(|(e, a)| first_differing_packet(e, a)),
+            first_byte: bytes.and_then(|(e, a)| first_differing_byte(e, a)),
+        });
+    }
+    diff
+}
+
+/// Tile CIDs of a group keyed by `(tile_y, tile_x)`, so iteration is row-major. Tiles without
+/// coordinates are keyed by their position.
+fn tile_cids(got: Option<&Got>) -> BTreeMap<(u32, u32), Cid> {
+    got.map_or_else(BTreeMap::new, |g| {
+        g.tiles
+            .iter()
+            .enumerate()
+            .map(|(i, t)| {
+                let key = (t.tile_y.unwrap_or(0), t.tile_x.unwrap_or(i as u32));
+                (key, t.cid)
+            })
+            .collect()
+    })
+}
+
+fn differing_fields(expected: &Manifest, actual: &Manifest) -> Vec<String> {
+    let object = |m: &Manifest| match serde_json::to_value(m) {
+        Ok(Value::Object(o)) => o,
+        _ => Default::default(),
+    };
+    let (e, a) = (object(expected), object(actual));
+    let keys: BTreeSet<&String> = e.keys().chain(a.keys()).collect();
+    keys.into_iter()
+        .filter(|k| !matches!(k.as_str(), "gots" | "audio" | "sidecars"))
+        .filter(|k| e.get(*k) != a.get(*k))
+        .cloned()
+        .collect()
+}
+
+/// Packets of an Ogg stream in order. A packet continues while its lacing values are 255,
+/// possibly across pages.
+fn ogg_packets(mut bytes: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut packets = Vec::new();
+    let mut packet = Vec::new();
+    while !bytes.is_empty() {
+        if bytes.len() < 27 || &bytes[..4] != b"OggS" {
+            return None;
+        }
+        let segments = bytes[26] as usize;
+        let lacing = bytes.get(27..27 + segments)?;
+        let mut body = &bytes[27 + segments..];
+        for &len in lacing {
+            packet.extend_from_slice(body.get(..len as usize)?);
+            body = &body[len as usize..];
+            if len < 255 {
+                packets.push(std::mem::take(&mut packet));
+            }
+        }
+        bytes = body;
+    }
+    if !packet.is_empty() {
+        packets.push(packet);
+    }
+    Some(packets)
+}
+
+fn first_differing_packet(expected: &[u8], actual: &[u8]) -> Option<usize> {
+    let (e, a) = (ogg_packets(expected)?, ogg_packets(actual)?);
+    (0..e.len().max(a.len())).find(|&i| e.get(i) != a.get(i))
+}
+
+fn first_differing_byte(expected: &[u8], actual: &[u8]) -> Option<usize> {
+    expected
+        .iter()
+        .zip(actual)
+        .position(|(e, a)| e != a)
+        .or_else(|| (expected.len() != actual.len()).then(|| expected.len().min(actual.len())))
+}
+
+fn tool_changes(
+    recorded: &BTreeMap<String, String>,
+    current: &BTreeMap<String, String>,
+) -> BTreeMap<String, Pair<String>> {
+    let names: BTreeSet<&String> = recorded.keys().chain(current.keys()).collect();
+    names
+        .into_iter()
+        .filter(|n| recorded.get(*n) != current.get(*n))
+        .map(|n| {
+            let version = |m: &BTreeMap<String, String>| m.get(n).cloned().unwrap_or_default();
+            let pair = Pair {
+                expected: version(recorded),
+                actual: version(current),
+            };
+            (n.clone(), pair)
+        })
+        .collect()
+}
+
+fn parse_receipt(bytes: &[u8]) -> Result<BuildReceiptChip> {
+    let receipt: BuildReceiptChip = serde_json::from_slice(bytes).context("BadReceipt")?;
+    receipt.validate()?;
+    receipt.verify_id()?;
+    Ok(receipt)
+}
+
+/// The pack a receipt describes, if it still sits unchanged next to a receipt at its default
+/// path (`<pack>.receipt.json`).
+fn receipt_pack(receipt: &BuildReceiptChip, receipt_path: &Path) -> Option<Snapshot> {
+    let path = receipt_path.to_str()?.strip_suffix(".receipt.json")?;
+    let snapshot = Snapshot::from_pack(&fs::read(path).ok()?).ok()?;
+    if snapshot.manifest_cid != receipt.manifest {
+        eprintln!(
+            "note: {} is not the receipt's pack; comparing CID and root only",
+            path
+        );
+        return None;
+    }
+    Some(snapshot)
+}
+
+fn recorded<T: FromStr>(parameters: &BTreeMap<String, String>, key: &str) -> Result<T> {
+    parameters
+        .get(key)
+        .and_then(|v| v.parse().ok())
+        .with_context(|| format!("BadReceiptParameter({})", key))
+}
+
+/// The encode a receipt recorded. Everything that shapes the output is replayed; where the
+/// source and the tools are (`--input`, `--ffmpeg-bin`, `--ffprobe-bin`) and what to do with
+/// the result come from the current command line, the input defaulting to the recorded path.
+/// The replay is never written, so it has no `--out`.
+fn replay_receipt(current: &Cli, parameters: &BTreeMap<String, String>) -> Result<Cli> {
+    let mut cli = current.clone();
+    cli.reproduce = None;
+    if cli.input.is_none() {
+        cli.input = Some(recorded(parameters, "input")?);
+    }
+    cli.world = Some(recorded(parameters, "world")?);
+    cli.manifest_id = parameters.get("manifest_id").cloned();
+    cli.max_frames = recorded(parameters, "max_frames")?;
+    cli.tile_size = recorded(parameters, "tile_size")?;
+    cli.no_audio = recorded(parameters, "no_audio")?;
+    cli.audio_bitrate = recorded(parameters, "audio_bitrate")?;
+    cli.no_strict_unc1 = recorded(parameters, "no_strict_unc1")?;
+    // Receipts from before `--threads` existed were single-threaded.
+    cli.threads = match parameters.get("threads") {
+        Some(_) => recorded(parameters, "threads")?,
+        None => 1,
+    };
+    Ok(cli)
+}
+
+/// Without a receipt: the encode the pack's manifest implies. `--input` must name the source
+/// (checked against the import sidecar's hash); `--audio-bitrate` is taken as given.
+fn replay_pack(current: &Cli, path: &Path, pack: &Snapshot) -> Result<(Cli, B3Digest)> {
+    let manifest = pack.manifest.as_ref().context("MissingManifest")?;
+    let video = manifest.video.as_ref().context("PackHasNoVideoInfo")?;
+    let hash = pack
+        .import
+        .as_ref()
+        .and_then(|s| s["source"]["hash_b3"].as_str())
+        .context("PackHasNoImportSidecar")?
+        .parse()?;
+    let mut cli = current.clone();
+    cli.reproduce = None;
+    if cli.input.is_none() {
+        bail!("--input is required to reproduce a pack without a build receipt");
+    }
+    cli.out = Some(path.to_path_buf());
+    cli.world = Some(manifest.world.clone());
+    cli.manifest_id = Some(manifest.id.clone());
+    cli.max_frames = video
+        .frames
+        .and_then(|f| u32::try_from(f).ok())
+        .context("PackHasNoFrameCount")?;
+    cli.tile_size = video
+        .tile_size
+        .and_then(|t| u16::try_from(t).ok())
+        .context("PackHasNoTileSize")?;
+    cli.no_audio = manifest.audio.is_none();
+    Ok((cli, hash))
+}
+
+/// Encode again with `threads` and compare with `first`.
+fn determinism_diff(cli: &Cli, first: &Snapshot, threads: u32) -> Result<ReproDiff> {
+    if threads == cli.threads {
+        bail!("--check-determinism {} must differ from --threads", threads);
+    }
+    let second = encode(cli, threads)?;
+    Ok(compare(first, &Snapshot::from_pack(&second.pack)?))
+}
+
+fn emit(diff: &ReproDiff, out: Option<&Path>) -> Result<()> {
+    match out {
+        Some(path) => write_pretty_json(path, &serde_json::to_value(diff)?),
+        None => {
+            println!("{}", serde_json::to_string_pretty(diff)?);
+            Ok(())
+        }
+    }
+}
+
+pub(crate) fn check_determinism(cli: &Cli, first: &Encoded, threads: u32) -> Result<()> {
+    let diff = determinism_diff(cli, &Snapshot::from_pack(&first.pack)?, threads)?;
+    if !diff.identical {
+        emit(&diff, cli.diff_out.as_deref())?;
+        bail!(
+            "NondeterministicEncode(--threads {} vs {})",
+            cli.threads,
+            threads
+        );
+    }
+    eprintln!("ok: identical encode with --threads {}", threads);
+    Ok(())
+}
+
+/// `--reproduce`: re-run the encode behind a receipt or pack and compare the outcome.
+pub(crate) fn run(cli: &Cli, reference: &Path) -> Result<()> {
+    let bytes = fs::read(reference).with_context(|| format!("read {}", reference.display()))?;
+    let (receipt, expected) = if bytes.starts_with(b"VCX1") {
+        let pack = Snapshot::from_pack(&bytes).context("reference pack failed verify --full")?;
+        let receipt_path = receipt_path_for(reference);
+        let receipt = match fs::read(&receipt_path) {
+            Ok(b) => Some(parse_receipt(&b)?),
+            Err(_) => None,
+        };
+        if let Some(r) = receipt.as_ref().filter(|r| r.manifest != pack.manifest_cid) {
+            bail!(
+                "ReceiptDoesNotMatchPack({} != {})",
+                r.manifest,
+                pack.manifest_cid
+            );
+        }
+        (receipt, pack)
+    } else {
+        let receipt = parse_receipt(&bytes)?;
+        let expected =
+            receipt_pack(&receipt, reference).unwrap_or_else(|| Snapshot::from_receipt(&receipt));
+        (Some(receipt), expected)
+    };
+    let (replay, source_hash) = match &receipt {
+        Some(r) => (replay_receipt(cli, &r.parameters)?, r.input.hash_b3),
+        None => replay_pack(cli, reference, &expected)?,
+    };
+    let input_hash = B3Digest(hash_file_blake3(replay.input())?);
+    if input_hash != source_hash {
+        bail!("InputHashMismatch({} != {})", input_hash, source_hash);
+    }
+
+    let encoded = encode(&replay, replay.threads)?;
+    let actual = Snapshot::from_pack(&encoded.pack)?;
+    let mut diff = compare(&expected, &actual);
+    if let Some(r) = &receipt {
+        diff.tools = tool_changes(&r.tools, &encoded.tools);
+    }
+    if let Some(threads) = cli.check_determinism {
+        diff.determinism = Some(Box::new(determinism_diff(&replay, &actual, threads)?));
+    }
+    emit(&diff, cli.diff_out.as_deref())?;
+
+    if !diff.identical {
+        bail!("NotReproduced({})", reference.display());
+    }
+    if diff.determinism.as_ref().is_some_and(|d| !d.identical) {
+        bail!("NondeterministicEncode({})", reference.display());
+    }
+    eprintln!(
+        "ok: reproduced manifest {} with merkle root {}",
+        diff.manifest.actual,
+        diff.merkle_root
+            .actual
+            .map_or("-".to_string(), |r| r.to_string())
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use vcx_pack::fixtures::{self, b3};
+
+    fn manifest(tiles: &[[u8; 4]], audio: Option<u8>) -> Manifest {
+        let groups: Vec<Vec<Cid>> = tiles.iter().map(|seeds| seeds.map(b3).to_vec()).collect();
+        Manifest {
+            audio: audio.map(|seed| fixtures::opus(b3(seed))),
+            ..fixtures::manifest(&groups, 3000)
+        }
+    }
+
+    fn snapshot(m: Manifest, audio: Option<Vec<u8>>) -> Snapshot {
+        let json = m.to_json().unwrap();
+        Snapshot {
+            manifest_cid: vcx_pack::cid_for_json_value(&json).unwrap().0,
+            merkle_root: Some(B3Digest([0xee; 32])),
+            manifest: Some(m),
+            audio,
+            import: None,
+        }
+    }
+
+    fn raw_page(lacing: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut page = b"OggS".to_vec();
+        page.resize(26, 0);
+        page.push(lacing.len() as u8);
+        page.extend_from_slice(lacing);
+        page.extend_from_slice(body);
+        page
+    }
+
+    /// One Ogg page holding exactly `packets`.
+    fn ogg_page(packets: &[&[u8]]) -> Vec<u8> {
+        let mut lacing = Vec::new();
+        for p in packets {
+            lacing.extend(std::iter::repeat_n(255u8, p.len() / 255));
+            lacing.push((p.len() % 255) as u8);
+        }
+        raw_page(&lacing, &packets.concat())
+    }
+
+    #[test]
+    fn diff_pinpoints_first_tile_and_audio_packet() {
+        let long = [7u8; 300];
+        let reference = snapshot(
+            manifest(&[[1, 2, 3, 4], [5, 6, 7, 8]], Some(9)),
+            Some(ogg_page(&[b"OpusHead", &long, b"p2"])),
+        );
+        let same = compare(&reference, &reference);
+        assert!(same.identical);
+        assert_eq!(same.tiles_differing, 0);
+        assert!(same.first_tile.is_none() && same.audio.is_none());
+
+        let mut changed = [7u8; 300];
+        changed[299] = 0;
+        let rerun = snapshot(
+            manifest(&[[1, 2, 3, 4], [5, 6, 0, 0]], Some(10)),
+            Some(ogg_page(&[b"OpusHead", &changed, b"p2"])),
+        );
+        let diff = compare(&reference, &rerun);
+        assert!(!diff.identical);
+        assert!(diff.payloads_compared);
+        assert!(diff.fields.is_empty());
+        assert_eq!(diff.tiles_differing, 2);
+        assert_eq!(
+            diff.first_tile,
+            Some(TileDivergence {
+                frame: 1,
+                tile_x: 0,
+                tile_y: 1,
+                expected: Some(Cid::blake3([7; 32])),
+                actual: Some(Cid::blake3([0; 32])),
+            })
+        );
+        let audio = diff.audio.unwrap();
+        assert_eq!(audio.first_packet, Some(1));
+        assert_eq!(audio.first_byte, Some(27 + 4 + 8 + 299));
+
+        // A missing frame shows up as its tiles plus the frame count and duration.
+        let short = snapshot(manifest(&[[1, 2, 3, 4]], Some(9)), None);
+        let diff = compare(&reference, &short);
+        assert_eq!(diff.tiles_differing, 4);
+        assert_eq!(diff.fields, ["duration_ticks"]);
+        assert_eq!(
+            diff.frames,
+            Some(Pair {
+                expected: 2,
+                actual: 1
+            })
+        );
+        assert_eq!(diff.first_tile.unwrap().actual, None);
+    }
+
+    #[test]
+    fn ogg_packets_span_pages_and_reject_garbage() {
+        // The second packet fills the first page and continues on the next one.
+        let big = [1u8; 600];
+        let mut stream = raw_page(&[4, 255, 255], &[b"head", &big[..510]].concat());
+        stream.extend(raw_page(&[90], &big[510..]));
+        let packets = ogg_packets(&stream).unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0], b"head");
+        assert_eq!(packets[1].len(), 600);
+
+        assert!(ogg_packets(b"not ogg at all, clearly").is_none());
+        assert_eq!(first_differing_byte(b"abc", b"abd"), Some(2));
+        assert_eq!(first_differing_byte(b"abc", b"ab"), Some(2));
+        assert_eq!(first_differing_byte(b"abc", b"abc"), None);
+    }
+
+    #[test]
+    fn receipt_parameters_replay_the_encode() {
+        let original = Cli::parse_from([
+            "vcx_enc_cli",
+            "--input",
+            "in.mp4",
+            "--out",
+            "out.vcx",
+            "--world",
+            "a/demo/t/prod",
+            "--tile-size",
+            "32",
+            "--no-audio",
+            "--threads",
+            "3",
+            "--check-determinism",
+            "2",
+            "--diff-out",
+            "diff.json",
+        ]);
+        for output_only in [
+            "out",
+            "receipt_out",
+            "manifest_out",
+            "diff_out",
+            "check_determinism",
+        ] {
+            assert!(
+                !original.parameters().contains_key(output_only),
+                "{}",
+                output_only
+            );
+        }
+        let auditor = Cli::parse_from([
+            "vcx_enc_cli",
+            "--reproduce",
+            "out.vcx.receipt.json",
+            "--ffmpeg-bin",
+            "/opt/ffmpeg",
+        ]);
+        let replay = replay_receipt(&auditor, &original.parameters()).unwrap();
+        assert!(replay.reproduce.is_none());
+        assert_eq!(replay.ffmpeg_bin, "/opt/ffmpeg");
+        let mut expected = original.parameters();
+        expected.insert("ffmpeg_bin".to_string(), "/opt/ffmpeg".to_string());
+        assert_eq!(replay.parameters(), expected);
+
+        let mut old = original.parameters();
+        old.remove("threads");
+        assert_eq!(replay_receipt(&auditor, &old).unwrap().threads, 1);
+        old.insert("tile_size".to_string(), "big".to_string());
+        assert!(replay_receipt(&auditor, &old)
+            .unwrap_err()
+            .to_string()
+            .contains("BadReceiptParameter(tile_size)"));
+    }
+}
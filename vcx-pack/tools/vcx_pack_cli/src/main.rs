@@ -2,16 +2,38 @@
 use anyhow::{bail, Context, Result};
 use clap::{Parser, Subcommand};
 use serde_json::Value;
+use std::collections::HashMap;
 use std::fs::File;
 use std::fs::{create_dir_all};
-use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::io::{BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use std::ops::RangeInclusive;
 use std::path::Path;
 
 use base64::Engine;
 
-use ubl_ai_nrf1::nrf::{decode_from_slice, NrfValue};
-
-use vcx_pack::{build_pack, cid_bytes_to_str, cid_str_to_bytes, read_and_verify_pack, MimeTag};
+use vcx_pack::chip::Chip;
+use vcx_pack::edit_engine::{
+    apply_edit_decision, referenced_cids, verify_edit_decision, ManifestStore,
+};
+use vcx_pack::lineage::verify_lineage;
+use vcx_pack::manifest::{Lineage, LineageReason, Manifest};
+use vcx_pack::nrf_json::decode_json;
+use vcx_pack::playback_sim::{parse_bandwidth_trace, simulate_pack_playback, PlaybackPolicy};
+use vcx_pack::prefetch::PrefetchScheduler;
+use vcx_pack::realtime_sim::{
+    fixtures, parse_trace, simulate, synthetic_trace, SimConfig, SyntheticPattern,
+};
+use vcx_pack::redaction::{redact_pack, redactions, region_cids};
+use vcx_pack::streaming_protocol::{
+    EditDecisionChip, RealtimePolicyHint, VodPredictabilitySidecar,
+};
+use vcx_pack::tile_index::{RegionMap, TileGrid};
+use vcx_pack::unc1;
+use vcx_pack::vod_predictability::{vod_sidecar_for_pack, VodAnalyzerConfig};
+use vcx_pack::{
+    build_pack, cid_for_json_value, read_and_verify_pack, read_and_verify_redacted_pack,
+    read_payload, Cid, IndexEntry, MimeTag, PACK_MAGIC,
+};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
@@ -47,6 +69,12 @@ enum Cmd {
         /// Recompute payload hashes from payload bytes (slower, but strongest)
         #[arg(long, default_value_t = false)]
         full: bool,
+        /// Cross-check manifest references against the index (dangling, orphaned, MIME)
+        #[arg(long, default_value_t = false)]
+        refs: bool,
+        /// Accept redacted payloads and list them (with --full: they must be zero-filled)
+        #[arg(long, default_value_t = false)]
+        allow_redacted: bool,
     },
     /// List index entries
     List {
@@ -70,6 +98,25 @@ enum Cmd {
         out: String,
     },
 
+    /// Tombstone payloads (zero bytes, set the redacted index flag) keeping the Merkle root
+    Redact {
+        /// Input pack (.vcx)
+        #[arg(long)]
+        input: String,
+        /// Output pack (.vcx)
+        #[arg(long)]
+        out: String,
+        /// Payload CID to redact (repeatable)
+        #[arg(long)]
+        cid: Vec<String>,
+        /// Redact the manifest's audio
+        #[arg(long, default_value_t = false)]
+        audio: bool,
+        /// Frame region GROUPS:TILE_XS:TILE_YS, each `a` or `a-b` inclusive (repeatable)
+        #[arg(long)]
+        region: Vec<String>,
+    },
+
     /// Convert a VCX pack into UBL-ready chips (Option A: vcx/blob chips embed bytes_b64)
     Ingest {
         /// Input pack (.vcx)
@@ -88,6 +135,139 @@ enum Cmd {
         #[arg(long, default_value_t = false)]
         full_verify: bool,
     },
+
+    /// Analyze a pack and emit a vcx/sidecar.predictability.vod chip for its manifest
+    VodSidecar {
+        /// Input pack (.vcx)
+        #[arg(long)]
+        input: String,
+        /// Output chip JSON (sealed: `@id` is the chip CID)
+        #[arg(long)]
+        out: String,
+    },
+
+    /// Apply a vcx/edit.decision chip and write the output manifest and/or a new pack
+    Edit {
+        /// Edit decision chip JSON
+        #[arg(long)]
+        chip: String,
+        /// Input manifest: a pack (.vcx) or manifest JSON
+        #[arg(long)]
+        input: String,
+        /// Splice source manifests: packs (.vcx) or manifest JSON (repeatable)
+        #[arg(long)]
+        source: Vec<String>,
+        /// Output manifest JSON
+        #[arg(long)]
+        out_manifest: Option<String>,
+        /// Output pack (.vcx); payloads are copied from the input and source packs
+        #[arg(long)]
+        out_pack: Option<String>,
+        /// Write the resolved chip (real output_manifest and reencode_required) here
+        #[arg(long)]
+        chip_out: Option<String>,
+    },
+
+    /// Replay a vcx/edit.decision chip against its manifests and payloads and report discrepancies
+    VerifyEdit {
+        /// Edit decision chip JSON
+        #[arg(long)]
+        chip: String,
+        /// Input manifest: a pack (.vcx) or manifest JSON
+        #[arg(long)]
+        input: String,
+        /// Splice source manifests and payload packs (.vcx) or manifest JSON (repeatable)
+        #[arg(long)]
+        source: Vec<String>,
+    },
+
+    /// Walk a manifest's lineage through local manifests and edit decisions and check each link
+    VerifyLineage {
+        /// Manifest to check: a pack (.vcx) or manifest JSON
+        #[arg(long)]
+        input: String,
+        /// Ancestor manifests: packs (.vcx) or manifest JSON (repeatable)
+        #[arg(long)]
+        source: Vec<String>,
+        /// Edit decision chips named by edited manifests (repeatable)
+        #[arg(long)]
+        chip: Vec<String>,
+    },
+
+    /// Replay an observation trace through the realtime predictor and report sidecars and stats
+    SimulateRealtime {
+        /// NDJSON trace, one {"guessed_tiles","correct_tiles"} object per group
+        #[arg(long, conflicts_with_all = ["fixture", "synthetic"])]
+        trace: Option<String>,
+        /// Named fixture trace (stable, volatile, warmup, hover-high)
+        #[arg(long, conflicts_with = "synthetic")]
+        fixture: Option<String>,
+        /// Synthetic trace pattern (stable, volatile, scene-cut)
+        #[arg(long)]
+        synthetic: Option<String>,
+        /// Groups to generate for --synthetic
+        #[arg(long, default_value_t = 120)]
+        groups: usize,
+        /// Tiles per group for --synthetic
+        #[arg(long, default_value_t = 100)]
+        tiles: u64,
+        /// PRNG seed for --synthetic
+        #[arg(long, default_value_t = 1)]
+        seed: u64,
+        /// Interpolate policy hints on the score instead of the three-step mapping
+        #[arg(long, default_value_t = false)]
+        continuous_hints: bool,
+        /// Output report JSON (summary and all sidecars)
+        #[arg(long)]
+        out: String,
+        /// Also write the sidecars as NDJSON
+        #[arg(long)]
+        sidecars_out: Option<String>,
+    },
+
+    /// Simulate network download and buffered playback of a pack and report stalls and quality
+    SimulatePlayback {
+        /// Input pack (.vcx)
+        #[arg(long)]
+        input: String,
+        /// NDJSON bandwidth trace, one {"duration_ms","bits_per_second","latency_ms"} per segment
+        #[arg(long)]
+        bandwidth: String,
+        /// Realtime policy mode with the default hint (aggressive_ghost, balanced, download_first)
+        #[arg(long, conflicts_with_all = ["hint", "vod_strategy"])]
+        mode: Option<String>,
+        /// Realtime policy hint JSON (a policy_hint object)
+        #[arg(long, conflicts_with = "vod_strategy")]
+        hint: Option<String>,
+        /// Uniform VOD strategy (copy_previous, hold_and_noise, download_aggressive)
+        #[arg(long)]
+        vod_strategy: Option<String>,
+        /// Output report JSON
+        #[arg(long)]
+        out: String,
+    },
+
+    /// Write the prioritized fetch plan (CIDs with deadlines) for a playhead and policy hint
+    PrefetchPlan {
+        /// Input manifest: a pack (.vcx) or manifest JSON
+        #[arg(long)]
+        input: String,
+        /// Group index of the playhead
+        #[arg(long, default_value_t = 0)]
+        playhead: u32,
+        /// Realtime policy mode with the default hint (aggressive_ghost, balanced, download_first)
+        #[arg(long, conflicts_with = "hint")]
+        mode: Option<String>,
+        /// Realtime policy hint JSON (a policy_hint object)
+        #[arg(long)]
+        hint: Option<String>,
+        /// VOD predictability sidecar whose regions mark tiles as speculative
+        #[arg(long)]
+        vod_sidecar: Option<String>,
+        /// Output plan JSON
+        #[arg(long)]
+        out: String,
+    },
 }
 
 fn main() -> Result<()> {
@@ -100,37 +280,108 @@ fn main() -> Result<()> {
             strict_unc1,
             skip_self_verify,
         } => cmd_build(&manifest, &payload, &out, strict_unc1, skip_self_verify),
-        Cmd::Verify { input, full } => cmd_verify(&input, full),
+        Cmd::Verify {
+            input,
+            full,
+            refs,
+            allow_redacted,
+        } => cmd_verify(&input, full, refs, allow_redacted),
         Cmd::List { input } => cmd_list(&input),
         Cmd::Extract { input, cid, out } => cmd_extract(&input, &cid, &out),
         Cmd::DumpManifest { input, out } => cmd_dump_manifest(&input, &out),
+        Cmd::Redact {
+            input,
+            out,
+            cid,
+            audio,
+            region,
+        } => cmd_redact(&input, &out, &cid, audio, &region),
         Cmd::Ingest { input, out_dir, world, include_manifest, full_verify } => {
             cmd_ingest(&input, &out_dir, world.as_deref(), include_manifest, full_verify)
         }
-    }
-}
-
-fn nrf_to_json(v: &NrfValue) -> Result<Value> {
-    Ok(match v {
-        NrfValue::Null => Value::Null,
-        NrfValue::Bool(b) => Value::Bool(*b),
-        NrfValue::Int(i) => Value::Number((*i).into()),
-        NrfValue::String(s) => Value::String(s.clone()),
-        NrfValue::Bytes(b) => {
-            // Bytes should not appear inside the VCX manifest; if they do, we still provide
-            // a deterministic JSON representation.
-            let enc = base64::engine::general_purpose::STANDARD.encode(b);
-            Value::String(format!("b64:{}", enc))
+        Cmd::VodSidecar { input, out } => cmd_vod_sidecar(&input, &out),
+        Cmd::Edit {
+            chip,
+            input,
+            source,
+            out_manifest,
+            out_pack,
+            chip_out,
+        } => cmd_edit(
+            &chip,
+            &input,
+            &source,
+            out_manifest.as_deref(),
+            out_pack.as_deref(),
+            chip_out.as_deref(),
+        ),
+        Cmd::VerifyEdit {
+            chip,
+            input,
+            source,
+        } => cmd_verify_edit(&chip, &input, &source),
+        Cmd::VerifyLineage {
+            input,
+            source,
+            chip,
+        } => cmd_verify_lineage(&input, &source, &chip),
+        Cmd::SimulateRealtime {
+            trace,
+            fixture,
+            synthetic,
+            groups,
+            tiles,
+            seed,
+            continuous_hints,
+            out,
+            sidecars_out,
+        } => {
+            let trace = if let Some(path) = trace {
+                let text =
+                    std::fs::read_to_string(&path).with_context(|| format!("read {}", path))?;
+                parse_trace(&text)?
+            } else if let Some(name) = fixture {
+                fixtures::by_name(&name).with_context(|| format!("UnknownFixture({})", name))?
+            } else if let Some(pattern) = synthetic {
+                synthetic_trace(SyntheticPattern::parse(&pattern)?, groups, tiles, seed)
+            } else {
+                bail!("one of --trace, --fixture or --synthetic is required");
+            };
+            cmd_simulate_realtime(&trace, continuous_hints, &out, sidecars_out.as_deref())
         }
-        NrfValue::Array(arr) => Value::Array(arr.iter().map(nrf_to_json).collect::<Result<Vec<_>>>()?),
-        NrfValue::Map(map) => {
-            let mut obj = serde_json::Map::new();
-            for (k, vv) in map {
-                obj.insert(k.clone(), nrf_to_json(vv)?);
-            }
-            Value::Object(obj)
+        Cmd::SimulatePlayback {
+            input,
+            bandwidth,
+            mode,
+            hint,
+            vod_strategy,
+            out,
+        } => {
+            let policy = if let Some(hint) = read_policy_hint(mode, hint)? {
+                PlaybackPolicy::Realtime { hint }
+            } else if let Some(name) = vod_strategy {
+                PlaybackPolicy::Vod {
+                    strategy: serde_json::from_value(Value::String(name.clone()))
+                        .with_context(|| format!("UnknownVodStrategy({})", name))?,
+                }
+            } else {
+                bail!("one of --mode, --hint or --vod-strategy is required");
+            };
+            cmd_simulate_playback(&input, &bandwidth, &policy, &out)
         }
-    })
+        Cmd::PrefetchPlan {
+            input,
+            playhead,
+            mode,
+            hint,
+            vod_sidecar,
+            out,
+        } => {
+            let hint =
+                read_policy_hint(mode, hint)?.context("one of --mode or --hint is required")?;
+            cmd_prefetch_plan(&input, playhead, &hint, vod_sidecar.as_deref(), &out)
+        }
+    }
 }
 
 fn reorder_envelope_top_level(v: &Value) -> Result<Value> {
@@ -202,40 +453,65 @@ fn cmd_build(
     Ok(())
 }
 
-fn cmd_verify(input: &str, full: bool) -> Result<()> {
+fn cmd_verify(input: &str, full: bool, refs: bool, allow_redacted: bool) -> Result<()> {
     let f = File::open(input).with_context(|| format!("open {}", input))?;
-    let pack = read_and_verify_pack(BufReader::new(f), full)?;
+    let pack = if allow_redacted {
+        read_and_verify_redacted_pack(BufReader::new(f), full)?
+    } else {
+        read_and_verify_pack(BufReader::new(f), full)?
+    };
+    if allow_redacted {
+        let redacted = redactions(&pack);
+        for r in &redacted {
+            println!("{}", r);
+        }
+        eprintln!("redacted: {}", redacted.len());
+    }
+    if refs {
+        let report = pack.verify_references()?;
+        for issue in &report.issues {
+            println!("{}", issue);
+        }
+        if !report.is_ok() {
+            bail!("ReferenceCheckFailed({} issues)", report.issues.len());
+        }
+        eprintln!("references: {} resolved", report.references);
+    }
     eprintln!("ok: pack verified");
     eprintln!("entries: {}", pack.index_entries.len());
-    eprintln!("merkle root: {}", cid_bytes_to_str(&pack.merkle.root));
+    eprintln!("merkle root: b3:{}", hex::encode(pack.merkle.root));
     Ok(())
 }
 
 fn cmd_list(input: &str) -> Result<()> {
     let f = File::open(input).with_context(|| format!("open {}", input))?;
-    let pack = read_and_verify_pack(BufReader::new(f), false)?;
-    println!("merkle_root {}", cid_bytes_to_str(&pack.merkle.root));
+    let pack = read_and_verify_redacted_pack(BufReader::new(f), false)?;
+    println!("merkle_root b3:{}", hex::encode(pack.merkle.root));
     for e in &pack.index_entries {
         println!(
-            "{}\tmime_tag={:?}\tlen={}\toff={}",
-            cid_bytes_to_str(&e.cid),
+            "{}\tmime_tag={:?}\tlen={}\toff={}{}",
+            e.cid,
             e.mime_tag,
             e.payload_len,
-            e.payload_off
+            e.payload_off,
+            if e.is_redacted() { "\tredacted" } else { "" }
         );
     }
     Ok(())
 }
 
 fn cmd_extract(input: &str, cid: &str, out_path: &str) -> Result<()> {
-    let target = cid_str_to_bytes(cid)?;
+    let target: Cid = cid.parse()?;
     let mut f = File::open(input).with_context(|| format!("open {}", input))?;
-    let pack = read_and_verify_pack(BufReader::new(&mut f), false)?;
+    let pack = read_and_verify_redacted_pack(BufReader::new(&mut f), false)?;
     let e = pack
         .index_entries
         .iter()
         .find(|x| x.cid == target)
         .context("cid not found in index")?;
+    if e.is_redacted() {
+        bail!("PayloadRedacted({})", target);
+    }
     f.seek(SeekFrom::Start(e.payload_off))?;
     let mut buf = vec![0u8; e.payload_len as usize];
     f.read_exact(&mut buf)?;
@@ -247,13 +523,75 @@ fn cmd_extract(input: &str, cid: &str, out_path: &str) -> Result<()> {
 
 fn cmd_dump_manifest(input: &str, out_path: &str) -> Result<()> {
     let f = File::open(input).with_context(|| format!("open {}", input))?;
-    let pack = read_and_verify_pack(BufReader::new(f), false)?;
+    let pack = read_and_verify_redacted_pack(BufReader::new(f), false)?;
     let mut out = File::create(out_path).with_context(|| format!("create {}", out_path))?;
     out.write_all(&pack.manifest_bytes)?;
     eprintln!("ok: wrote {}", out_path);
     Ok(())
 }
 
+fn cmd_redact(
+    input: &str,
+    out_path: &str,
+    cids: &[String],
+    audio: bool,
+    regions: &[String],
+) -> Result<()> {
+    let bytes = std::fs::read(input).with_context(|| format!("read {}", input))?;
+    let pack = read_and_verify_redacted_pack(Cursor::new(&bytes), false)?;
+    let manifest = Manifest::from_nrf(&pack.manifest_bytes)?;
+    let mut targets = cids
+        .iter()
+        .map(|c| c.parse())
+        .collect::<Result<Vec<Cid>>>()?;
+    if audio {
+        targets.push(manifest.audio.as_ref().context("PackHasNoAudio")?.cid);
+    }
+    for region in regions {
+        let (groups, xs, ys) = parse_region(region)?;
+        let selected = region_cids(&manifest, groups, xs, ys);
+        if selected.is_empty() {
+            bail!("EmptyRegion({})", region);
+        }
+        targets.extend(selected);
+    }
+    if targets.is_empty() {
+        bail!("NothingToRedact (use --cid, --audio or --region)");
+    }
+
+    let redacted = redact_pack(&bytes, &targets)?;
+    let mut out = File::create(out_path).with_context(|| format!("create {}", out_path))?;
+    out.write_all(&redacted)?;
+    let pack = read_and_verify_redacted_pack(Cursor::new(&redacted), true)?;
+    for r in redactions(&pack) {
+        println!("{}", r);
+    }
+    eprintln!("ok: wrote {}", out_path);
+    eprintln!("merkle root: b3:{} (unchanged)", hex::encode(pack.merkle.root));
+    Ok(())
+}
+
+type Region = (
+    RangeInclusive<usize>,
+    RangeInclusive<u32>,
+    RangeInclusive<u32>,
+);
+
+/// `GROUPS:TILE_XS:TILE_YS`, each an inclusive range `a-b` or a single `a`.
+fn parse_region(s: &str) -> Result<Region> {
+    fn range<T: std::str::FromStr>(s: &str) -> Option<RangeInclusive<T>> {
+        let (a, b) = s.split_once('-').unwrap_or((s, s));
+        Some(a.parse().ok()?..=b.parse().ok()?)
+    }
+    fn region(s: &str) -> Option<Region> {
+        match s.split(':').collect::<Vec<_>>()[..] {
+            [g, x, y] => Some((range(g)?, range(x)?, range(y)?)),
+            _ => None,
+        }
+    }
+    region(s).with_context(|| format!("BadRegion({})", s))
+}
+
 fn mime_tag_to_mime(tag: MimeTag) -> &'static str {
     match tag {
         MimeTag::Ic0Tile => "application/vcx-ic0t",
@@ -264,17 +602,12 @@ fn mime_tag_to_mime(tag: MimeTag) -> &'static str {
     }
 }
 
-fn unc_int(v: u64) -> Value {
-    serde_json::json!({"@num":"int/1","v": v.to_string()})
-}
-
 fn cmd_ingest(input: &str, out_dir: &str, world_override: Option<&str>, include_manifest: bool, full_verify: bool) -> Result<()> {
     let mut f = File::open(input).with_context(|| format!("open {}", input))?;
     let pack = read_and_verify_pack(BufReader::new(&mut f), full_verify)?;
 
-    // Decode manifest NRF bytes back to JSON (should be pure maps/strings/ints).
-    let nrf = decode_from_slice(&pack.manifest_bytes).context("decode manifest NRF")?;
-    let mut manifest_json = nrf_to_json(&nrf).context("manifest NRF->JSON")?;
+    // Decode manifest NRF bytes back to JSON (lossless: re-encoding gives the same CID).
+    let mut manifest_json = decode_json(&pack.manifest_bytes).context("manifest NRF->JSON")?;
     if !manifest_json.is_object() {
         bail!("manifest JSON must be object");
     }
@@ -288,8 +621,17 @@ fn cmd_ingest(input: &str, out_dir: &str, world_override: Option<&str>, include_
             .to_string(),
     };
     if let Some(_) = world_override {
+        let (parent, _nrf) = cid_for_json_value(&manifest_json)?;
+        let lineage = Lineage {
+            reason: LineageReason::WorldOverride,
+            parents: vec![parent],
+            edit_decision: None,
+            source: None,
+            note: None,
+        };
         if let Some(obj) = manifest_json.as_object_mut() {
             obj.insert("@world".to_string(), Value::String(world.clone()));
+            obj.insert("lineage".to_string(), serde_json::to_value(lineage)?);
         }
     }
 
@@ -311,7 +653,7 @@ fn cmd_ingest(input: &str, out_dir: &str, world_override: Option<&str>, include_
     // For each payload entry, read bytes and emit a vcx/blob chip.
     // Note: UBL JSON pipeline cannot represent NRF Bytes directly; we embed bytes as base64.
     // The payload CID used for dedupe is still the UBL-style CID computed as BLAKE3(NRF(Bytes(payload_raw))).
-    let merkle_root = cid_bytes_to_str(&pack.merkle.root);
+    let merkle_root = format!("b3:{}", hex::encode(pack.merkle.root));
 
     let mut ndjson = String::new();
     for e in &pack.index_entries {
@@ -328,7 +670,7 @@ fn cmd_ingest(input: &str, out_dir: &str, world_override: Option<&str>, include_
         }
 
         let bytes_b64 = base64::engine::general_purpose::STANDARD.encode(&buf);
-        let cid = cid_bytes_to_str(&e.cid);
+        let cid = e.cid.to_string();
         let chip = serde_json::json!({
             "@type": "vcx/blob",
             "@id": cid.clone(),
@@ -336,7 +678,7 @@ fn cmd_ingest(input: &str, out_dir: &str, world_override: Option<&str>, include_
             "@world": world.clone(),
             "cid": cid,
             "mime": mime_tag_to_mime(e.mime_tag),
-            "size": unc_int(e.payload_len as u64),
+            "size": unc1::int_value(e.payload_len),
             "bytes_b64": bytes_b64,
             "pack_merkle_root": merkle_root.clone(),
             "hash_raw_b3": format!("b3:{}", hex::encode(e.payload_hash)),
@@ -379,3 +721,295 @@ fn cmd_ingest(input: &str, out_dir: &str, world_override: Option<&str>, include_
     eprintln!("- ndjson: {}/chips.ndjson", out_dir);
     Ok(())
 }
+
+fn cmd_vod_sidecar(input: &str, out_path: &str) -> Result<()> {
+    let f = File::open(input).with_context(|| format!("open {}", input))?;
+    let sidecar = vod_sidecar_for_pack(BufReader::new(f), &VodAnalyzerConfig::default())?;
+    sidecar.validate_for_grid(&TileGrid::from_manifest(&read_manifest_any(input)?)?)?;
+    let mut out = File::create(out_path).with_context(|| format!("create {}", out_path))?;
+    out.write_all(serde_json::to_string_pretty(&sidecar)?.as_bytes())?;
+    out.write_all(b"\n")?;
+    eprintln!("ok: wrote {} ({})", out_path, sidecar.cid()?);
+    eprintln!("target manifest: {}", sidecar.target_manifest);
+    eprintln!(
+        "volatility: {:.6} avg shot: {} ms regions: {}",
+        sidecar.global_stats.volatility_score,
+        sidecar.global_stats.average_shot_length_ms,
+        sidecar.regions.len()
+    );
+    Ok(())
+}
+
+fn cmd_simulate_realtime(
+    trace: &[vcx_pack::realtime_predictability::GroupObservation],
+    continuous_hints: bool,
+    out_path: &str,
+    sidecars_out: Option<&str>,
+) -> Result<()> {
+    let mut cfg = SimConfig::default();
+    cfg.hints.continuous = continuous_hints;
+    let report = simulate(trace, &cfg)?;
+
+    let mut out = File::create(out_path).with_context(|| format!("create {}", out_path))?;
+    out.write_all(serde_json::to_string_pretty(&report)?.as_bytes())?;
+    out.write_all(b"\n")?;
+    if let Some(path) = sidecars_out {
+        let mut w = BufWriter::new(File::create(path).with_context(|| format!("create {}", path))?);
+        for sidecar in &report.sidecars {
+            serde_json::to_writer(&mut w, sidecar)?;
+            w.write_all(b"\n")?;
+        }
+        w.flush()?;
+    }
+
+    let s = &report.summary;
+    eprintln!("ok: wrote {}", out_path);
+    eprintln!(
+        "groups: {} (aggressive_ghost {}, balanced {}, download_first {})",
+        s.groups, s.aggressive_ghost_groups, s.balanced_groups, s.download_first_groups
+    );
+    eprintln!(
+        "transitions: {} speculated: {} wasted: {} mean score: {:.6}",
+        s.transitions, s.speculated_tiles, s.wasted_speculative_tiles, s.mean_score
+    );
+    Ok(())
+}
+
+/// `--mode` (default hint of a policy mode) or `--hint` (policy_hint JSON file), if given.
+fn read_policy_hint(
+    mode: Option<String>,
+    hint: Option<String>,
+) -> Result<Option<RealtimePolicyHint>> {
+    if let Some(mode) = mode {
+        let mode = serde_json::from_value(Value::String(mode.clone()))
+            .with_context(|| format!("UnknownPolicyMode({})", mode))?;
+        return Ok(Some(RealtimePolicyHint::for_mode(mode)));
+    }
+    hint.map(|path| serde_json::from_value(read_json(&path)?).context("BadPolicyHint"))
+        .transpose()
+}
+
+fn cmd_prefetch_plan(
+    input: &str,
+    playhead: u32,
+    hint: &RealtimePolicyHint,
+    vod_sidecar: Option<&str>,
+    out_path: &str,
+) -> Result<()> {
+    let manifest = read_manifest_any(input)?;
+    let regions = match vod_sidecar {
+        Some(path) => {
+            let sidecar: VodPredictabilitySidecar =
+                serde_json::from_value(read_json(path)?).context("BadVodSidecar")?;
+            sidecar.validate_for_grid(&TileGrid::from_manifest(&manifest)?)?;
+            Some(RegionMap::new(&sidecar.regions)?)
+        }
+        None => None,
+    };
+    let mut scheduler = PrefetchScheduler::new(&manifest, regions)?;
+    scheduler.update(playhead, hint);
+    let plan = scheduler.last_plan().context("NoPlan")?;
+
+    let mut out = File::create(out_path).with_context(|| format!("create {}", out_path))?;
+    out.write_all(serde_json::to_string_pretty(plan)?.as_bytes())?;
+    out.write_all(b"\n")?;
+    eprintln!("ok: wrote {}", out_path);
+    eprintln!(
+        "groups {}..{} mode: {:?} items: {}",
+        plan.playhead_group,
+        plan.window_end_group,
+        plan.mode,
+        plan.items.len()
+    );
+    Ok(())
+}
+
+fn cmd_simulate_playback(
+    input: &str,
+    bandwidth: &str,
+    policy: &PlaybackPolicy,
+    out_path: &str,
+) -> Result<()> {
+    let text = std::fs::read_to_string(bandwidth).with_context(|| format!("read {}", bandwidth))?;
+    let trace = parse_bandwidth_trace(&text)?;
+    let f = File::open(input).with_context(|| format!("open {}", input))?;
+    let report = simulate_pack_playback(BufReader::new(f), &trace, policy)?;
+
+    let mut out = File::create(out_path).with_context(|| format!("create {}", out_path))?;
+    out.write_all(serde_json::to_string_pretty(&report)?.as_bytes())?;
+    out.write_all(b"\n")?;
+    eprintln!("ok: wrote {}", out_path);
+    eprintln!(
+        "groups: {} startup: {} ms stalls: {} ({} ms)",
+        report.groups, report.startup_delay_ms, report.stall_count, report.total_stall_ms
+    );
+    eprintln!(
+        "downloaded: {} B wasted: {} B speculated: {} wrong: {} degraded: {} quality: {:.6}",
+        report.bytes_downloaded,
+        report.bytes_wasted,
+        report.speculated_tiles,
+        report.wrong_speculations,
+        report.degraded_tiles,
+        report.mean_quality
+    );
+    Ok(())
+}
+
+fn is_pack_file(path: &str) -> Result<bool> {
+    let mut f = File::open(path).with_context(|| format!("open {}", path))?;
+    let mut magic = [0u8; 4];
+    Ok(f.read_exact(&mut magic).is_ok() && &magic == PACK_MAGIC)
+}
+
+/// Load a manifest from a pack (decoded from its NRF bytes) or from a JSON file.
+fn read_manifest_any(path: &str) -> Result<Value> {
+    if !is_pack_file(path)? {
+        return read_json(path);
+    }
+    let f = File::open(path).with_context(|| format!("open {}", path))?;
+    let pack = read_and_verify_pack(BufReader::new(f), false)?;
+    decode_json(&pack.manifest_bytes).context("manifest NRF->JSON")
+}
+
+/// Where each payload of the edit context lives: pack paths and, per CID, the pack's position in
+/// that list and its index entry.
+#[derive(Default)]
+struct PayloadLocations {
+    packs: Vec<String>,
+    entries: HashMap<Cid, (usize, IndexEntry)>,
+}
+
+/// Load the input and source manifests into a store; payload CIDs of packs are registered as
+/// available. Each pack is opened and verified once.
+fn load_edit_context(
+    input: &str,
+    sources: &[String],
+) -> Result<(ManifestStore, PayloadLocations)> {
+    let mut store = ManifestStore::new();
+    let mut locations = PayloadLocations::default();
+    for path in std::iter::once(&input.to_string()).chain(sources) {
+        let manifest = if is_pack_file(path)? {
+            let f = File::open(path).with_context(|| format!("open {}", path))?;
+            let pack = read_and_verify_pack(BufReader::new(f), false)?;
+            let pack_idx = locations.packs.len();
+            for e in &pack.index_entries {
+                store.insert_payload(e.cid);
+                locations
+                    .entries
+                    .entry(e.cid)
+                    .or_insert_with(|| (pack_idx, e.clone()));
+            }
+            locations.packs.push(path.clone());
+            decode_json(&pack.manifest_bytes).context("manifest NRF->JSON")?
+        } else {
+            read_json(path)?
+        };
+        let cid = store.insert(manifest)?;
+        eprintln!("manifest {} <- {}", cid, path);
+    }
+    Ok((store, locations))
+}
+
+fn cmd_edit(
+    chip_path: &str,
+    input: &str,
+    sources: &[String],
+    out_manifest: Option<&str>,
+    out_pack: Option<&str>,
+    chip_out: Option<&str>,
+) -> Result<()> {
+    if out_manifest.is_none() && out_pack.is_none() && chip_out.is_none() {
+        bail!("nothing to write (use --out-manifest, --out-pack and/or --chip-out)");
+    }
+    let chip: EditDecisionChip =
+        serde_json::from_value(read_json(chip_path)?).context("parse edit decision chip")?;
+
+    let (store, locations) = load_edit_context(input, sources)?;
+    let (resolved, outcome) = apply_edit_decision(&chip, &store)?;
+
+    if let Some(path) = out_manifest {
+        let mut f = File::create(path).with_context(|| format!("create {}", path))?;
+        f.write_all(serde_json::to_string_pretty(&outcome.manifest)?.as_bytes())?;
+        f.write_all(b"\n")?;
+        eprintln!("ok: wrote {}", path);
+    }
+
+    if let Some(path) = out_pack {
+        let mut files = Vec::with_capacity(locations.packs.len());
+        for pack_path in &locations.packs {
+            files.push(File::open(pack_path).with_context(|| format!("open {}", pack_path))?);
+        }
+        let mut payloads = Vec::new();
+        for cid in referenced_cids(&outcome.manifest)? {
+            let (pack_idx, e) = locations
+                .entries
+                .get(&cid)
+                .with_context(|| format!("PayloadNotInPacks({})", cid))?;
+            payloads.push((e.mime_tag, read_payload(&mut files[*pack_idx], e)?));
+        }
+        let f = File::create(path).with_context(|| format!("create {}", path))?;
+        let mut w = BufWriter::new(f);
+        build_pack(&mut w, &outcome.manifest, payloads, true)?;
+        w.flush()?;
+        let f = File::open(path).with_context(|| format!("open {}", path))?;
+        read_and_verify_pack(BufReader::new(f), true)
+            .context("self-verify failed after edit pack build")?;
+        eprintln!("ok: wrote {} (self-verify full passed)", path);
+    }
+
+    if let Some(path) = chip_out {
+        let mut f = File::create(path).with_context(|| format!("create {}", path))?;
+        f.write_all(serde_json::to_string_pretty(&resolved)?.as_bytes())?;
+        f.write_all(b"\n")?;
+        eprintln!("ok: wrote {}", path);
+    }
+
+    eprintln!("output manifest: {}", outcome.manifest_cid);
+    eprintln!("reencode required: {}", outcome.reencode_required);
+    Ok(())
+}
+
+fn cmd_verify_edit(chip_path: &str, input: &str, sources: &[String]) -> Result<()> {
+    let chip: EditDecisionChip =
+        serde_json::from_value(read_json(chip_path)?).context("parse edit decision chip")?;
+    let (store, _locations) = load_edit_context(input, sources)?;
+    let report = verify_edit_decision(&chip, &store);
+    if let Some(cid) = &report.replayed_output {
+        eprintln!("replayed output manifest: {}", cid);
+    }
+    for d in &report.discrepancies {
+        println!("{}", d);
+    }
+    if !report.is_ok() {
+        bail!(
+            "EditDecisionRejected({} discrepancies)",
+            report.discrepancies.len()
+        );
+    }
+    eprintln!("ok: edit decision verified");
+    Ok(())
+}
+
+fn cmd_verify_lineage(input: &str, sources: &[String], chips: &[String]) -> Result<()> {
+    let (mut store, _locations) = load_edit_context(input, sources)?;
+    for path in chips {
+        let chip: EditDecisionChip =
+            serde_json::from_value(read_json(path)?).context("parse edit decision chip")?;
+        let cid = store.insert_edit_decision(chip)?;
+        eprintln!("edit decision {} <- {}", cid, path);
+    }
+    let report = verify_lineage(&read_manifest_any(input)?, &store)?;
+    for issue in &report.issues {
+        println!("{}", issue);
+    }
+    if !report.is_ok() {
+        bail!("LineageRejected({} issues)", report.issues.len());
+    }
+    let roots: Vec<String> = report.roots.iter().map(Cid::to_string).collect();
+    eprintln!(
+        "ok: lineage verified ({} manifests, roots: {})",
+        report.visited.len(),
+        roots.join(", ")
+    );
+    Ok(())
+}